@@ -13,16 +13,12 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::env;
 use alpaca_rs::AlpacaClient;
 
 #[tokio::main]
 async fn main() -> Result<(),Box<dyn std::error::Error>>
 {
-    let client = AlpacaClient::connect(
-            env::var("ALPACA_API_KEY").unwrap().as_str(),
-            env::var("ALPACA_SECRET_KEY").unwrap().as_str())
-        .await?;
+    let client = AlpacaClient::connect_from_env().await?;
 
     let positions = client.get_positions().await.unwrap();
 
@@ -30,5 +26,11 @@ async fn main() -> Result<(),Box<dyn std::error::Error>>
 
     println!("{}", serde_json::to_string_pretty(&positions).unwrap());
 
+    let change = client.daily_change().await?;
+    match change.change_pct {
+        Some(pct) => println!("Equity {:.2}, {:+.2} ({:+.2}%) today", change.equity, change.change, pct),
+        None => println!("Equity {:.2}, no previous close", change.equity),
+    }
+
     Ok(())
 }
@@ -15,18 +15,462 @@
 
 #![allow(dead_code)]
 
-use std::collections::HashMap;
-use std::str::FromStr;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use tokio::runtime::Runtime;
-use tokio::task::JoinSet;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use std::sync::atomic;
 
-//use futures::future::join_all;
-use log;
+/// Bars kept per symbol for the indicators.
+pub const BAR_HISTORY: usize = 500;
+
+/// Samples kept by the equity curve unless set with
+/// `with_equity_curve_capacity`, a day of one minute updates.
+pub const DEFAULT_EQUITY_CURVE_CAPACITY: usize = 1440;
+
+/// Age after which a price is too old to size an order or fire a stop.
+pub const DEFAULT_MAX_PRICE_AGE: Duration = Duration::from_secs(60);
+
+/// Time between the checks of the account activities by `update_prices`
+/// while a dividend due today has not been credited yet, unless set with
+/// `with_dividend_check_interval`.
+pub const DEFAULT_DIVIDEND_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Price types fetched by `update_prices` unless set in `PriceOptions`.
+const DEFAULT_PRICE_TYPES: [crate::PriceType; 3] = [
+    crate::PriceType::Trades,
+    crate::PriceType::Quotes,
+    crate::PriceType::Bars,
+];
+
+/// What `update_prices` fetches, given to `with_client_options` or
+/// `with_api_options` so that the first fetch of the construction uses it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceOptions {
+    pub types: Vec<crate::PriceType>,
+    pub source: PriceSource,
+    /// Whether the construction fetches the prices, off for the accounts
+    /// of a `MultiAccountWrapper` which are given theirs.
+    pub initial_fetch: bool,
+}
+
+impl Default for PriceOptions {
+    fn default() -> Self {
+        Self { types: DEFAULT_PRICE_TYPES.to_vec(), source: PriceSource::default(), initial_fetch: true }
+    }
+}
+
+impl PriceOptions {
+    pub fn types(mut self, types: &[crate::PriceType]) -> Self {
+        self.types = types.to_vec();
+        self
+    }
+
+    pub fn source(mut self, source: PriceSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn initial_fetch(mut self, initial_fetch: bool) -> Self {
+        self.initial_fetch = initial_fetch;
+        self
+    }
+}
+
+/// How `update_prices` fetches the prices of a cycle.
+///
+/// With the three default types over stocks only, `Snapshots` makes one
+/// data request per cycle where `Latest` makes three, one per type; both
+/// double when crypto pairs are mixed in and grow with the symbol chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PriceSource {
+    /// One snapshot request, decomposed into the same per type prices.
+    #[default]
+    Snapshots,
+    /// One latest trades, quotes or bars request per type.
+    Latest,
+}
+
+/// `start_order_polling` polls this many times less often without open
+/// orders, until a new one is tracked.
+const IDLE_POLL_FACTOR: u32 = 10;
+
+/// Fractional quantities are rounded down to the 9 decimals Alpaca accepts.
+const QTY_DECIMALS: f64 = 1e9;
+
+/// Profit and loss since the first update of the current session.
+///
+/// Only the orders already filled in their submit response are realized
+/// at once. The others, most market orders included, are realized when
+/// `AlpacaWrapper::start_order_polling` sees their fills: without it their
+/// gain stays in `unrealized` once the position is gone, as part of the
+/// equity change.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DailyPnl {
+    /// Closed by the sells filled during the session.
+    pub realized: f64,
+    /// The rest of the equity change, from the positions still held.
+    pub unrealized: f64,
+}
+
+impl DailyPnl {
+    pub fn total(&self) -> f64 {
+        self.realized + self.unrealized
+    }
+}
+
+/// Halt or resume of a symbol, emitted by `apply_trading_status` on each
+/// transition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HaltEvent {
+    pub symbol: String,
+    /// True for a halt, false for the resume.
+    pub halted: bool,
+    pub status: crate::TradingStatus,
+}
+
+/// What makes a signal order a duplicate of the last one submitted for
+/// its symbol and side, see `with_duplicate_guard`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKey {
+    /// Any order of the same symbol and side.
+    #[default]
+    Side,
+    /// Only an order with the same content, i.e. also the same quantity.
+    Order,
+}
+
+/// Change of the cached state for a corporate action of a tracked
+/// symbol, see `apply_corporate_actions`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorporateAdjustment {
+    /// A split or a stock dividend, `ratio` new shares per old one: 4 for
+    /// a 4:1 split, 1.05 for a 5% stock dividend.
+    Split {
+        symbol: String,
+        ratio: f64,
+        qty: (f64, f64),
+        entry: (f64, f64),
+        /// Armed stops with their price divided by `ratio`.
+        stops: usize,
+    },
+    /// A cash dividend credited to the account.
+    CashDividend { symbol: String, amount: f64 },
+}
+
+/// Signal order suppressed by the duplicate guard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuppressedSignal {
+    pub symbol: String,
+    pub side: crate::OrderSide,
+    pub qty: f64,
+    /// Suppressed since the last submission of the symbol and side,
+    /// this one included.
+    pub count: u32,
+    /// Left of the cooldown window.
+    pub remaining: Duration,
+}
+
+// Last signal order submitted for a symbol and side, reserved while its
+// submission is in flight
+#[derive(Debug)]
+struct Cooldown {
+    submitted: std::time::Instant,
+    hash: u64,
+    suppressed: u32,
+    in_flight: bool,
+}
+
+fn order_hash(ticker: &str, qty: f64, side: crate::OrderSide) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (ticker, side, qty.to_bits()).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Outcome of `AlpacaWrapper::shutdown` for each background task.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ShutdownReport {
+    /// Exited on their own after the cancellation.
+    pub exited: Vec<String>,
+    /// Panicked, before or during the shutdown.
+    pub failed: Vec<String>,
+    /// Still running at the timeout.
+    pub aborted: Vec<String>,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.failed.is_empty() && self.aborted.is_empty()
+    }
+}
+
+/// The prices of one `update_prices` cycle from `source`.
+pub(crate) async fn fetch_prices<C: crate::AlpacaApi>(
+    client: &C,
+    source: PriceSource,
+    assets: &[&str],
+    types: &[crate::PriceType],
+) -> Result<HashMap<String, HashMap<crate::PriceType, Value>>, crate::AlpacaError> {
+    match source {
+        PriceSource::Snapshots => client.get_snapshot_prices(assets, types).await,
+        PriceSource::Latest => client.get_prices_multi(assets, types).await,
+    }
+}
+
+/// `AlpacaWrapper::push_bar` on the bars of every symbol.
+fn push_bar_into(bars: &mut HashMap<String, VecDeque<crate::Bar>>, symbol: &str, bar: crate::Bar) {
+    let history = bars.entry(symbol.to_string()).or_default();
+
+    match history.back() {
+        Some(last) if last.timestamp == bar.timestamp => *history.back_mut().unwrap() = bar,
+        Some(last) if last.timestamp > bar.timestamp => {},
+        _ => {
+            history.push_back(bar);
+            if history.len() > BAR_HISTORY {
+                history.pop_front();
+            }
+        },
+    }
+}
+
+/// Asset among `assets` traded as `symbol` in orders or positions.
+fn asset_in<'a>(assets: &'a [String], symbol: &str) -> Option<&'a str> {
+    let symbol = crate::utils::position_symbol(symbol);
+    assets.iter()
+        .find(|asset| crate::utils::position_symbol(asset) == symbol)
+        .map(String::as_str)
+}
+
+type Prices = HashMap<String, HashMap<crate::PriceType, Value>>;
+
+/// Cash plus the positions valued at their latest trade, or at their
+/// market value without one. Both locks are held together so that an
+/// update can't land between the reads.
+fn equity_of(last_prices: &RwLock<Prices>, state: &CompletePosition) -> f64 {
+    let prices_guard = last_prices.read().unwrap();
+    let positions_guard = state.positions.read().unwrap();
+    let positions_value: f64 = positions_guard.iter()
+        .map(|(symbol, position)| {
+            prices_guard.get(symbol)
+                .and_then(|prices| prices.get(&crate::PriceType::Trades))
+                .and_then(|trade| trade["p"].as_f64())
+                .map_or(position.value, |price| price * position.qty)
+        })
+        .sum();
+
+    // Stored under the positions lock, see `store_balances`
+    state.cash.load(atomic::Ordering::Relaxed) + positions_value
+}
+
+/// Equity samples of the update cycles, oldest first.
+#[derive(Debug)]
+struct EquityCurve {
+    samples: VecDeque<(chrono::DateTime<chrono::Utc>, f64)>,
+    capacity: usize,
+    retention: Option<Duration>,
+}
+
+impl EquityCurve {
+    fn push(&mut self, timestamp: chrono::DateTime<chrono::Utc>, equity: f64) {
+        self.samples.push_back((timestamp, equity));
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+        if let Some(retention) = self.retention.and_then(|retention| chrono::Duration::from_std(retention).ok()) {
+            while self.samples.front().is_some_and(|(oldest, _)| timestamp - *oldest > retention) {
+                self.samples.pop_front();
+            }
+        }
+    }
+}
+
+impl Default for EquityCurve {
+    fn default() -> Self {
+        Self { samples: VecDeque::new(), capacity: DEFAULT_EQUITY_CURVE_CAPACITY, retention: None }
+    }
+}
+
+/// Positions of `assets` held by the account.
+async fn fetch_positions<C: crate::AlpacaApi>(
+    client: &C,
+    assets: &[String],
+) -> Result<HashMap<String, crate::utils::Position>, crate::AlpacaError> {
+    let positions = client.get_positions().await?;
+    let Some(positions) = positions.as_array() else {
+        return Err(crate::AlpacaError::Other(format!("Positions are not a list: {}", positions)));
+    };
+
+    let mut new_positions = HashMap::new();
+    for position in positions {
+        let Some(symbol) = position["symbol"].as_str() else {
+            log::warn!("Skipping position without symbol: {}", position);
+            continue;
+        };
+        // Positions hold the crypto pairs without slash
+        let Some(asset) = asset_in(assets, symbol) else { continue };
+
+        let parse_value = |key: &str| -> Option<f64> {
+            position[key].as_str().and_then(|s| s.parse::<f64>().ok())
+        };
+
+        let Some(qty) = parse_value("qty_available") else {
+            log::warn!("Skipping position of {} without quantity: {}", symbol, position);
+            continue;
+        };
+
+        new_positions.insert(asset.to_string(), crate::utils::Position {
+            qty,
+            value: parse_value("market_value").unwrap_or(0.0),
+            entry: parse_value("avg_entry_price").unwrap_or(0.0),
+            price: parse_value("current_price").unwrap_or(0.0),
+        });
+    }
+
+    Ok(new_positions)
+}
+
+/// Cash, buying power and shorting flag of the account.
+#[derive(Debug, Clone, Copy)]
+struct Balances {
+    cash: f64,
+    buying_power: f64,
+    shorting_enabled: bool,
+}
+
+async fn fetch_balances<C: crate::AlpacaApi>(client: &C) -> Result<Balances, crate::AlpacaError> {
+    let account = client.refresh_account().await?;
+
+    let cash = crate::utils::account_number(&account, "cash")?;
+    Ok(Balances {
+        cash,
+        buying_power: crate::utils::account_number(&account, "buying_power").unwrap_or(cash.max(0.0)),
+        shorting_enabled: account["shorting_enabled"].as_bool().unwrap_or(false),
+    })
+}
+
+impl CompletePosition {
+    /// Stores the new cash under the positions lock, for the equity reads
+    /// holding it.
+    fn store_balances(&self, _positions_guard: &HashMap<String, crate::utils::Position>, balances: Balances) {
+        self.cash.store(balances.cash, atomic::Ordering::Relaxed);
+        self.buying_power.store(balances.buying_power, atomic::Ordering::Relaxed);
+        self.shorting_enabled.store(balances.shorting_enabled, atomic::Ordering::Relaxed);
+    }
+
+    fn positions_guard(&self) -> Result<std::sync::RwLockWriteGuard<'_, HashMap<String, crate::utils::Position>>, crate::AlpacaError> {
+        self.positions.write()
+            .map_err(|e| crate::AlpacaError::Other(format!("Failed to acquire write lock for positions: {}", e)))
+    }
+}
+
+/// `AlpacaWrapper::update_positions` on the positions of `assets`.
+async fn refresh_positions<C: crate::AlpacaApi>(
+    client: &C,
+    assets: &[String],
+    state: &CompletePosition,
+) -> Result<(), crate::AlpacaError> {
+    let new_positions = fetch_positions(client, assets).await?;
+
+    // Update the shared positions with a write lock
+    let mut positions_guard = state.positions_guard()?;
+    *positions_guard = new_positions;
+    *state.positions_refreshed.lock().unwrap() = Some(chrono::Utc::now());
+
+    Ok(())
+}
+
+/// `AlpacaWrapper::update_cash` on `position`.
+async fn refresh_cash<C: crate::AlpacaApi>(client: &C, position: &CompletePosition) -> Result<(), crate::AlpacaError> {
+    let balances = fetch_balances(client).await?;
+
+    let positions_guard = position.positions_guard()?;
+    position.store_balances(&positions_guard, balances);
+    Ok(())
+}
+
+/// `refresh_positions` and `refresh_cash` swapping both under a single
+/// lock, so that no equity is read with the positions after a fill and
+/// the cash before it.
+async fn refresh_positions_and_cash<C: crate::AlpacaApi>(
+    client: &C,
+    assets: &[String],
+    state: &CompletePosition,
+) -> Result<(), crate::AlpacaError> {
+    let new_positions = fetch_positions(client, assets).await?;
+    let balances = fetch_balances(client).await?;
+
+    let mut positions_guard = state.positions_guard()?;
+    *positions_guard = new_positions;
+    *state.positions_refreshed.lock().unwrap() = Some(chrono::Utc::now());
+    state.store_balances(&positions_guard, balances);
+
+    Ok(())
+}
+
+/// `AlpacaWrapper::record_fill` of `qty` filled at `price`.
+fn realize_fill(
+    session: &Mutex<Session>,
+    realized: &Mutex<f64>,
+    state: &CompletePosition,
+    assets: &[String],
+    order: &Value,
+    qty: f64,
+    price: f64,
+) {
+    let Some((held, entry)) = order["symbol"].as_str()
+        .and_then(|symbol| asset_in(assets, symbol))
+        .and_then(|asset| state.positions.read().unwrap().get(asset).map(|position| (position.qty, position.entry)))
+    else {
+        return;
+    };
+
+    // Only the part closing the position, not the one opening the other side
+    let buy = order["side"] == "buy";
+    let closed = if buy { -held } else { held }.clamp(0.0, qty);
+    if closed == 0.0 {
+        return;
+    }
+    let gain = if buy { entry - price } else { price - entry } * closed;
+
+    *realized.lock().unwrap() += gain;
+    let mut session = session.lock().unwrap();
+    if session.close.is_some() {
+        session.realized += gain;
+    }
+}
+
+/// Updates between two polls of the same order: an execution when the
+/// filled quantity grew, then the cancel when the order ended before
+/// filling.
+fn poll_updates(previous: &crate::Order, current: crate::Order) -> Vec<crate::TradeUpdate> {
+    let ended = current.status.is_terminal() && current.status != crate::OrderStatus::Filled;
+    match crate::TradeUpdate::from_poll(previous, current.clone()) {
+        Some(fill) if fill.event.is_fill() && ended => {
+            // The cancel out of the fill with the status left as it was
+            let mut filled = current.clone();
+            filled.status = previous.status.clone();
+            std::iter::once(fill).chain(crate::TradeUpdate::from_poll(&filled, current)).collect()
+        },
+        update => update.into_iter().collect(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    // `next_close` of the clock while the session is open
+    close: Option<chrono::DateTime<chrono::FixedOffset>>,
+    baseline: f64,
+    realized: f64,
+    // Next open or close of the clock, not asked again before
+    recheck: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct CompletePosition {
@@ -34,333 +478,1411 @@ struct CompletePosition {
     positions: Arc<RwLock<HashMap<String, crate::utils::Position>>>,
     #[serde(with = "crate::utils::atomic_f64")]
     cash: crate::AtomicF64,
+    // From the account, with the cash
+    #[serde(with = "crate::utils::atomic_f64")]
+    buying_power: crate::AtomicF64,
+    #[serde(skip)]
+    shorting_enabled: atomic::AtomicBool,
+    // Last refresh of the positions from the account
+    #[serde(skip)]
+    positions_refreshed: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
 }
 
 impl Default for CompletePosition {
     fn default() -> Self {
         Self {
             positions: Arc::new(RwLock::new(HashMap::new())),
-            cash: crate::AtomicF64::default()
+            cash: crate::AtomicF64::default(),
+            buying_power: crate::AtomicF64::default(),
+            shorting_enabled: atomic::AtomicBool::new(false),
+            positions_refreshed: Mutex::new(None),
         }
     }
 }
 
+/// Synchronous trading loop state over any `AlpacaApi`.
+///
+/// `AlpacaClient` is the default, given to `with_client`; tests and
+/// downstream users can run the same wrapper against `MockAlpaca` through
+/// `with_api`.
 #[derive(Debug)]
-struct AlpacaWrapper {
-    client: Arc<crate::AlpacaClient>,
-    assets: Vec<String>,
+pub struct AlpacaWrapper<C = crate::AlpacaClient> {
+    // Shared with the background tasks
+    inner: Arc<Inner<C>>,
     runtime: Arc<Runtime>,
+    price_source: PriceSource,
+    fractionable: HashMap<String, bool>,
+    shortable: HashMap<String, bool>,
+    shorting: bool,
+    halt_events: broadcast::Sender<HaltEvent>,
+    // Signal orders are suppressed for `window` after a submission
+    duplicate_guard: Option<(Duration, DuplicateKey)>,
+    cooldowns: Mutex<HashMap<(String, crate::OrderSide), Cooldown>>,
+    suppressed_events: broadcast::Sender<SuppressedSignal>,
+
+    initial_position: Option<Arc<HashMap<String, crate::utils::Position>>>,
+    initial_cash: f64,
+    initial_counts: Option<crate::RequestCounts>,
+    started_at: chrono::DateTime<chrono::Utc>,
+    report_path: Option<std::path::PathBuf>,
+
+    // Background tasks, stopped by `shutdown` or the drop
+    cancel: CancellationToken,
+    tasks: Mutex<Vec<(String, tokio::task::JoinHandle<()>)>>,
+}
+
+/// State of an `AlpacaWrapper` that its background tasks update too.
+#[derive(Debug)]
+struct Inner<C> {
+    client: Arc<C>,
+    assets: Vec<String>,
 
     // Using RwLock for better read concurrency where possible
-    position: CompletePosition,
-    last_prices: Arc<RwLock<HashMap<String, HashMap<String, Value>>>>,
+    position: Arc<CompletePosition>,
+    last_prices: Arc<RwLock<Prices>>,
+    equity_curve: Arc<Mutex<EquityCurve>>,
+    price_types: Vec<crate::PriceType>,
+    // Oldest first, at most BAR_HISTORY per symbol
+    bars: Arc<RwLock<HashMap<String, VecDeque<crate::Bar>>>>,
+    max_price_age: Duration,
+    stops: crate::stops::StopBook,
+    stop_events: broadcast::Sender<crate::StopEvent>,
+    session: Arc<Mutex<Session>>,
+    // Realized since the wrapper was built, across the sessions
+    realized: Arc<Mutex<f64>>,
+    pnl_events: broadcast::Sender<DailyPnl>,
+    halted: Arc<RwLock<HashSet<String>>>,
+    // Assets without prices in the last update
+    missing: RwLock<HashSet<String>>,
+    // Orders not terminal yet, by id, as of their last poll
+    open_orders: Arc<Mutex<HashMap<String, Value>>>,
+    order_tracked: Arc<tokio::sync::Notify>,
+    order_events: broadcast::Sender<crate::TradeUpdate>,
+    // New York date of the last corporate actions check, the ids applied
+    // since and the symbols paying a cash dividend that day
+    corporate_date: Mutex<Option<chrono::NaiveDate>>,
+    corporate_applied: Mutex<HashSet<String>>,
+    dividends_due: Mutex<HashSet<String>>,
+    dividend_check_interval: Duration,
+    dividends_checked: Mutex<Option<std::time::Instant>>,
+    corporate_events: broadcast::Sender<CorporateAdjustment>,
+    journal: Option<Arc<crate::Journal>>,
+    strategy: Option<String>,
+}
 
-    initial_position: Option<Arc<HashMap<String, crate::utils::Position>>>,
+impl<C> Drop for AlpacaWrapper<C> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
 }
 
 impl AlpacaWrapper {
+    /// Connects to the paper trading API with the given keys, see
+    /// `with_client`.
     pub fn new(
         api_key: &str,
         api_secret: &str,
         assets: Vec<String>,
     ) -> Self {
-        assert!(!assets.is_empty(), "Assets list cannot be empty");
-
-        let client = Arc::new(crate::AlpacaClient::connect(api_key, api_secret).unwrap());
         // Create a multi-threaded runtime with default thread count
         let runtime = Arc::new(Runtime::new().unwrap());
+        let client = Arc::new(
+            runtime.block_on(crate::AlpacaClient::connect(api_key, api_secret)).unwrap()
+        );
 
-        let mut wrapper = AlpacaWrapper {
+        Self::build(runtime, client, assets, PriceOptions::default()).unwrap()
+    }
+
+    /// Builds a wrapper over an already configured client, e.g. with its
+    /// own hosts, retry policy or metrics. Clones of the client share its
+    /// connection pool and circuit breaker with the wrapper.
+    ///
+    /// Not async like `AlpacaClient::connect`: the wrapper blocks on a
+    /// runtime of its own, which tokio can neither start nor drop inside
+    /// another one, so this must not be called from async code.
+    pub fn with_client(client: Arc<crate::AlpacaClient>, assets: Vec<String>) -> Result<Self, crate::AlpacaError> {
+        Self::with_client_options(client, assets, PriceOptions::default())
+    }
+
+    /// `with_client` fetching the prices of `options`.
+    pub fn with_client_options(
+        client: Arc<crate::AlpacaClient>,
+        assets: Vec<String>,
+        options: PriceOptions,
+    ) -> Result<Self, crate::AlpacaError> {
+        Self::build(Arc::new(Runtime::new()?), client, assets, options)
+    }
+
+    pub fn environment(&self) -> &crate::Environment {
+        self.inner.client.environment()
+    }
+
+    pub fn is_paper(&self) -> bool {
+        self.inner.client.is_paper()
+    }
+}
+
+impl<C: crate::AlpacaApi> AlpacaWrapper<C> {
+    /// Builds a wrapper over an already constructed API implementation.
+    /// Like `with_client`, this must not be called from async code.
+    pub fn with_api(api: Arc<C>, assets: Vec<String>) -> Result<Self, crate::AlpacaError> {
+        Self::with_api_options(api, assets, PriceOptions::default())
+    }
+
+    /// `with_api` fetching the prices of `options`.
+    pub fn with_api_options(api: Arc<C>, assets: Vec<String>, options: PriceOptions) -> Result<Self, crate::AlpacaError> {
+        Self::build(Arc::new(Runtime::new()?), api, assets, options)
+    }
+
+    fn build(runtime: Arc<Runtime>, client: Arc<C>, assets: Vec<String>, options: PriceOptions) -> Result<Self, crate::AlpacaError> {
+        if assets.is_empty() {
+            return Err(crate::AlpacaError::InvalidParameter("assets list cannot be empty".to_string()));
+        }
+
+        let inner = Inner {
             client,
             assets,
-            runtime,
-            position: CompletePosition::default(),
+            position: Default::default(),
             last_prices: Arc::new(RwLock::new(HashMap::new())),
+            equity_curve: Default::default(),
+            price_types: DEFAULT_PRICE_TYPES.into_iter().filter(|price_type| options.types.contains(price_type)).collect(),
+            bars: Arc::new(RwLock::new(HashMap::new())),
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            stops: Default::default(),
+            stop_events: broadcast::channel(64).0,
+            session: Default::default(),
+            realized: Default::default(),
+            pnl_events: broadcast::channel(64).0,
+            halted: Default::default(),
+            missing: Default::default(),
+            open_orders: Default::default(),
+            order_tracked: Default::default(),
+            order_events: broadcast::channel(64).0,
+            corporate_date: Default::default(),
+            corporate_applied: Default::default(),
+            dividends_due: Default::default(),
+            dividend_check_interval: DEFAULT_DIVIDEND_CHECK_INTERVAL,
+            dividends_checked: Default::default(),
+            corporate_events: broadcast::channel(64).0,
+            journal: None,
+            strategy: None,
+        };
+        let mut wrapper = AlpacaWrapper {
+            inner: Arc::new(inner),
+            runtime,
+            price_source: options.source,
+            fractionable: HashMap::new(),
+            shortable: HashMap::new(),
+            shorting: false,
+            halt_events: broadcast::channel(64).0,
+            duplicate_guard: None,
+            cooldowns: Default::default(),
+            suppressed_events: broadcast::channel(64).0,
             initial_position: None,
+            initial_cash: 0.0,
+            initial_counts: None,
+            started_at: chrono::Utc::now(),
+            report_path: None,
+            cancel: CancellationToken::new(),
+            tasks: Default::default(),
         };
 
         // Initialize data
-        wrapper.runtime.block_on(wrapper.update_cash_async());
-        wrapper.runtime.block_on(wrapper.update_positions_async());
-        wrapper.update_prices();
+        if let Err(e) = wrapper.update_cash() {
+            log::error!("Failed to get the initial cash: {}", e);
+        }
+        if let Err(e) = wrapper.update_positions() {
+            log::error!("Failed to get the initial positions: {}", e);
+        }
+        if options.initial_fetch {
+            wrapper.update_prices();
+        }
+
+        // Queried once, it doesn't change during a session
+        for asset in &wrapper.inner.assets {
+            // Crypto can't be sold short
+            if crate::utils::is_crypto(asset) {
+                wrapper.fractionable.insert(asset.clone(), true);
+                wrapper.shortable.insert(asset.clone(), false);
+                continue;
+            }
+
+            let (fractionable, shortable) = match wrapper.runtime.block_on(wrapper.inner.client.get_asset(asset)) {
+                Ok(asset) => (asset.fractionable, asset.shortable),
+                Err(e) => {
+                    log::warn!("Failed to get asset {}, sizing it in whole shares without shorting: {}", asset, e);
+                    (false, false)
+                }
+            };
+            wrapper.fractionable.insert(asset.clone(), fractionable);
+            wrapper.shortable.insert(asset.clone(), shortable);
+        }
 
         // Store initial position
-        wrapper.initial_position = Some(Arc::new(wrapper.position.positions.read().unwrap().clone()));
+        wrapper.initial_position = Some(Arc::new(wrapper.inner.position.positions.read().unwrap().clone()));
+        wrapper.initial_cash = wrapper.inner.position.cash.load(atomic::Ordering::Relaxed);
+        wrapper.initial_counts = wrapper.inner.client.request_counts();
 
-        wrapper
+        Ok(wrapper)
     }
 
-    pub fn update_prices(&self) {
-        let items = &["trades", "quotes", "bars"];
+    // The settings of the shared state are only changed by the builder
+    // methods, before any background task holds it
+    fn inner_mut(&mut self) -> &mut Inner<C> {
+        Arc::get_mut(&mut self.inner).expect("wrapper configured after starting its background tasks")
+    }
+
+    /// Keeps the last `capacity` samples of the equity curve.
+    pub fn with_equity_curve_capacity(self, capacity: usize) -> Self {
+        self.inner.equity_curve.lock().unwrap().capacity = capacity;
+        self
+    }
+
+    /// Drops the samples of the equity curve older than `retention`
+    /// before the newest one, besides those beyond its capacity.
+    pub fn with_equity_curve_retention(self, retention: Duration) -> Self {
+        self.inner.equity_curve.lock().unwrap().retention = Some(retention);
+        self
+    }
 
-        // Execute all requests in parallel using Tokio
-        let mut set = JoinSet::new();
+    /// Checks the account activities for the dividends due today at most
+    /// once per `interval` in `update_prices`.
+    pub fn with_dividend_check_interval(mut self, interval: Duration) -> Self {
+        self.inner_mut().dividend_check_interval = interval;
+        self
+    }
 
-        for item in items.into_iter() {
-            let client = self.client.clone();
-            let assets = self.assets.clone();
+    /// Prices older than `max_age` are ignored by the sizing helpers and
+    /// the stop losses.
+    pub fn with_max_price_age(mut self, max_age: Duration) -> Self {
+        self.inner_mut().max_price_age = max_age;
+        self
+    }
 
-            set.spawn(async move {
-                let assets_copy = assets;
-                client.get_prices(&assets_copy, crate::PriceType::from_str(item).unwrap()).await
+    /// Only fetches `types` from the next `update_prices`, all of them by
+    /// default. The cached prices of the other types are dropped, so their
+    /// accessors return `None` from now on. The construction already
+    /// fetched every type, set them in `PriceOptions` to avoid it.
+    pub fn with_price_types(mut self, types: &[crate::PriceType]) -> Self {
+        self.inner_mut().price_types = DEFAULT_PRICE_TYPES.into_iter().filter(|price_type| types.contains(price_type)).collect();
+        for prices in self.inner.last_prices.write().unwrap().values_mut() {
+            prices.retain(|price_type, _| self.inner.price_types.contains(price_type));
+        }
+        self
+    }
+
+    pub fn price_types(&self) -> &[crate::PriceType] {
+        &self.inner.price_types
+    }
+
+    /// Falls back to the per type latest endpoints with
+    /// `PriceSource::Latest` from the next `update_prices`, e.g. for a feed
+    /// without snapshots. The construction already fetched from the
+    /// default source, set it in `PriceOptions` to avoid it.
+    pub fn with_price_source(mut self, source: PriceSource) -> Self {
+        self.price_source = source;
+        self
+    }
+
+    pub fn price_source(&self) -> PriceSource {
+        self.price_source
+    }
+
+    /// Journals the orders submitted by the wrapper under `strategy`.
+    pub fn with_journal(mut self, journal: Arc<crate::Journal>, strategy: &str) -> Self {
+        self.inner_mut().journal = Some(journal);
+        self.inner_mut().strategy = Some(strategy.to_string());
+        self
+    }
+
+    /// Lets a sell signal without position open a short, when the account
+    /// has shorting enabled and the asset is shortable. Off by default, so
+    /// the sells only close what is held.
+    pub fn with_shorting(mut self, shorting: bool) -> Self {
+        self.shorting = shorting;
+        self
+    }
+
+    /// Whether a sell signal on `ticker` may open a short, as of the last
+    /// `update_cash`.
+    pub fn can_short(&self, ticker: &str) -> bool {
+        self.shorting
+            && self.inner.position.shorting_enabled.load(atomic::Ordering::Relaxed)
+            && self.shortable.get(ticker).copied().unwrap_or(false)
+    }
+
+    /// Writes the `session_report` to `path` on `shutdown`, see
+    /// `SessionReport::write_to` for the format.
+    pub fn with_session_report(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.report_path = Some(path.into());
+        self
+    }
+
+    /// Journals an update of an order, such as its fill or cancel seen
+    /// through `get_order_info`.
+    pub fn journal_order(&self, order: &Value) {
+        self.inner.journal_order(order)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub fn update_prices(&self) {
+        let assets: Vec<&str> = self.inner.assets.iter().map(String::as_str).collect();
+
+        let fetch = fetch_prices(self.inner.client.as_ref(), self.price_source, &assets, &self.inner.price_types);
+        let last_prices = match self.runtime.block_on(fetch) {
+            Ok(prices) => prices,
+            Err(e) => {
+                log::error!("Failed to update prices: {}", e);
+                return;
             }
-            );
-        }
-
-        let last_prices = HashMap::new();
-
-        let mut asset_prices = HashMap::new();
-        for asset in self.assets.clone() {
-            asset_prices.insert(asset.to_string(), HashMap::new());
-        }
-
-        while let Some(result) = self.runtime.block_on(set.join_next()) {
-
-            match result.unwrap().unwrap() {
-                Value::Object(type_map) => {
-                    for (price_name, price_values) in type_map {
-                        match price_values {
-                            Value::Object(price_map) => {
-                                for (asset_name, prices) in price_map {
-                                    if self.assets.contains(&asset_name) {
-                                        asset_prices.get_mut(asset_name.as_str())
-                                            .unwrap()
-                                            .insert(
-                                                crate::PriceType::from_str(price_name.as_str()).unwrap(),
-                                                prices
-                                            );
-                                    }
-                                }
-                            },
-                            _ => println!("Entry is not a JSON object."),
-                        }
-                    }
-                },
-                _ => println!("Value is not a JSON object.")
+        };
+
+        self.apply_prices(last_prices);
+    }
+
+    /// Second half of `update_prices`, for prices fetched elsewhere like
+    /// the shared ones of `MultiAccountWrapper`.
+    pub(crate) fn apply_prices(&self, last_prices: HashMap<String, HashMap<crate::PriceType, Value>>) {
+        self.runtime.block_on(self.inner.apply_prices(last_prices))
+    }
+
+    /// Adjusts the cached positions and stops of the tracked symbols for
+    /// the splits and stock dividends effective today, and the cached cash
+    /// for the dividends credited today, once per action. The positions
+    /// refreshed from the account today are left as they are, the account
+    /// already has them adjusted, and so is the cash after a refresh.
+    ///
+    /// `update_prices` calls it at the first update of each day, before
+    /// the adjusted prices replace the cached ones, so that neither the
+    /// stops nor the P&L see the price drop of a split.
+    pub async fn apply_corporate_actions_async(&self) -> Result<Vec<CorporateAdjustment>, crate::AlpacaError> {
+        let today = crate::market_hours::new_york_date(chrono::Utc::now());
+        self.apply_corporate_actions_on(today, true).await
+    }
+
+    pub fn apply_corporate_actions(&self) -> Result<Vec<CorporateAdjustment>, crate::AlpacaError> {
+        self.runtime.block_on(self.apply_corporate_actions_async())
+    }
+
+    pub fn subscribe_corporate_actions(&self) -> broadcast::Receiver<CorporateAdjustment> {
+        self.inner.corporate_events.subscribe()
+    }
+
+    pub(crate) async fn apply_corporate_actions_on(
+        &self,
+        today: chrono::NaiveDate,
+        fetch_actions: bool,
+    ) -> Result<Vec<CorporateAdjustment>, crate::AlpacaError> {
+        self.inner.apply_corporate_actions_on(today, fetch_actions).await
+    }
+
+    /// `None` while the market is closed. The fills are only realized
+    /// with `start_order_polling` running, see `DailyPnl`.
+    pub fn daily_pnl(&self) -> Option<DailyPnl> {
+        self.inner.daily_pnl()
+    }
+
+    /// Receives the daily P&L after every price update of the session.
+    pub fn subscribe_pnl(&self) -> broadcast::Receiver<DailyPnl> {
+        self.inner.pnl_events.subscribe()
+    }
+
+    /// Tracks the halts from a trading status message of the stream.
+    ///
+    /// On a halt the cached prices of the symbol are dropped and kept out
+    /// by `update_prices`, and the signal handlers ignore the symbol until
+    /// its resume.
+    pub fn apply_trading_status(&self, status: &crate::TradingStatus) {
+        let halted = status.status_code.is_halted();
+        if !halted && status.status_code != crate::TradingStatusCode::Resume {
+            return;
+        }
+
+        let changed = {
+            let mut halted_guard = self.inner.halted.write().unwrap();
+            if halted {
+                halted_guard.insert(status.symbol.clone())
+            } else {
+                halted_guard.remove(&status.symbol)
             }
+        };
+        if !changed {
+            return;
         }
 
+        if halted {
+            log::warn!("{} halted: {} {}", status.symbol, status.status_message, status.reason_message);
+            self.inner.last_prices.write().unwrap().remove(&status.symbol);
+        } else {
+            log::info!("{} resumed", status.symbol);
+        }
 
-        // Take write lock only to update the final result
-        let mut prices_guard = self.last_prices.write().unwrap();
-        *prices_guard = last_prices;
+        let _ = self.halt_events.send(HaltEvent { symbol: status.symbol.clone(), halted, status: status.clone() });
+    }
+
+    pub fn is_halted(&self, symbol: &str) -> bool {
+        self.inner.is_halted(symbol)
+    }
+
+    /// Assets left out of the last prices update, without cached prices
+    /// until a later update brings them back. Sorted.
+    pub fn stale_symbols(&self) -> Vec<String> {
+        let mut stale: Vec<String> = self.inner.missing.read().unwrap().iter().cloned().collect();
+        stale.sort_unstable();
+        stale
+    }
+
+    pub fn subscribe_halts(&self) -> broadcast::Receiver<HaltEvent> {
+        self.halt_events.subscribe()
+    }
+
+    /// Suppresses the orders of the buy and sell signals repeating one
+    /// submitted less than `window` ago for the same symbol and side, and
+    /// for `DuplicateKey::Order` the same quantity. The window starts from
+    /// the successful submission, a rejected order doesn't start it.
+    pub fn with_duplicate_guard(mut self, window: Duration, key: DuplicateKey) -> Self {
+        self.duplicate_guard = Some((window, key));
+        self
+    }
+
+    /// Lets the next signal of `symbol` submit its order, on either side.
+    pub fn clear_cooldown(&self, symbol: &str) {
+        self.cooldowns.lock().unwrap().retain(|(ticker, _), _| ticker != symbol);
+    }
+
+    pub fn subscribe_suppressed(&self) -> broadcast::Receiver<SuppressedSignal> {
+        self.suppressed_events.subscribe()
+    }
+
+    /// Watches `symbol` until its latest trade or bid is at or below
+    /// `stop_price`, see `StopMode`. Returns the id to disarm it.
+    pub fn arm_stop_loss(&self, symbol: &str, stop_price: f64, mode: crate::StopMode) -> u64 {
+        self.inner.stops.arm(symbol, stop_price, mode)
+    }
+
+    /// False when `id` is not armed, e.g. because it already fired.
+    pub fn disarm_stop_loss(&self, id: u64) -> bool {
+        self.inner.stops.disarm(id)
+    }
+
+    pub fn active_stops(&self) -> Vec<crate::StopLoss> {
+        self.inner.stops.active()
+    }
+
+    pub fn subscribe_stops(&self) -> broadcast::Receiver<crate::StopEvent> {
+        self.inner.stop_events.subscribe()
+    }
+
+    /// Adds `bar` to the history of `symbol`. A bar with the timestamp of
+    /// the last one replaces it, as the current bar keeps updating until
+    /// it closes, and older bars are ignored.
+    pub fn push_bar(&self, symbol: &str, bar: crate::Bar) {
+        self.inner.push_bar(symbol, bar)
+    }
+
+    /// Close of the last `n` bars of `symbol`, oldest first, or `None`
+    /// with fewer bars than that.
+    pub fn last_n_closes(&self, symbol: &str, n: usize) -> Option<Vec<f64>> {
+        let bars_guard = self.inner.bars.read().unwrap();
+        let history = bars_guard.get(symbol)?;
+
+        if n == 0 || history.len() < n {
+            return None;
+        }
+        Some(history.iter().skip(history.len() - n).map(|bar| bar.close).collect())
+    }
+
+    /// Simple moving average of the last `period` closes.
+    pub fn sma(&self, symbol: &str, period: usize) -> Option<f64> {
+        let closes = self.last_n_closes(symbol, period)?;
+        Some(closes.iter().sum::<f64>() / period as f64)
+    }
+
+    /// Exponential moving average over the whole history, seeded with the
+    /// simple average of its first `period` closes.
+    pub fn ema(&self, symbol: &str, period: usize) -> Option<f64> {
+        let closes: Vec<f64> = self.inner.bars.read().unwrap()
+            .get(symbol)?
+            .iter()
+            .map(|bar| bar.close)
+            .collect();
+        if period == 0 || closes.len() < period {
+            return None;
+        }
+
+        let alpha = 2.0 / (period as f64 + 1.0);
+        let seed = closes[..period].iter().sum::<f64>() / period as f64;
+
+        Some(closes[period..].iter().fold(seed, |ema, close| alpha * close + (1.0 - alpha) * ema))
     }
 
     pub async fn get_order_info_async(&self, order_id: &str) -> Value {
-        self.client.get_order_info_async(order_id).await.unwrap()
+        self.inner.client.get_order_info(order_id, false).await.unwrap()
     }
 
     pub fn get_order_info(&self, order_id: &str) -> Value {
-        self.runtime.block_on(self.client.get_order_info_async(order_id)).unwrap()
+        self.runtime.block_on(self.inner.client.get_order_info(order_id, false)).unwrap()
     }
 
-    pub async fn update_positions_async(&self)
+    /// Replaces the cached positions of the wrapper assets. On error the
+    /// previous ones are kept, and the malformed entries are skipped.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn update_positions_async(&self) -> Result<(), crate::AlpacaError>
     {
-        let positions = self.client.get_positions().await;
+        refresh_positions(self.inner.client.as_ref(), &self.inner.assets, &self.inner.position).await
+    }
+
+    pub fn assets(&self) -> &[String] {
+        &self.inner.assets
+    }
+
+    pub fn positions(&self) -> HashMap<String, crate::Position> {
+        self.inner.position.positions.read().unwrap().clone()
+    }
+
+    pub fn update_positions(&self) -> Result<(), crate::AlpacaError> {
+        self.runtime.block_on(self.update_positions_async())
+    }
+
+    /// Refreshes the cached cash, kept as it was on error. A missing or
+    /// invalid `cash` is an error, not a zero balance.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn update_cash_async(&self) -> Result<(), crate::AlpacaError> {
+        refresh_cash(self.inner.client.as_ref(), &self.inner.position).await
+    }
+
+    pub fn update_cash(&self) -> Result<(), crate::AlpacaError> {
+        self.runtime.block_on(self.update_cash_async())
+    }
+
+    fn quote_price(&self, ticker: &str, field: &str) -> f64 {
+        let prices_guard = self.inner.last_prices.read().unwrap();
+        prices_guard
+            .get(ticker)
+            .and_then(|asset_prices| asset_prices.get(&crate::PriceType::Quotes))
+            .and_then(|quotes| quotes[field].as_f64())
+            .unwrap_or(0.0)
+    }
+
+    fn cached_price<T: serde::de::DeserializeOwned>(&self, ticker: &str, price_type: crate::PriceType) -> Option<T> {
+        let prices_guard = self.inner.last_prices.read().unwrap();
+        let price = prices_guard.get(ticker)?.get(&price_type)?;
+        serde_json::from_value(price.clone()).ok()
+    }
+
+    /// Last trade of `ticker` fetched by `update_prices`, `None` when
+    /// trades are not among the fetched `price_types`.
+    pub fn latest_trade(&self, ticker: &str) -> Option<crate::Trade> {
+        self.cached_price(ticker, crate::PriceType::Trades)
+    }
+
+    /// Last quote of `ticker`, as `latest_trade`.
+    pub fn latest_quote(&self, ticker: &str) -> Option<crate::Quote> {
+        self.cached_price(ticker, crate::PriceType::Quotes)
+    }
+
+    /// Last bar of `ticker`, as `latest_trade`.
+    pub fn latest_bar(&self, ticker: &str) -> Option<crate::Bar> {
+        self.cached_price(ticker, crate::PriceType::Bars)
+    }
+
+    /// Cash plus the market value of the positions, at their last trade
+    /// price when there is one.
+    pub fn equity(&self) -> f64 {
+        self.inner.equity()
+    }
+
+    /// Equity after each prices update, oldest first, bounded by the
+    /// capacity and retention of the curve.
+    pub fn equity_curve(&self) -> Vec<(chrono::DateTime<chrono::Utc>, f64)> {
+        self.inner.equity_curve.lock().unwrap().samples.iter().copied().collect()
+    }
+
+    /// Highest equity of the curve, `None` before the first sample.
+    pub fn high_water_mark(&self) -> Option<f64> {
+        self.inner.equity_curve.lock().unwrap().samples.iter()
+            .map(|(_, equity)| *equity)
+            .reduce(f64::max)
+    }
+
+    /// Largest fall of the curve from a previous high, as a fraction of
+    /// that high: 0.1 for a 10% drawdown. `None` before the first sample.
+    pub fn max_drawdown(&self) -> Option<f64> {
+        let curve = self.inner.equity_curve.lock().unwrap();
+        let mut samples = curve.samples.iter().map(|(_, equity)| *equity);
+        let mut peak = samples.next()?;
+
+        Some(samples.fold(0.0, |drawdown: f64, equity| {
+            peak = peak.max(equity);
+            if peak > 0.0 { drawdown.max((peak - equity) / peak) } else { drawdown }
+        }))
+    }
+
+    fn round_qty(&self, ticker: &str, qty: f64) -> Option<f64> {
+        let qty = if self.fractionable.get(ticker).copied().unwrap_or(false) {
+            (qty * QTY_DECIMALS).floor() / QTY_DECIMALS
+        } else {
+            qty.floor()
+        };
+
+        (qty > 0.0).then_some(qty)
+    }
+
+    /// Quantity of `ticker` worth `fraction_of_equity` of the equity at
+    /// the current ask. `None` with a stale quote or a zero quantity.
+    pub fn size_fixed_fraction(&self, ticker: &str, fraction_of_equity: f64) -> Option<f64> {
+        let ask = self.inner.fresh_price(ticker, crate::PriceType::Quotes, "ap")?;
+        self.round_qty(ticker, self.equity() * fraction_of_equity / ask)
+    }
 
-        let new_positions = positions
-            .into_iter()
-            .filter_map(|position| {
-                let symbol = position["symbol"].as_str().unwrap().to_string();
+    /// Quantity of `ticker` losing `risk_fraction` of the equity if the
+    /// price falls `stop_distance` (a fraction of the ask) below the ask.
+    /// Never more than the equity buys, so a tight stop doesn't lever up.
+    pub fn size_risk_based(&self, ticker: &str, risk_fraction: f64, stop_distance: f64) -> Option<f64> {
+        if stop_distance <= 0.0 {
+            return None;
+        }
 
-                if !self.assets.contains(&symbol) {
+        let ask = self.inner.fresh_price(ticker, crate::PriceType::Quotes, "ap")?;
+        let equity = self.equity();
+        let qty = (equity * risk_fraction / (ask * stop_distance)).min(equity / ask);
+
+        self.round_qty(ticker, qty)
+    }
+
+    // Suppressed when the guard finds a duplicate in its cooldown window
+    async fn place_signal_order(&self, ticker: &str, qty: f64, side: crate::OrderSide) -> Option<Value> {
+        let Some((window, key)) = self.duplicate_guard else {
+            return self.inner.place_market_order(ticker, qty, side).await;
+        };
+
+        let hash = order_hash(ticker, qty, side);
+        let cooldown_key = (ticker.to_string(), side);
+        let suppressed = {
+            let mut cooldowns = self.cooldowns.lock().unwrap();
+            let suppressed = cooldowns.get_mut(&cooldown_key).and_then(|cooldown| {
+                // The window starts once the submission is answered
+                let elapsed = if cooldown.in_flight { Duration::ZERO } else { cooldown.submitted.elapsed() };
+                if elapsed >= window || (key == DuplicateKey::Order && cooldown.hash != hash) {
                     return None;
                 }
+                cooldown.suppressed += 1;
+                Some(SuppressedSignal {
+                    symbol: ticker.to_string(),
+                    side,
+                    qty,
+                    count: cooldown.suppressed,
+                    remaining: window - elapsed,
+                })
+            });
+
+            // Reserved before submitting, so that the signals arriving
+            // meanwhile are suppressed too
+            if suppressed.is_none() {
+                cooldowns.insert(cooldown_key.clone(), Cooldown {
+                    submitted: std::time::Instant::now(),
+                    hash,
+                    suppressed: 0,
+                    in_flight: true,
+                });
+            }
+            suppressed
+        };
+        if let Some(event) = suppressed {
+            log::warn!("Suppressed {:?} signal of {} repeated within {:?}, {} so far",
+                side, ticker, window, event.count);
+            let _ = self.suppressed_events.send(event);
+            return None;
+        }
+
+        let order = self.inner.place_market_order(ticker, qty, side).await;
+
+        let mut cooldowns = self.cooldowns.lock().unwrap();
+        // Unless cleared or taken over by another order meanwhile
+        if let Some(cooldown) = cooldowns.get_mut(&cooldown_key).filter(|cooldown| cooldown.in_flight && cooldown.hash == hash) {
+            match order {
+                Some(_) => {
+                    cooldown.submitted = std::time::Instant::now();
+                    cooldown.in_flight = false;
+                },
+                None => {
+                    cooldowns.remove(&cooldown_key);
+                },
+            }
+        }
+        order
+    }
+
+    pub async fn manage_buy_signal_async(&self, ticker: &str) -> Option<Value> {
+        log::info!("Manage buy signal");
+        if self.is_halted(ticker) {
+            log::warn!("{} is halted, ignoring the buy signal", ticker);
+            return None;
+        }
+
+        // Get seller price
+        let seller_price = self.quote_price(ticker, "ap");
+        if seller_price <= 0.0 {
+            return None;
+        }
+
+        // A short is covered first, the next signal opens the long
+        let held = self.inner.position.positions.read().unwrap().get(ticker).map_or(0.0, |position| position.qty);
+        if held < 0.0 {
+            return self.place_signal_order(ticker, -held, crate::OrderSide::Buy).await;
+        }
+
+        let cash = self.inner.position.cash.load(atomic::Ordering::Relaxed);
+
+        // Only buy if we have enough cash, for a fraction with crypto and
+        // the fractionable assets
+        let qty = self.round_qty(ticker, cash / seller_price)?;
+        self.place_signal_order(ticker, qty, crate::OrderSide::Buy).await
+    }
+
+    pub fn manage_buy_signal(&self, ticker: &str) -> Option<Value> {
+        self.runtime.block_on(self.manage_buy_signal_async(ticker))
+    }
+
+    pub async fn manage_sell_signal_async(&self, ticker: &str) -> Option<Value> {
+        log::info!("Manage sell signal");
+        if self.is_halted(ticker) {
+            log::warn!("{} is halted, ignoring the sell signal", ticker);
+            return None;
+        }
+
+        // Get position information
+        let (qty, entry_price) = {
+            let positions_guard = self.inner.position.positions.read().unwrap();
+            if let Some(position) = positions_guard.get(ticker) {
+                (position.qty, position.entry)
+            } else {
+                (0.0, 0.0)
+            }
+        };
+
+        // Get buyer price
+        let buyer_price = self.quote_price(ticker, "bp");
+
+        // Only place the order if we hold some and bought them cheaper than current price
+        if qty > 0.0 && buyer_price > entry_price {
+            return self.place_signal_order(ticker, qty, crate::OrderSide::Sell).await;
+        }
+
+        // Without position, a short of whole shares worth the buying power
+        if qty == 0.0 && buyer_price > 0.0 {
+            if !self.can_short(ticker) {
+                log::info!("No position in {} and shorting it is not allowed", ticker);
+                return None;
+            }
+
+            let short_qty = (self.inner.position.buying_power.load(atomic::Ordering::Relaxed) / buyer_price).floor();
+            if short_qty > 0.0 {
+                return self.place_signal_order(ticker, short_qty, crate::OrderSide::Sell).await;
+            }
+        }
+
+        None
+    }
+
+    pub fn manage_sell_signal(&self, ticker: &str) -> Option<Value> {
+        self.runtime.block_on(self.manage_sell_signal_async(ticker))
+    }
+
+    /// Runs `update_prices` every `interval` on a task of the wrapper
+    /// runtime until `shutdown`, corporate actions, stops and daily P&L
+    /// included.
+    pub fn start_background_updates(&self, interval: Duration)
+    where
+        C: 'static,
+    {
+        let (inner, source) = (self.inner.clone(), self.price_source);
+        let cancel = self.cancel.clone();
+
+        let task = self.runtime.spawn(async move {
+            let symbols: Vec<&str> = inner.assets.iter().map(String::as_str).collect();
+            let mut ticker = tokio::time::interval(interval);
+
+            loop {
+                // An update in flight is dropped as well
+                let prices = tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    prices = async {
+                        ticker.tick().await;
+                        fetch_prices(inner.client.as_ref(), source, &symbols, &inner.price_types).await
+                    } => prices,
+                };
+
+                match prices {
+                    Ok(prices) => inner.apply_prices(prices).await,
+                    Err(e) => log::error!("Failed to update prices in the background: {}", e),
+                }
+            }
+        });
+
+        self.tasks.lock().unwrap().push(("price_updates".to_string(), task));
+    }
+
+    /// Stops the background tasks, waiting up to `timeout` for them to
+    /// exit before aborting the rest, then flushes the journal. Tasks can't
+    /// be started again afterwards.
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.cancel.cancel();
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        let mut report = ShutdownReport::default();
+        self.runtime.block_on(async {
+            for (name, mut task) in tasks {
+                match tokio::time::timeout_at(deadline, &mut task).await {
+                    Ok(Ok(())) => report.exited.push(name),
+                    Ok(Err(e)) => {
+                        log::error!("Background task {} failed: {}", name, e);
+                        report.failed.push(name);
+                    }
+                    Err(_) => {
+                        log::warn!("Background task {} still running after {:?}, aborted", name, timeout);
+                        task.abort();
+                        report.aborted.push(name);
+                    }
+                }
+            }
+        });
+
+        if let Some(journal) = &self.inner.journal {
+            journal.flush();
+        }
+        if let Some(path) = &self.report_path {
+            if let Err(e) = self.session_report().write_to(path) {
+                log::error!("Failed to write the session report to {}: {}", path.display(), e);
+            }
+        }
+        report
+    }
+
+    /// Follows `order` with `start_order_polling` until it ends, unless
+    /// it already has. The orders of the signal handlers are tracked
+    /// already, this is for the ones submitted through the client.
+    pub fn track_order(&self, order: &Value) {
+        self.inner.track_order(order)
+    }
+
+    /// Ids of the tracked orders still open.
+    pub fn open_orders(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.inner.open_orders.lock().unwrap().keys().cloned().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Receives the fills and cancels of the tracked orders.
+    pub fn subscribe_orders(&self) -> broadcast::Receiver<crate::TradeUpdate> {
+        self.inner.order_events.subscribe()
+    }
+
+    /// Polls the tracked orders every `interval` on a task of the wrapper
+    /// runtime until `shutdown`, and every `IDLE_POLL_FACTOR` intervals
+    /// while none is open. Their changes go to the journal and, once a fill
+    /// has refreshed the positions and the cash, to `subscribe_orders`.
+    pub fn start_order_polling(&self, interval: Duration)
+    where
+        C: 'static,
+    {
+        let client = self.inner.client.clone();
+        let (open_orders, tracked, events) = (self.inner.open_orders.clone(), self.inner.order_tracked.clone(), self.inner.order_events.clone());
+        let (assets, state, session) = (self.inner.assets.clone(), self.inner.position.clone(), self.inner.session.clone());
+        let realized = self.inner.realized.clone();
+        let (journal, strategy) = (self.inner.journal.clone(), self.inner.strategy.clone());
+        let cancel = self.cancel.clone();
+
+        let task = self.runtime.spawn(async move {
+            loop {
+                let idle = open_orders.lock().unwrap().is_empty();
+                tokio::select! {
+                    _ = cancel.cancelled() => break,
+                    _ = tokio::time::sleep(if idle { interval * IDLE_POLL_FACTOR } else { interval }) => {},
+                    _ = tracked.notified(), if idle => {},
+                }
+
+                let polled: Vec<(String, Value)> = open_orders.lock().unwrap()
+                    .iter()
+                    .map(|(id, order)| (id.clone(), order.clone()))
+                    .collect();
+
+                let mut polled_events = Vec::new();
+                for (id, previous) in polled {
+                    let current = match client.get_order_info(&id, false).await {
+                        Ok(current) => current,
+                        Err(e) => {
+                            log::warn!("Failed to poll order {}: {}", id, e);
+                            continue;
+                        }
+                    };
+
+                    let updates = match (serde_json::from_value::<crate::Order>(previous), serde_json::from_value::<crate::Order>(current.clone())) {
+                        (Ok(previous), Ok(parsed)) => poll_updates(&previous, parsed),
+                        (Err(e), _) | (_, Err(e)) => {
+                            log::warn!("Failed to parse the polled order {}: {}", id, e);
+                            continue;
+                        }
+                    };
+                    for update in &updates {
+                        if let (Some(qty), Some(price)) = (update.qty, update.price) {
+                            realize_fill(&session, &realized, &state, &assets, &current, qty, price);
+                        }
+                    }
+                    if let Some(journal) = journal.as_ref().filter(|_| !updates.is_empty()) {
+                        if let Some(record) = crate::JournalRecord::from_order(&current, strategy.as_deref()) {
+                            journal.record(record);
+                        }
+                    }
+
+                    let mut open_guard = open_orders.lock().unwrap();
+                    if crate::OrderStatus::from(current["status"].as_str().unwrap_or_default()).is_terminal() {
+                        open_guard.remove(&id);
+                    } else {
+                        open_guard.insert(id, current);
+                    }
+                    drop(open_guard);
+
+                    polled_events.extend(updates);
+                }
+
+                // Sent after the refresh, so that the receivers see the new positions
+                if polled_events.iter().any(|update| update.event.is_fill()) {
+                    if let Err(e) = refresh_positions_and_cash(client.as_ref(), &assets, &state).await {
+                        log::error!("Failed to refresh the positions and the cash after a fill: {}", e);
+                    }
+                }
+                for event in polled_events {
+                    let _ = events.send(event);
+                }
+            }
+        });
+
+        self.tasks.lock().unwrap().push(("order_polling".to_string(), task));
+    }
+
+    /// Orders, fills, P&L, cash, positions and request counts since the
+    /// wrapper was built, from the cached state. The orders and fills need
+    /// a journal, and are those of the wrapper strategy.
+    pub fn session_report(&self) -> crate::SessionReport {
+        let records = self.inner.journal.as_ref().and_then(|journal| {
+            match journal.query(None, self.started_at..chrono::DateTime::<chrono::Utc>::MAX_UTC) {
+                Ok(records) => Some(records.into_iter().filter(|record| record.strategy == self.inner.strategy).collect::<Vec<_>>()),
+                Err(e) => {
+                    log::error!("Failed to read the journal for the session report: {}", e);
+                    None
+                }
+            }
+        });
+        // The records of a partial fill are cumulative, the last one of
+        // each order replaces those before it
+        let fills = records.as_ref().map(|records| {
+            let mut fills: Vec<crate::ReportFill> = Vec::new();
+            for record in records.iter().filter(|record| matches!(record.outcome, crate::JournalOutcome::Filled | crate::JournalOutcome::PartiallyFilled)) {
+                let fill = crate::ReportFill {
+                    timestamp: record.timestamp,
+                    symbol: record.symbol.clone(),
+                    side: record.side,
+                    qty: record.qty,
+                    price: record.price,
+                    order_id: record.order_id.clone(),
+                };
+                match fills.iter_mut().find(|previous| previous.order_id.is_some() && previous.order_id == fill.order_id) {
+                    Some(previous) => *previous = fill,
+                    None => fills.push(fill),
+                }
+            }
+            fills
+        });
+
+        let realized_pnl = *self.inner.realized.lock().unwrap();
+
+        let positions = self.positions();
+        let unrealized_pnl = positions.iter()
+            .map(|(symbol, position)| {
+                let price = self.cached_price::<crate::Trade>(symbol, crate::PriceType::Trades)
+                    .map_or(position.price, |trade| trade.price);
+                (price - position.entry) * position.qty
+            })
+            .sum();
+
+        let mut changes: std::collections::BTreeMap<String, crate::PositionChange> = Default::default();
+        for (symbol, position) in self.initial_position.iter().flat_map(|initial| initial.iter()) {
+            changes.entry(symbol.clone()).or_default().start = position.qty;
+        }
+        for (symbol, position) in &positions {
+            changes.entry(symbol.clone()).or_default().end = position.qty;
+        }
+
+        let requests = match (self.inner.client.request_counts(), self.initial_counts) {
+            (Some(counts), Some(initial)) => Some(counts.since(&initial)),
+            (counts, _) => counts,
+        };
+
+        crate::SessionReport {
+            started_at: self.started_at,
+            ended_at: chrono::Utc::now(),
+            strategy: self.inner.strategy.clone(),
+            orders: records.as_deref().map(crate::OrderOutcomes::from_records),
+            fills,
+            realized_pnl,
+            unrealized_pnl,
+            starting_cash: self.initial_cash,
+            ending_cash: self.inner.position.cash.load(atomic::Ordering::Relaxed),
+            positions: changes,
+            requests,
+        }
+    }
+}
+
+
+impl<C: crate::AlpacaApi> Inner<C> {
+    fn journal_order(&self, order: &Value) {
+        let Some(journal) = &self.journal else { return };
+
+        match crate::JournalRecord::from_order(order, self.strategy.as_deref()) {
+            Some(record) => journal.record(record),
+            None => log::warn!("Order without symbol, side or status not journaled: {}", order),
+        }
+    }
+
+    fn journal_request(&self, order: &crate::OrderRequest, outcome: crate::JournalOutcome) {
+        if let Some(journal) = &self.journal {
+            journal.record(crate::JournalRecord::from_request(order, self.strategy.as_deref(), outcome));
+        }
+    }
+
+    /// `AlpacaWrapper::apply_prices`, also run by the background updates.
+    async fn apply_prices(&self, mut last_prices: HashMap<String, HashMap<crate::PriceType, Value>>) {
+        // Before the prices, which are already the adjusted ones
+        self.check_corporate_actions().await;
+
+        // The prices of a halted symbol are still the pre-halt ones
+        last_prices.retain(|symbol, _| self.assets.contains(symbol) && !self.is_halted(symbol));
+        for prices in last_prices.values_mut() {
+            prices.retain(|price_type, _| self.price_types.contains(price_type));
+        }
+
+        // Left out by the server, their previous prices are dropped too
+        let missing: HashSet<String> = self.assets.iter()
+            .filter(|asset| !last_prices.contains_key(*asset) && !self.is_halted(asset))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            let mut sorted: Vec<&str> = missing.iter().map(String::as_str).collect();
+            sorted.sort_unstable();
+            log::warn!("No prices for {}, treated as stale", sorted.join(", "));
+        }
+        *self.missing.write().unwrap() = missing;
+
+        for (symbol, prices) in &last_prices {
+            let bar = prices.get(&crate::PriceType::Bars)
+                .and_then(|bar| serde_json::from_value::<crate::Bar>(bar.clone()).ok());
+            if let Some(bar) = bar {
+                self.push_bar(symbol, bar);
+            }
+        }
+
+        // Take write lock only to update the final result
+        {
+            let mut prices_guard = self.last_prices.write().unwrap();
+            *prices_guard = last_prices;
+        }
+        let equity = equity_of(&self.last_prices, &self.position);
+        self.equity_curve.lock().unwrap().push(chrono::Utc::now(), equity);
+
+        self.update_session().await;
+        self.check_stops().await;
+
+        if let Some(pnl) = self.daily_pnl() {
+            let _ = self.pnl_events.send(pnl);
+        }
+    }
+
+    /// `apply_corporate_actions` at the first update of each New York
+    /// date, and once per `dividend_check_interval` while a dividend due
+    /// that day has not been credited yet.
+    async fn check_corporate_actions(&self) {
+        let today = crate::market_hours::new_york_date(chrono::Utc::now());
+        let first = *self.corporate_date.lock().unwrap() != Some(today);
+        if !first {
+            let checked = *self.dividends_checked.lock().unwrap();
+            if self.dividends_due.lock().unwrap().is_empty()
+                || checked.is_some_and(|at| at.elapsed() < self.dividend_check_interval)
+            {
+                return;
+            }
+        }
+
+        match self.apply_corporate_actions_on(today, first).await {
+            Ok(_) => *self.corporate_date.lock().unwrap() = Some(today),
+            Err(e) => log::error!("Failed to check the corporate actions: {}", e),
+        }
+    }
+
+    async fn apply_corporate_actions_on(
+        &self,
+        today: chrono::NaiveDate,
+        fetch_actions: bool,
+    ) -> Result<Vec<CorporateAdjustment>, crate::AlpacaError> {
+        let mut adjustments = Vec::new();
+
+        let symbols: Vec<String> = self.assets.iter().filter(|asset| !crate::utils::is_crypto(asset)).cloned().collect();
+        if fetch_actions && !symbols.is_empty() {
+            let params = crate::CorporateActionsParams {
+                symbols,
+                types: vec![
+                    crate::CorporateActionType::ForwardSplit,
+                    crate::CorporateActionType::ReverseSplit,
+                    crate::CorporateActionType::StockDividend,
+                    crate::CorporateActionType::CashDividend,
+                ],
+                start: Some(today),
+                end: Some(today),
+                ..Default::default()
+            };
+            let actions = self.client.get_corporate_actions(&params).await?;
+
+            let splits = actions.forward_splits.iter().chain(&actions.reverse_splits)
+                .filter(|split| split.ex_date == today && split.old_rate > 0.0)
+                .map(|split| (&split.id, &split.symbol, split.new_rate / split.old_rate));
+            let stock_dividends = actions.stock_dividends.iter()
+                .filter(|dividend| dividend.ex_date == today)
+                .map(|dividend| (&dividend.id, &dividend.symbol, 1.0 + dividend.rate));
+            for (id, symbol, ratio) in splits.chain(stock_dividends) {
+                if self.corporate_applied.lock().unwrap().insert(id.clone()) {
+                    adjustments.extend(self.apply_split(symbol, ratio, today));
+                }
+            }
+
+            let positions = self.position.positions.read().unwrap();
+            let mut due = self.dividends_due.lock().unwrap();
+            due.clear();
+            due.extend(actions.cash_dividends.iter()
+                .filter(|dividend| dividend.payable_date == Some(today) && positions.contains_key(&dividend.symbol))
+                .map(|dividend| dividend.symbol.clone()));
+        }
 
-                let parse_value = |key: &str| -> f64 {
-                    position[key]
-                        .as_str()
-                        .and_then(|s| s.parse::<f64>().ok())
-                        .unwrap_or(0.0)
+        if !self.dividends_due.lock().unwrap().is_empty() {
+            *self.dividends_checked.lock().unwrap() = Some(std::time::Instant::now());
+            let activities = self.client.get_account_activities(&["DIV"], Some(today)).await?;
+            for activity in activities.as_array().into_iter().flatten() {
+                let (Some(id), Some(symbol)) = (activity["id"].as_str(), activity["symbol"].as_str()) else {
+                    continue;
                 };
+                let Some(asset) = self.asset_of(symbol) else { continue };
+                let amount = crate::utils::account_number(activity, "net_amount")?;
+                if !self.corporate_applied.lock().unwrap().insert(id.to_string()) {
+                    continue;
+                }
+
+                {
+                    // Under the positions lock, see `store_balances`
+                    let _positions_guard = self.position.positions.write().unwrap();
+                    let cash = self.position.cash.load(atomic::Ordering::Relaxed);
+                    self.position.cash.store(cash + amount, atomic::Ordering::Relaxed);
+                }
+                self.dividends_due.lock().unwrap().remove(asset);
+                log::info!("Dividend of {} credited: {}", asset, amount);
+                adjustments.push(CorporateAdjustment::CashDividend { symbol: asset.to_string(), amount });
+            }
+        }
+
+        for adjustment in &adjustments {
+            let _ = self.corporate_events.send(adjustment.clone());
+        }
+        Ok(adjustments)
+    }
+
+    fn apply_split(&self, symbol: &str, ratio: f64, today: chrono::NaiveDate) -> Option<CorporateAdjustment> {
+        let symbol = self.asset_of(symbol)?;
+        let stops = self.stops.adjust(symbol, ratio);
+
+        let refreshed = *self.position.positions_refreshed.lock().unwrap();
+        let stale = refreshed.is_none_or(|at| crate::market_hours::new_york_date(at) < today);
+        let mut positions = self.position.positions.write().unwrap();
+        let (qty, entry) = match positions.get_mut(symbol).filter(|_| stale) {
+            Some(position) => {
+                let before = (position.qty, position.entry);
+                position.qty *= ratio;
+                position.entry /= ratio;
+                position.price /= ratio;
+                ((before.0, position.qty), (before.1, position.entry))
+            },
+            None => {
+                let (qty, entry) = positions.get(symbol).map_or((0.0, 0.0), |position| (position.qty, position.entry));
+                ((qty, qty), (entry, entry))
+            },
+        };
+
+        log::info!("{} split {}:1, {} shares at {} now {} at {}", symbol, ratio, qty.0, entry.0, qty.1, entry.1);
+        Some(CorporateAdjustment::Split { symbol: symbol.to_string(), ratio, qty, entry, stops })
+    }
+
+    /// Takes the baseline at the first update after the open and drops it
+    /// after the close, so the overnight moves never count for the day.
+    /// The clock is only asked again once its next open or close passed.
+    async fn update_session(&self) {
+        let recheck = self.session.lock().unwrap().recheck;
+        if recheck.is_some_and(|recheck| chrono::Utc::now() < recheck) {
+            return;
+        }
+
+        let clock = match self.client.get_clock().await {
+            Ok(clock) => clock,
+            Err(e) => {
+                log::error!("Failed to get the clock: {}", e);
+                return;
+            }
+        };
+
+        let mut session = self.session.lock().unwrap();
+        if !clock.is_open {
+            if session.close.is_some() {
+                log::info!("Market closed, daily P&L reset");
+                *session = Session::default();
+            }
+            session.recheck = Some(clock.next_open);
+            return;
+        }
+
+        if session.close != Some(clock.next_close) {
+            *session = Session {
+                close: Some(clock.next_close),
+                baseline: self.equity(),
+                realized: 0.0,
+                recheck: None,
+            };
+        }
+        session.recheck = Some(clock.next_close);
+    }
+
+    fn daily_pnl(&self) -> Option<DailyPnl> {
+        let session = self.session.lock().unwrap();
+        session.close?;
+
+        let total = self.equity() - session.baseline;
+        Some(DailyPnl { realized: session.realized, unrealized: total - session.realized })
+    }
 
-                Some((
-                    symbol,
-                    crate::utils::Position {
-                        qty: parse_value("qty_available"),
-                        value: parse_value("market_value"),
-                        entry: parse_value("avg_entry_price"),
-                        price: parse_value("current_price"),
-                    },
-                ))
-            }).collect();
-
-        // Update the shared positions with a write lock
-        match self.position.positions.write() {
-            Ok(mut positions_guard) => {
-                *positions_guard = new_positions;
+    /// Adds the gain of a filled order closing the cached position, a sell
+    /// of a long or a buy covering a short, to the realized P&L against its
+    /// entry price. The orders still open when submitted are left to the
+    /// order polling, which realizes them as they fill.
+    fn record_fill(&self, order: &Value) {
+        if order["status"] != "filled" {
+            return;
+        }
+
+        let parse = |key: &str| order[key].as_str().and_then(|value| value.parse::<f64>().ok());
+        if let (Some(qty), Some(price)) = (parse("filled_qty"), parse("filled_avg_price")) {
+            realize_fill(&self.session, &self.realized, &self.position, &self.assets, order, qty, price);
+        }
+    }
+
+    fn is_halted(&self, symbol: &str) -> bool {
+        self.halted.read().unwrap().contains(symbol)
+    }
+
+    /// Fires the stops crossed by the fresh prices. Stale prices never
+    /// fire, and the stops of a symbol sell its position at most once.
+    async fn check_stops(&self) {
+        let stopped: Vec<String> = self.stops.active().into_iter().map(|stop| stop.symbol).collect();
+
+        for symbol in self.assets.iter().filter(|asset| stopped.contains(asset)) {
+            let price = [
+                self.fresh_price(symbol, crate::PriceType::Trades, "p"),
+                self.fresh_price(symbol, crate::PriceType::Quotes, "bp"),
+            ].into_iter().flatten().reduce(f64::min);
+
+            let Some(price) = price else { continue };
+            let crossed = self.stops.take_crossed(symbol, price);
+
+            let order = match crossed.iter().any(|stop| stop.mode == crate::StopMode::SellMarket) {
+                true => self.sell_position(symbol).await,
+                false => None,
+            };
+
+            for stop in crossed {
+                log::warn!("Stop loss {} of {} at {} crossed at {}", stop.id, symbol, stop.stop_price, price);
+                let order = order.clone().filter(|_| stop.mode == crate::StopMode::SellMarket);
+                let _ = self.stop_events.send(crate::StopEvent { stop, price, order });
+            }
+        }
+    }
+
+    async fn sell_position(&self, ticker: &str) -> Option<Value> {
+        let qty = self.position.positions.read().unwrap()
+            .get(ticker)
+            .map_or(0.0, |position| position.qty);
+
+        if qty <= 0.0 {
+            log::warn!("Stop loss of {} crossed without long position", ticker);
+            return None;
+        }
+        self.place_market_order(ticker, qty, crate::OrderSide::Sell).await
+    }
+
+    fn push_bar(&self, symbol: &str, bar: crate::Bar) {
+        push_bar_into(&mut self.bars.write().unwrap(), symbol, bar);
+    }
+
+    /// Asset of the wrapper traded as `symbol` in orders or positions.
+    fn asset_of(&self, symbol: &str) -> Option<&str> {
+        asset_in(&self.assets, symbol)
+    }
+
+    /// `field` of the last `price_type` of `ticker` when younger than
+    /// `max_price_age`.
+    fn fresh_price(&self, ticker: &str, price_type: crate::PriceType, field: &str) -> Option<f64> {
+        let prices_guard = self.last_prices.read().unwrap();
+        let price = prices_guard.get(ticker)?.get(&price_type)?;
+
+        let timestamp = chrono::DateTime::parse_from_rfc3339(price["t"].as_str()?).ok()?;
+        let age = (chrono::Utc::now() - timestamp.to_utc()).to_std().unwrap_or_default();
+        if age > self.max_price_age {
+            log::warn!("{:?} of {} is {:?} old", price_type, ticker, age);
+            return None;
+        }
+
+        price[field].as_f64().filter(|price| *price > 0.0)
+    }
+
+    fn equity(&self) -> f64 {
+        equity_of(&self.last_prices, &self.position)
+    }
+
+    async fn place_market_order(&self, ticker: &str, qty: f64, side: crate::OrderSide) -> Option<Value> {
+        let mut order = crate::OrderRequest::market(ticker, qty, side);
+        // Crypto orders only take gtc or ioc
+        if crate::utils::is_crypto(ticker) {
+            order = order.time_in_force(crate::TimeInForce::Gtc);
+        }
+        self.journal_request(&order, crate::JournalOutcome::Submitted);
+
+        match self.client.submit_order(&order).await {
+            Ok(order) => {
+                self.journal_order(&order);
+                self.record_fill(&order);
+                self.track_order(&order);
+                Some(order)
             },
             Err(e) => {
-                log::error!("Failed to acquire write lock for positions: {}", e);
+                self.journal_request(&order, crate::JournalOutcome::Rejected);
+                log::error!("Failed to submit {:?} order for {}: {}", side, ticker, e);
+                None
             }
         }
     }
 
-    pub async fn update_cash_async(&self) {
-        let cash = self.client
-            .get_account_info()
-            .await
-            .expect("Couldn't get account info")
-            .get("cash")
-            .expect("Couldn't get cash from account info")
-            .as_str()
-            .unwrap_or("0")
-            .parse::<f64>()
-            .unwrap_or(0.0);
-
-        self.position.cash.store(cash, atomic::Ordering::Relaxed);
-    }
-
-    // pub fn manage_buy_signal_async(&self, ticker: &str) -> Option<Value> {
-    //     log::info!("Manage buy signal");
-
-    //     // Get seller price
-    //     let seller_price = {
-    //         let prices_guard = self.last_prices.read().unwrap();
-    //         prices_guard
-    //             .get(ticker)
-    //             .and_then(|asset_prices| asset_prices.get("quotes"))
-    //             .and_then(|quotes| quotes["ap"].as_f64())
-    //             .unwrap_or(0.0)
-    //     };
-
-    //     let cash = self.position.cash.load(atomic::Ordering::Relaxed);
-    //     let qty = (cash / seller_price).floor() as i64;
-
-    //     // Only buy if we have enough cash
-    //     if qty > 0 {
-    //         return Some(self.client.place_order_async(ticker, qty as i64, "buy", None, None).await);
-    //     }
-
-    //     None
-    // }
-
-    // pub fn manage_buy_signal(&self, ticker: &str) -> Option<Value> {
-    //     self.runtime.block_on(self.manage_buy_signal_async(ticker))
-    // }
-
-    // pub async fn manage_sell_signal_async(&self, ticker: &str) -> Option<Value> {
-    //     log::info!("Manage sell signal");
-
-    //     // Get position information
-    //     let (qty, entry_price) = {
-    //         let positions_guard = self.positions.read().unwrap();
-    //         if let Some(position) = positions_guard.get(ticker) {
-    //             (position.qty, position.entry)
-    //         } else {
-    //             (0.0, 0.0)
-    //         }
-    //     };
-
-    //     // Get buyer price
-    //     let buyer_price = {
-    //         let prices_guard = self.last_prices.read().unwrap();
-    //         prices_guard
-    //             .get(ticker)
-    //             .and_then(|asset_prices| asset_prices.get("quotes"))
-    //             .and_then(|quotes| quotes["bp"].as_f64())
-    //             .unwrap_or(0.0)
-    //     };
-
-    //     // Only place the order if we hold some and bought them cheaper than current price
-    //     if qty > 0.0 && buyer_price > entry_price {
-    //         return Some(self.client.place_order_async(ticker, qty as i64, "sell", None, None).await);
-    //     }
-
-    //     None
-    // }
-
-    // pub fn manage_sell_signal(&self, ticker: &str) -> Option<Value> {
-    //     self.runtime.block_on(self.manage_sell_signal_async(ticker))
-    // }
-
-    // Add this method to spawn background tasks for periodic updates
-    // pub fn start_background_updates(&self, update_interval_ms: u64) {
-    //     let last_prices = self.last_prices.clone();
-    //     let positions = self.positions.clone();
-    //     let cash = self.cash.clone();
-    //     let client = self.client.clone();
-    //     let assets = self.assets.clone();
-
-    //     // Spawn a Tokio task for periodic updates
-    //     self.runtime.spawn(async move {
-    //         let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(update_interval_ms));
-
-    //         loop {
-    //             interval.tick().await;
-
-    //             // Update prices (most time-sensitive)
-    //             let items = vec!["trades", "quotes", "bars"];
-    //             let price_futures: Vec<_> = items.iter().map(|item| {
-    //                 let item_str = item.to_string();
-    //                 let client_clone = client.clone();
-    //                 let assets_clone = assets.clone();
-
-    //                 async move {
-    //                     let result = client_clone.get_prices(&assets_clone, &item_str).await;
-    //                     (item_str, result)
-    //                 }
-    //             }).collect();
-
-    //             let price_results = join_all(price_futures).await;
-
-    //             // Process price results
-    //             let mut results = HashMap::new();
-    //             for (item, result) in price_results {
-    //                 results.insert(item, result);
-    //             }
-
-    //             // Reshape results
-    //             let mut new_prices = HashMap::new();
-    //             for asset in &assets {
-    //                 let mut asset_prices = HashMap::new();
-    //                 for item in &items {
-    //                     if let Some(item_data) = results.get(*item) {
-    //                         if let Some(asset_data) = item_data.get(asset) {
-    //                             asset_prices.insert(item.to_string(), asset_data.clone());
-    //                         }
-    //                     }
-    //                 }
-    //                 new_prices.insert(asset.clone(), asset_prices);
-    //             }
-
-    //             // Update last_prices
-    //             {
-    //                 let mut prices_guard = last_prices.write().unwrap();
-    //                 *prices_guard = new_prices;
-    //             }
-
-    //             // Update positions (less frequently if desired)
-    //             let positions_result = client.get_positions_async().await;
-    //             let mut new_positions = HashMap::new();
-    //             for position in positions_result {
-    //                 let symbol = position["symbol"].as_str().unwrap_or_default().to_string();
-
-    //                 if assets.contains(&symbol) {
-    //                     new_positions.insert(symbol, Position {
-    //                         qty: position["qty_available"].as_str().unwrap_or("0.0").parse::<f64>().unwrap_or(0.0),
-    //                         value: position["market_value"].as_str().unwrap_or("0.0").parse::<f64>().unwrap_or(0.0),
-    //                         entry: position["avg_entry_price"].as_str().unwrap_or("0.0").parse::<f64>().unwrap_or(0.0),
-    //                         price: position["current_price"].as_str().unwrap_or("0.0").parse::<f64>().unwrap_or(0.0),
-    //                     });
-    //                 }
-    //             }
-
-    //             // Update positions
-    //             {
-    //                 let mut positions_guard = positions.write().unwrap();
-    //                 *positions_guard = new_positions;
-    //             }
-
-    //             // Update cash
-    //             let account = client.get_account_async().await;
-    //             let new_cash = account["cash"].as_str().unwrap_or("0").parse::<f64>().unwrap_or(0.0);
-
-    //             {
-    //                 let mut cash_guard = cash.lock().unwrap();
-    //                 *cash_guard = new_cash;
-    //             }
-    //         }
-    //     });
-    // }
+    fn track_order(&self, order: &Value) {
+        let Some(id) = order["id"].as_str() else { return };
+        if crate::OrderStatus::from(order["status"].as_str().unwrap_or_default()).is_terminal() {
+            return;
+        }
+
+        self.open_orders.lock().unwrap().insert(id.to_string(), order.clone());
+        self.order_tracked.notify_one();
+    }
 }
@@ -0,0 +1,149 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Trait over the client operations used by `AlpacaWrapper`, so that it
+//! can run against `MockAlpaca` or any other fake.
+
+use std::collections::HashMap;
+use std::future::Future;
+use serde_json::Value;
+
+use chrono::NaiveDate;
+
+use crate::{AlpacaClient, AlpacaError, Asset, Clock, CorporateActions, CorporateActionsParams, OrderRequest, PriceType, RequestCounts};
+
+pub trait AlpacaApi: Send + Sync {
+    fn get_account(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send;
+
+    /// `get_account` also updating the account cached by the
+    /// implementation, if any.
+    fn refresh_account(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        self.get_account()
+    }
+
+    fn get_positions(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send;
+
+    fn get_prices_multi(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> impl Future<Output = Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError>> + Send;
+
+    /// `get_prices_multi` in a single request per chunk where the
+    /// implementation has snapshots, else `get_prices_multi` itself.
+    fn get_snapshot_prices(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> impl Future<Output = Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError>> + Send {
+        self.get_prices_multi(assets, types)
+    }
+
+    fn submit_order(&self, order: &OrderRequest) -> impl Future<Output = Result<Value, AlpacaError>> + Send;
+
+    fn get_order_info(&self, id: &str, nested: bool) -> impl Future<Output = Result<Value, AlpacaError>> + Send;
+
+    fn get_asset(&self, symbol: &str) -> impl Future<Output = Result<Asset, AlpacaError>> + Send;
+
+    fn get_clock(&self) -> impl Future<Output = Result<Clock, AlpacaError>> + Send;
+
+    /// `None` for the implementations not counting their requests.
+    fn request_counts(&self) -> Option<RequestCounts> {
+        None
+    }
+
+    /// No actions for the implementations without them.
+    fn get_corporate_actions(
+        &self,
+        _params: &CorporateActionsParams,
+    ) -> impl Future<Output = Result<CorporateActions, AlpacaError>> + Send {
+        std::future::ready(Ok(CorporateActions::default()))
+    }
+
+    /// An empty list for the implementations without activities.
+    fn get_account_activities(
+        &self,
+        _activity_types: &[&str],
+        _date: Option<NaiveDate>,
+    ) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        std::future::ready(Ok(Value::Array(Vec::new())))
+    }
+}
+
+impl AlpacaApi for AlpacaClient {
+    fn get_account(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        AlpacaClient::get_account(self)
+    }
+
+    async fn refresh_account(&self) -> Result<Value, AlpacaError> {
+        AlpacaClient::refresh_account(self).await.map(|account| account.as_ref().clone())
+    }
+
+    fn get_positions(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        AlpacaClient::get_positions(self)
+    }
+
+    fn get_prices_multi(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> impl Future<Output = Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError>> + Send {
+        AlpacaClient::get_prices_multi(self, assets, types)
+    }
+
+    fn get_snapshot_prices(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> impl Future<Output = Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError>> + Send {
+        AlpacaClient::get_snapshot_prices(self, assets, types)
+    }
+
+    fn submit_order(&self, order: &OrderRequest) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        AlpacaClient::submit_order(self, order)
+    }
+
+    fn get_order_info(&self, id: &str, nested: bool) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        AlpacaClient::get_order_info(self, id, nested)
+    }
+
+    fn get_asset(&self, symbol: &str) -> impl Future<Output = Result<Asset, AlpacaError>> + Send {
+        AlpacaClient::get_asset(self, symbol)
+    }
+
+    fn get_clock(&self) -> impl Future<Output = Result<Clock, AlpacaError>> + Send {
+        AlpacaClient::get_clock(self)
+    }
+
+    fn request_counts(&self) -> Option<RequestCounts> {
+        Some(AlpacaClient::request_counts(self))
+    }
+
+    fn get_corporate_actions(
+        &self,
+        params: &CorporateActionsParams,
+    ) -> impl Future<Output = Result<CorporateActions, AlpacaError>> + Send {
+        AlpacaClient::get_corporate_actions(self, params)
+    }
+
+    fn get_account_activities(
+        &self,
+        activity_types: &[&str],
+        date: Option<NaiveDate>,
+    ) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        AlpacaClient::get_account_activities(self, activity_types, date)
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Record/replay of the HTTP interactions, VCR style.
+//!
+//! In `Record` mode every response the client receives is appended to the
+//! cassette, and `save` writes them as JSON. In `Replay` mode the responses
+//! come from the cassette and the network is never used. The credentials
+//! never reach the file: headers are not recorded, the hosts are stored as
+//! `trading` or `data` and the bodies and queries are redacted.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use bytes::Bytes;
+use reqwest::{header, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::AlpacaError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// One request with the response it got.
+///
+/// Bodies are stored as text, binary ones (logos) don't survive the trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub host: String,
+    /// Templated path, e.g. `/v2/orders/{id}`.
+    pub endpoint: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+/// What identifies a request when replaying.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestKey {
+    method: String,
+    host: String,
+    path: String,
+    query: Vec<(String, String)>,
+}
+
+impl RequestKey {
+    pub(crate) fn new(method: &Method, host: &str, path: &str, query: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            host: host.to_string(),
+            path: path.to_string(),
+            query: query.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    played: Vec<bool>,
+}
+
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    ignored_params: Vec<String>,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Empty cassette to be written to `path` by `save`.
+    pub fn record(path: impl AsRef<Path>) -> Self {
+        Self {
+            mode: CassetteMode::Record,
+            path: path.as_ref().to_path_buf(),
+            ignored_params: Vec::new(),
+            tape: Mutex::default(),
+        }
+    }
+
+    /// Loads the interactions previously saved to `path`.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, AlpacaError> {
+        let interactions: Vec<Interaction> = serde_json::from_slice(&std::fs::read(path.as_ref())?)?;
+        let played = vec![false; interactions.len()];
+
+        Ok(Self {
+            mode: CassetteMode::Replay,
+            path: path.as_ref().to_path_buf(),
+            ignored_params: Vec::new(),
+            tape: Mutex::new(Tape { interactions, played }),
+        })
+    }
+
+    /// Ignores the query parameter `key` when matching the requests, for
+    /// the volatile ones like `start` or `end` timestamps.
+    pub fn ignore_query_param(mut self, key: &str) -> Self {
+        self.ignored_params.push(key.to_string());
+        self
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.tape.lock().unwrap().interactions.clone()
+    }
+
+    /// Writes the recorded interactions to the cassette file.
+    pub fn save(&self) -> Result<(), AlpacaError> {
+        let json = serde_json::to_vec_pretty(&self.tape.lock().unwrap().interactions)?;
+        Ok(std::fs::write(&self.path, json)?)
+    }
+
+    fn matches(&self, interaction: &Interaction, key: &RequestKey) -> bool {
+        let relevant = |query: &[(String, String)]| -> Vec<(String, String)> {
+            query.iter().filter(|(name, _)| !self.ignored_params.contains(name)).cloned().collect()
+        };
+
+        interaction.method == key.method
+            && interaction.host == key.host
+            && interaction.path == key.path
+            && relevant(&interaction.query) == relevant(&key.query)
+    }
+
+    /// Recorded response for `key`. Identical requests get the recorded
+    /// responses in order, and the last one once they run out.
+    pub(crate) fn play(&self, key: &RequestKey) -> Result<reqwest::Response, AlpacaError> {
+        let mut tape = self.tape.lock().unwrap();
+
+        let candidates: Vec<usize> = tape.interactions.iter()
+            .enumerate()
+            .filter(|(_, interaction)| self.matches(interaction, key))
+            .map(|(index, _)| index)
+            .collect();
+
+        let Some(index) = candidates.iter().copied().find(|index| !tape.played[*index]).or(candidates.last().copied()) else {
+            return Err(AlpacaError::UnrecordedRequest {
+                method: key.method.clone(),
+                endpoint: key.path.clone(),
+            });
+        };
+        tape.played[index] = true;
+
+        let interaction = &tape.interactions[index];
+        let mut response = http::Response::builder()
+            .status(interaction.status);
+        if let Some(content_type) = &interaction.content_type {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+
+        let response = response.body(Bytes::from(interaction.body.clone()))
+            .map_err(|e| AlpacaError::Other(e.to_string()))?;
+        Ok(reqwest::Response::from(response))
+    }
+
+    /// Stores a copy of `response` and hands back an equivalent one.
+    pub(crate) async fn record_response(
+        &self,
+        key: RequestKey,
+        label: &str,
+        response: reqwest::Response,
+        redact: impl Fn(&str) -> String,
+    ) -> Result<reqwest::Response, AlpacaError> {
+        let status = response.status();
+        let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+        let bytes = response.bytes().await?;
+
+        let interaction = Interaction {
+            method: key.method,
+            endpoint: label.to_string(),
+            host: key.host,
+            path: key.path,
+            query: key.query.into_iter().map(|(name, value)| (name, redact(&value))).collect(),
+            status: status.as_u16(),
+            content_type: content_type.as_ref().and_then(|value| value.to_str().ok()).map(str::to_string),
+            body: redact(&String::from_utf8_lossy(&bytes)),
+        };
+
+        let mut tape = self.tape.lock().unwrap();
+        tape.interactions.push(interaction);
+        tape.played.push(false);
+
+        let mut rebuilt = http::Response::builder().status(status);
+        if let Some(content_type) = content_type {
+            rebuilt = rebuilt.header(header::CONTENT_TYPE, content_type);
+        }
+        let rebuilt = rebuilt.body(bytes).map_err(|e| AlpacaError::Other(e.to_string()))?;
+        Ok(reqwest::Response::from(rebuilt))
+    }
+}
+
@@ -0,0 +1,159 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Circuit breaker failing fast while the API keeps erroring.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+
+use crate::AlpacaError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally.
+    Closed,
+    /// Requests fail fast with `AlpacaError::CircuitOpen` until `retry_at`.
+    Open { retry_at: Instant },
+    /// The cooldown is over and a single probe request decides the state.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: CircuitState,
+    failures: u32,
+    probing: bool,
+}
+
+/// Opens after `threshold` consecutive failures and lets a probe through
+/// `cooldown` later. Only the server side failures count: 5xx answers,
+/// timeouts and connection errors. The 4xx answers are the caller's fault
+/// and reset the count like any other response.
+///
+/// The time is passed explicitly to the `_at` methods so the transitions
+/// can be tested without sleeping.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                failures: 0,
+                probing: false,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state_at(Instant::now())
+    }
+
+    pub(crate) fn state_at(&self, now: Instant) -> CircuitState {
+        match self.inner.lock().unwrap().state {
+            CircuitState::Open { retry_at } if now >= retry_at => CircuitState::HalfOpen,
+            state => state,
+        }
+    }
+
+    pub fn counts_as_failure(error: &AlpacaError) -> bool {
+        match error {
+            AlpacaError::Timeout { .. } | AlpacaError::ConnectionError { .. } => true,
+            AlpacaError::HttpError { status, .. } | AlpacaError::ApiError { status, .. } => {
+                status.is_server_error()
+            },
+            _ => false,
+        }
+    }
+
+    /// Admits a request, or refuses it while the circuit is open. Once the
+    /// cooldown is over only one probe is admitted until it completes, or
+    /// until its `Permit` is dropped without an outcome.
+    pub(crate) fn acquire_at(&self, now: Instant) -> Result<Permit<'_>, AlpacaError> {
+        let mut inner = self.inner.lock().unwrap();
+
+        let probe = match inner.state {
+            CircuitState::Closed => false,
+            CircuitState::Open { retry_at } if now < retry_at => {
+                return Err(AlpacaError::CircuitOpen { retry_at });
+            },
+            CircuitState::Open { .. } | CircuitState::HalfOpen if !inner.probing => {
+                inner.state = CircuitState::HalfOpen;
+                inner.probing = true;
+                true
+            },
+            _ => return Err(AlpacaError::CircuitOpen { retry_at: now }),
+        };
+
+        Ok(Permit { breaker: self, probe })
+    }
+
+    /// Accounts the outcome of an admitted request.
+    pub(crate) fn record_at<T>(&self, result: &Result<T, AlpacaError>, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        let failed = result.as_ref().err().is_some_and(Self::counts_as_failure);
+        let probe = std::mem::take(&mut inner.probing);
+
+        if !failed {
+            if probe {
+                info!("Circuit breaker probe succeeded, closing the circuit");
+            }
+            inner.state = CircuitState::Closed;
+            inner.failures = 0;
+            return;
+        }
+
+        inner.failures += 1;
+        if probe || inner.failures >= self.threshold {
+            let retry_at = now + self.cooldown;
+            warn!("Circuit breaker open after {} consecutive failures", inner.failures);
+            inner.state = CircuitState::Open { retry_at };
+        }
+    }
+}
+
+/// Admission of a request by `CircuitBreaker::acquire_at`. Dropping the
+/// permit of a probe without `record_at`, e.g. with its request future
+/// cancelled, lets the next request probe instead.
+#[derive(Debug)]
+pub(crate) struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+impl Permit<'_> {
+    /// Accounts the outcome of the admitted request.
+    pub(crate) fn record_at<T>(mut self, result: &Result<T, AlpacaError>, now: Instant) {
+        self.probe = false;
+        self.breaker.record_at(result, now);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.inner.lock().unwrap().probing = false;
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Coalescing of identical in-flight requests.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use serde_json::Value;
+
+use crate::AlpacaError;
+
+type SharedResponse = Shared<BoxFuture<'static, Result<Value, Arc<AlpacaError>>>>;
+
+/// Requests in flight keyed by method and full URL. Later callers of an
+/// identical request await the first one instead of sending their own.
+#[derive(Default)]
+pub(crate) struct InFlight {
+    requests: Mutex<HashMap<String, SharedResponse>>,
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InFlight")
+            .field("requests", &self.requests.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl InFlight {
+    /// Awaits the request in flight under `key`, or starts `request` when
+    /// there is none. The entry is dropped once the response resolves.
+    pub(crate) async fn run<F>(&self, key: String, request: impl FnOnce() -> F) -> Result<Value, AlpacaError>
+    where
+        F: Future<Output = Result<Value, AlpacaError>> + Send + 'static,
+    {
+        let shared = {
+            let mut requests = self.requests.lock().unwrap();
+            requests.entry(key.clone())
+                .or_insert_with(|| request().map(|result| result.map_err(Arc::new)).boxed().shared())
+                .clone()
+        };
+
+        let result = shared.clone().await;
+
+        let mut requests = self.requests.lock().unwrap();
+        if requests.get(&key).is_some_and(|current| current.ptr_eq(&shared)) {
+            requests.remove(&key);
+        }
+
+        result.map_err(|e| Arc::try_unwrap(e).unwrap_or_else(|e| AlpacaError::duplicate(&e)))
+    }
+}
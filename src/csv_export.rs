@@ -0,0 +1,177 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! CSV rendering of the market data records.
+//!
+//! Columns keep the single letter keys of the data API in a fixed order,
+//! timestamps are RFC3339 in UTC and a missing optional value is an empty
+//! field. Fields holding the delimiter, a quote or a line break are quoted.
+
+use chrono::SecondsFormat;
+
+use crate::{Bar, Quote, Trade};
+
+/// Layout of the CSV output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: ',', header: true }
+    }
+}
+
+impl CsvOptions {
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+}
+
+/// Record with a fixed CSV layout.
+pub trait CsvRecord {
+    /// Column names, in the order of `fields`.
+    fn columns() -> &'static [&'static str];
+
+    fn fields(&self) -> Vec<String>;
+
+    /// The record as a CSV line, after the header line if asked for.
+    fn to_csv(&self, options: &CsvOptions) -> String where Self: Sized {
+        to_csv(std::slice::from_ref(self), options)
+    }
+}
+
+/// `records` as CSV lines, after the header line if asked for.
+pub fn to_csv<R: CsvRecord>(records: &[R], options: &CsvOptions) -> String {
+    let mut out = String::new();
+    if options.header {
+        push_row(&mut out, R::columns().iter().copied(), options.delimiter);
+    }
+    for record in records {
+        push_row(&mut out, record.fields().iter().map(String::as_str), options.delimiter);
+    }
+    out
+}
+
+/// Appends `fields` to `out` as one line.
+pub(crate) fn push_row<'a>(out: &mut String, fields: impl Iterator<Item = &'a str>, delimiter: char) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        if field.contains([delimiter, '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push('\n');
+}
+
+/// Rows of `text` as written by `push_row`, the quoted fields keeping
+/// their delimiters, quotes and line breaks.
+pub(crate) fn parse_rows(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                row.last_mut().unwrap().push('"');
+            },
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => row.push(String::new()),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {},
+            '\n' if !quoted => rows.push(std::mem::replace(&mut row, vec![String::new()])),
+            c => row.last_mut().unwrap().push(c),
+        }
+    }
+    // A last row without its line break
+    if row.len() > 1 || !row[0].is_empty() {
+        rows.push(row);
+    }
+
+    rows
+}
+
+fn timestamp(timestamp: &chrono::DateTime<chrono::Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl CsvRecord for Bar {
+    fn columns() -> &'static [&'static str] {
+        &["t", "o", "h", "l", "c", "v", "n", "vw"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.timestamp),
+            self.open.to_string(),
+            self.high.to_string(),
+            self.low.to_string(),
+            self.close.to_string(),
+            self.volume.to_string(),
+            self.trade_count.to_string(),
+            self.vwap.map(|vwap| vwap.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+/// The conditions are joined with `|` in the `c` column.
+impl CsvRecord for Trade {
+    fn columns() -> &'static [&'static str] {
+        &["t", "x", "p", "s", "c"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.timestamp),
+            self.exchange.clone(),
+            self.price.to_string(),
+            self.size.to_string(),
+            self.conditions.join("|"),
+        ]
+    }
+}
+
+impl CsvRecord for Quote {
+    fn columns() -> &'static [&'static str] {
+        &["t", "bp", "bs", "ap", "as"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            timestamp(&self.timestamp),
+            self.bid_price.to_string(),
+            self.bid_size.to_string(),
+            self.ask_price.to_string(),
+            self.ask_size.to_string(),
+        ]
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Trade journal appending every order event to a CSV file.
+//!
+//! The records go through a channel to a writer thread so journaling never
+//! blocks on the disk, and dropping the `Journal` flushes what is pending.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{AlpacaError, OrderRequest, OrderSide};
+
+const HEADER: &str = "timestamp,symbol,side,qty,price,order_id,client_order_id,strategy,outcome";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOutcome {
+    /// About to be sent.
+    Submitted,
+    /// Acknowledged by the server, not filled yet.
+    Accepted,
+    /// Filled in part, the rest still open or canceled.
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalRecord {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub qty: f64,
+    /// Average fill price, or the limit price while nothing is filled.
+    pub price: Option<f64>,
+    pub order_id: Option<String>,
+    pub client_order_id: Option<String>,
+    pub strategy: Option<String>,
+    pub outcome: JournalOutcome,
+}
+
+impl JournalRecord {
+    /// Record of `order` before it is sent, or after sending it failed.
+    pub fn from_request(order: &OrderRequest, strategy: Option<&str>, outcome: JournalOutcome) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            symbol: order.symbol.clone(),
+            side: order.side,
+            qty: order.qty.as_f64(),
+            price: order.limit_price,
+            order_id: None,
+            client_order_id: order.client_order_id.clone(),
+            strategy: strategy.map(str::to_string),
+            outcome,
+        }
+    }
+
+    /// Record of an order as returned by the API, the outcome following
+    /// its `status`. An order with some quantity filled records that
+    /// quantity at its average price, even once the rest is canceled.
+    pub fn from_order(order: &Value, strategy: Option<&str>) -> Option<Self> {
+        let parse = |key: &str| order[key].as_str().and_then(|value| value.parse::<f64>().ok());
+        let partially_filled = parse("filled_qty").is_some_and(|qty| qty > 0.0);
+        let outcome = match order["status"].as_str()? {
+            "filled" => JournalOutcome::Filled,
+            "rejected" => JournalOutcome::Rejected,
+            _ if partially_filled => JournalOutcome::PartiallyFilled,
+            "canceled" | "expired" => JournalOutcome::Canceled,
+            _ => JournalOutcome::Accepted,
+        };
+        let filled = matches!(outcome, JournalOutcome::Filled | JournalOutcome::PartiallyFilled);
+
+        Some(Self {
+            timestamp: Utc::now(),
+            symbol: order["symbol"].as_str()?.to_string(),
+            side: serde_json::from_value(order["side"].clone()).ok()?,
+            qty: if filled { parse("filled_qty") } else { parse("qty") }.unwrap_or(0.0),
+            price: if filled { parse("filled_avg_price") } else { parse("limit_price") },
+            order_id: order["id"].as_str().map(str::to_string),
+            client_order_id: order["client_order_id"].as_str().map(str::to_string),
+            strategy: strategy.map(str::to_string),
+            outcome,
+        })
+    }
+
+    /// The record as a CSV row, line break included.
+    fn to_csv(&self) -> String {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        let fields = [
+            self.timestamp.to_rfc3339(),
+            self.symbol.clone(),
+            tag(&self.side),
+            self.qty.to_string(),
+            self.price.map(|price| price.to_string()).unwrap_or_default(),
+            optional(&self.order_id),
+            optional(&self.client_order_id),
+            optional(&self.strategy),
+            tag(&self.outcome),
+        ];
+
+        let mut row = String::new();
+        crate::csv_export::push_row(&mut row, fields.iter().map(String::as_str), ',');
+        row
+    }
+
+    fn from_csv(fields: &[String]) -> Result<Self, AlpacaError> {
+        let invalid = || AlpacaError::Other(format!("Invalid journal row: {:?}", fields));
+        let [timestamp, symbol, side, qty, price, order_id, client_order_id, strategy, outcome] = fields else {
+            return Err(invalid());
+        };
+        let optional = |value: &String| (!value.is_empty()).then(|| value.clone());
+        let untag = |value: &str| Value::String(value.to_string());
+
+        Ok(Self {
+            timestamp: DateTime::parse_from_rfc3339(timestamp).map_err(|_| invalid())?.to_utc(),
+            symbol: symbol.clone(),
+            side: serde_json::from_value(untag(side))?,
+            qty: qty.parse().map_err(|_| invalid())?,
+            price: optional(price).map(|price| price.parse()).transpose().map_err(|_| invalid())?,
+            order_id: optional(order_id),
+            client_order_id: optional(client_order_id),
+            strategy: optional(strategy),
+            outcome: serde_json::from_value(untag(outcome))?,
+        })
+    }
+}
+
+/// Serde name of a unit variant, e.g. `buy`.
+fn tag<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value).ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+enum Message {
+    Record(JournalRecord),
+    Flush(mpsc::Sender<()>),
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    sender: Mutex<Option<mpsc::Sender<Message>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Journal {
+    /// Appends to the CSV file at `path`, writing the header when it is
+    /// new or empty.
+    pub fn csv(path: impl AsRef<Path>) -> Result<Self, AlpacaError> {
+        let file = OpenOptions::new().create(true).append(true).open(path.as_ref())?;
+        let mut file = BufWriter::new(file);
+        if file.get_ref().metadata()?.len() == 0 {
+            writeln!(file, "{}", HEADER)?;
+            file.flush()?;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::spawn(move || Self::write_loop(file, receiver));
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            sender: Mutex::new(Some(sender)),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    fn write_loop(mut file: BufWriter<File>, receiver: mpsc::Receiver<Message>) {
+        // Ends when the sender is dropped, after draining the channel
+        for message in receiver {
+            let result = match message {
+                Message::Record(record) => file.write_all(record.to_csv().as_bytes()),
+                Message::Flush(done) => file.flush().map(|_| { let _ = done.send(()); }),
+            };
+            if let Err(e) = result {
+                log::error!("Failed to write the journal: {}", e);
+            }
+        }
+
+        if let Err(e) = file.flush() {
+            log::error!("Failed to flush the journal: {}", e);
+        }
+    }
+
+    /// Queues `record` for writing, never blocking.
+    pub fn record(&self, record: JournalRecord) {
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(Message::Record(record));
+        }
+    }
+
+    /// Waits until the records queued so far are on disk.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            if sender.send(Message::Flush(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+    }
+
+    /// Records of `symbol` (any with `None`) timestamped in `range`, in
+    /// the order they were written.
+    pub fn query(&self, symbol: Option<&str>, range: Range<DateTime<Utc>>) -> Result<Vec<JournalRecord>, AlpacaError> {
+        self.flush();
+
+        let mut records = Vec::new();
+        for row in crate::csv_export::parse_rows(&std::fs::read_to_string(&self.path)?, ',').iter().skip(1) {
+            let record = JournalRecord::from_csv(row)?;
+            if range.contains(&record.timestamp) && symbol.is_none_or(|symbol| symbol == record.symbol) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+impl Drop for Journal {
+    /// Writes everything still queued before returning.
+    fn drop(&mut self) {
+        self.sender.lock().unwrap().take();
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            let _ = writer.join();
+        }
+    }
+}
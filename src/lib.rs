@@ -17,11 +17,92 @@
 mod utils;
 pub use utils::PriceType;
 pub use utils::AtomicF64;
+pub use utils::Position;
+pub use utils::strip_html;
+pub use utils::resample_bars;
+pub use utils::{vwap, vwap_by, RollingVwap, VwapAnchor};
+pub use utils::{is_crypto, position_symbol};
+
+mod models;
+pub use models::{Trade, LatestTrade, Quote, LatestQuote, Bar, PricesResult};
+pub use models::{TimeFrame, Sort, HistoricalOptions, DownloadFormat, DownloadSummary};
+pub use models::{HistoricalBarsParams, HistoricalBarsParamsBuilder, Adjustment, DataFeed};
+pub use models::{MarketType, Mover, Movers, OrderBook};
+pub use models::{CorporateActionType, CorporateActionsParams, CorporateActions};
+pub use models::{Split, CashDividend, StockDividend, StockMerger, CashMerger, SpinOff};
+pub use models::{NewsParams, NewsArticle, NewsPage};
+pub use models::{Clock, CalendarDay, DailyChange, DayTradeStatus, PdtDecision, PDT_EQUITY_THRESHOLD};
+pub use models::{Wallet, WalletTransfer, Asset, Watchlist};
+
+mod options;
+pub use options::{OptionGreeks, OptionSnapshot, OptionType, OccSymbol, parse_occ_symbol};
+
+mod csv_export;
+pub use csv_export::{CsvOptions, CsvRecord, to_csv};
+
+mod orders;
+pub use orders::{OrderSide, OrderType, OrderStatus, TimeInForce, Qty, OrderRequest, OptionLeg, PositionIntent};
+pub use orders::{ReplaceOrderRequest, ReplaceOrderBuilder};
+pub use orders::{ListOrdersParams, OrderClassRole, Order, CancelOutcome};
+
+mod trade_updates;
+pub use trade_updates::{TradeUpdate, TradeEventKind};
+
+mod stream;
+pub use stream::{StreamMessage, TradingStatus, TradingStatusCode, HaltReason, Luld, Subscriptions};
+
+mod retry;
+pub use retry::RetryPolicy;
+
+mod circuit;
+pub use circuit::{CircuitBreaker, CircuitState};
+
+mod metrics;
+pub use metrics::{Metrics, AtomicMetrics, EndpointStats, RequestCounts, OTHER_ENDPOINT};
+
+#[cfg(any(test, feature = "vcr"))]
+mod cassette;
+#[cfg(any(test, feature = "vcr"))]
+pub use cassette::{Cassette, CassetteMode, Interaction};
+
+mod coalesce;
+mod pages;
 
 mod alpaca_client;
-pub use alpaca_client::{AlpacaClient, AlpacaError};
+pub use alpaca_client::{AlpacaClient, AlpacaError, ApiHost, AuctionWindowCheck, BodyLogging, Environment, DEFAULT_BODY_LOG_LIMIT};
+pub use alpaca_client::{EndpointCategory, DEFAULT_TIMEOUT};
+
+mod api;
+pub use api::AlpacaApi;
+
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::{MockAlpaca, MockCall};
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+
+mod journal;
+pub use journal::{Journal, JournalRecord, JournalOutcome};
+
+mod session_report;
+pub use session_report::{SessionReport, OrderOutcomes, ReportFill, PositionChange};
+
+mod stops;
+pub use stops::{StopMode, StopLoss, StopEvent};
 
 mod alpaca_wrapper;
+pub use alpaca_wrapper::{AlpacaWrapper, CorporateAdjustment, DailyPnl, DuplicateKey, HaltEvent, PriceOptions, PriceSource, ShutdownReport, SuppressedSignal, BAR_HISTORY, DEFAULT_DIVIDEND_CHECK_INTERVAL, DEFAULT_EQUITY_CURVE_CAPACITY, DEFAULT_MAX_PRICE_AGE};
+
+mod multi_account;
+pub use multi_account::MultiAccountWrapper;
+
+mod market_hours;
+pub use market_hours::{MarketHours, auction_order_accepted};
+
+mod market_guard;
+pub use market_guard::{MarketGuard, MarketClosedMode, QueuedOrder, Submission, GuardEvent};
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,182 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Market hours aware order submission.
+//!
+//! `MarketGuard` wraps a client and consults `/v2/clock` before every
+//! order, either refusing it while the market is closed or holding it in
+//! memory until the next open.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use log::{info, warn};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::{AlpacaClient, AlpacaError, OrderRequest};
+
+/// Delay before asking the clock again after it failed to answer.
+const CLOCK_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketClosedMode {
+    /// Fail with `AlpacaError::MarketClosed`.
+    Reject,
+    /// Hold the order and submit it right after the next open.
+    Queue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedOrder {
+    pub id: u64,
+    pub order: OrderRequest,
+}
+
+#[derive(Debug)]
+pub enum Submission {
+    /// The market was open and the order went out, with the server response.
+    Submitted(Value),
+    /// The market was closed and the order waits under this queue id.
+    Queued(u64),
+}
+
+/// Emitted by the background task when a queued order finally goes out.
+#[derive(Debug, Clone)]
+pub enum GuardEvent {
+    Submitted { id: u64, order: OrderRequest, response: Value },
+    Failed { id: u64, order: OrderRequest, error: String },
+}
+
+#[derive(Debug, Default)]
+struct GuardState {
+    next_id: u64,
+    queue: Vec<QueuedOrder>,
+    running: bool,
+}
+
+#[derive(Debug)]
+pub struct MarketGuard {
+    client: Arc<AlpacaClient>,
+    mode: MarketClosedMode,
+    state: Arc<Mutex<GuardState>>,
+    events: broadcast::Sender<GuardEvent>,
+}
+
+impl MarketGuard {
+    pub fn new(client: Arc<AlpacaClient>, mode: MarketClosedMode) -> Self {
+        let (events, _) = broadcast::channel(64);
+
+        Self {
+            client,
+            mode,
+            state: Arc::new(Mutex::new(GuardState::default())),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GuardEvent> {
+        self.events.subscribe()
+    }
+
+    /// Submits `order` if the market is open, otherwise rejects or queues
+    /// it according to the mode.
+    ///
+    /// Queuing must happen inside a tokio runtime since it spawns the task
+    /// waiting for the open.
+    pub async fn submit(&self, order: &OrderRequest) -> Result<Submission, AlpacaError> {
+        let clock = self.client.get_clock().await?;
+
+        if clock.is_open {
+            return Ok(Submission::Submitted(self.client.submit_order(order).await?));
+        }
+
+        if self.mode == MarketClosedMode::Reject {
+            return Err(AlpacaError::MarketClosed { next_open: clock.next_open });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.queue.push(QueuedOrder { id, order: order.clone() });
+        info!("Market closed, {} order queued as {} until {}", order.symbol, id, clock.next_open);
+
+        if !state.running {
+            state.running = true;
+            tokio::spawn(Self::flush_at_open(
+                self.client.clone(),
+                self.state.clone(),
+                self.events.clone(),
+            ));
+        }
+
+        Ok(Submission::Queued(id))
+    }
+
+    /// Orders still waiting for the open.
+    pub fn queued(&self) -> Vec<QueuedOrder> {
+        self.state.lock().unwrap().queue.clone()
+    }
+
+    /// Drops a queued order before it is submitted. Returns false when the
+    /// id is unknown or the order already went out.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let before = state.queue.len();
+        state.queue.retain(|queued| queued.id != id);
+        state.queue.len() != before
+    }
+
+    async fn flush_at_open(
+        client: Arc<AlpacaClient>,
+        state: Arc<Mutex<GuardState>>,
+        events: broadcast::Sender<GuardEvent>,
+    ) {
+        loop {
+            match client.get_clock().await {
+                Ok(clock) if clock.is_open => break,
+                Ok(clock) => {
+                    let wait = (clock.next_open - clock.timestamp).to_std().unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                },
+                Err(e) => {
+                    warn!("Market guard failed to get the clock: {}", client.redact(&e));
+                    tokio::time::sleep(CLOCK_RETRY_DELAY).await;
+                },
+            }
+        }
+
+        let queue = {
+            let mut state = state.lock().unwrap();
+            state.running = false;
+            std::mem::take(&mut state.queue)
+        };
+
+        for QueuedOrder { id, order } in queue {
+            // Nobody listening is fine, the events are informative only
+            let _ = events.send(match client.submit_order(&order).await {
+                Ok(response) => {
+                    info!("Queued order {} for {} submitted at the open", id, order.symbol);
+                    GuardEvent::Submitted { id, order, response }
+                },
+                Err(e) => {
+                    let error = client.redact(&e);
+                    warn!("Queued order {} for {} failed at the open: {}", id, order.symbol, error);
+                    GuardEvent::Failed { id, order, error }
+                },
+            });
+        }
+    }
+}
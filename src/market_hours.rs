@@ -0,0 +1,207 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Session bounds of the US equity market, in UTC.
+//!
+//! The exchange runs on New York time. Its UTC offset follows the US
+//! daylight saving rule in force since 2007: daylight time from the second
+//! Sunday of March to the first Sunday of November. The conversion is done
+//! here so that callers only ever deal with UTC.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, MappedLocalTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+
+use crate::{AlpacaClient, AlpacaError, CalendarDay, TimeInForce};
+
+fn nth_sunday(year: i32, month: u32, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n).expect("every month has two Sundays")
+}
+
+/// The New York time zone, with the daylight saving rule above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NewYork;
+
+impl NewYork {
+    const EST: i32 = -5 * 3600;
+    const EDT: i32 = -4 * 3600;
+
+    fn offset(seconds: i32) -> FixedOffset {
+        FixedOffset::east_opt(seconds).unwrap()
+    }
+
+    /// Local times of the switches of `year`, both at 02:00 on the clock
+    /// in force before the switch.
+    fn switches(year: i32) -> (NaiveDateTime, NaiveDateTime) {
+        let two = NaiveTime::from_hms_opt(2, 0, 0).unwrap();
+        (nth_sunday(year, 3, 2).and_time(two), nth_sunday(year, 11, 1).and_time(two))
+    }
+}
+
+impl TimeZone for NewYork {
+    type Offset = FixedOffset;
+
+    fn from_offset(_: &FixedOffset) -> Self {
+        NewYork
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+        self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<FixedOffset> {
+        let (start, end) = Self::switches(local.year());
+        let hour = Duration::hours(1);
+
+        if *local >= start && *local < start + hour {
+            // Skipped when the clocks go forward
+            MappedLocalTime::None
+        } else if *local >= end - hour && *local < end {
+            // Repeated when the clocks go back
+            MappedLocalTime::Ambiguous(Self::offset(Self::EDT), Self::offset(Self::EST))
+        } else if *local >= start && *local < end {
+            MappedLocalTime::Single(Self::offset(Self::EDT))
+        } else {
+            MappedLocalTime::Single(Self::offset(Self::EST))
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+        self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+        let (start, end) = Self::switches(utc.year());
+        let dst = *utc >= start - Duration::seconds(Self::EST.into())
+            && *utc < end - Duration::seconds(Self::EDT.into());
+        Self::offset(if dst { Self::EDT } else { Self::EST })
+    }
+}
+
+/// `time` of New York on `date`, in UTC. A time repeated when the clocks
+/// go back is taken in daylight time, and a time skipped when they go
+/// forward is taken an hour later.
+pub(crate) fn new_york_to_utc(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(time);
+    NewYork.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| NewYork.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .expect("the skipped hour ends an hour later")
+        .to_utc()
+}
+
+/// Date in New York when `timestamp` happens.
+pub(crate) fn new_york_date(timestamp: DateTime<Utc>) -> NaiveDate {
+    timestamp.with_timezone(&NewYork).date_naive()
+}
+
+fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+/// Whether an order with `time_in_force` submitted at `timestamp` is in
+/// its submission window. Alpaca rejects the `opg` orders from 09:28 and
+/// the `cls` orders from 15:50 until 19:00 New York time, when those of
+/// the next session start being taken. Any other order is accepted.
+pub fn auction_order_accepted(time_in_force: TimeInForce, timestamp: DateTime<Utc>) -> bool {
+    let cutoff = match time_in_force {
+        TimeInForce::Opg => time(9, 28),
+        TimeInForce::Cls => time(15, 50),
+        _ => return true,
+    };
+
+    let local = timestamp.with_timezone(&NewYork).time();
+    local < cutoff || local >= time(19, 0)
+}
+
+/// Market hours from the Alpaca calendar, or the standard ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketHours {
+    // `None` in the standard mode
+    days: Option<BTreeMap<NaiveDate, CalendarDay>>,
+}
+
+impl MarketHours {
+    /// Hours of the calendar days, with the holidays and the early closes.
+    /// Dates missing from `days` have no session, so the calendar should
+    /// cover all the dates asked about.
+    pub fn from_calendar(days: Vec<CalendarDay>) -> Self {
+        Self { days: Some(days.into_iter().map(|day| (day.date, day)).collect()) }
+    }
+
+    /// Fetches the calendar from `start` to `end` for `from_calendar`.
+    pub async fn fetch(client: &AlpacaClient, start: NaiveDate, end: NaiveDate) -> Result<Self, AlpacaError> {
+        Ok(Self::from_calendar(client.get_calendar(start, end).await?))
+    }
+
+    /// Offline approximation: 09:30 to 16:00, extended from 04:00 to 20:00,
+    /// every weekday. It knows nothing of the holidays and the early closes.
+    pub fn standard() -> Self {
+        Self { days: None }
+    }
+
+    fn day(&self, date: NaiveDate) -> Option<CalendarDay> {
+        match &self.days {
+            Some(days) => days.get(&date).cloned(),
+            None => (!matches!(date.weekday(), Weekday::Sat | Weekday::Sun)).then(|| CalendarDay {
+                date,
+                open: time(9, 30),
+                close: time(16, 0),
+                session_open: Some(time(4, 0)),
+                session_close: Some(time(20, 0)),
+            }),
+        }
+    }
+
+    /// Open and close of the regular session of `date`, `None` when the
+    /// market is closed that day.
+    pub fn regular_session(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let day = self.day(date)?;
+        Some((new_york_to_utc(date, day.open), new_york_to_utc(date, day.close)))
+    }
+
+    /// Start of the pre-market to the end of the after-hours of `date`.
+    /// Without extended bounds in the calendar, the regular session.
+    pub fn extended_session(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let day = self.day(date)?;
+        Some((
+            new_york_to_utc(date, day.session_open.unwrap_or(day.open)),
+            new_york_to_utc(date, day.session_close.unwrap_or(day.close)),
+        ))
+    }
+
+    pub fn is_regular_hours(&self, timestamp: DateTime<Utc>) -> bool {
+        self.regular_session(new_york_date(timestamp))
+            .is_some_and(|(open, close)| open <= timestamp && timestamp < close)
+    }
+
+    /// In the pre-market or the after-hours, not in the regular session.
+    pub fn is_extended_hours(&self, timestamp: DateTime<Utc>) -> bool {
+        let in_extended = self.extended_session(new_york_date(timestamp))
+            .is_some_and(|(open, close)| open <= timestamp && timestamp < close);
+        in_extended && !self.is_regular_hours(timestamp)
+    }
+
+    /// Next regular session opening after `timestamp`, looking two weeks
+    /// ahead at most and never past the end of the calendar.
+    pub fn next_session_after(&self, timestamp: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = new_york_date(timestamp);
+        (0..14)
+            .map(|days| first + Duration::days(days))
+            .filter_map(|date| self.regular_session(date))
+            .find(|(open, _)| *open > timestamp)
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Request metrics hook.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use reqwest::{Method, StatusCode};
+
+/// Receives every completed request attempt, successful or not.
+///
+/// `endpoint` is the templated path, e.g. `/v2/orders/{id}`, so it can be
+/// used as a label without unbounded cardinality, and `OTHER_ENDPOINT` for
+/// the paths given to `request` and `make_request_raw`. `status` is `None`
+/// when no response came back (timeout, connection error).
+pub trait Metrics: Debug + Send + Sync {
+    fn record(&self, endpoint: &'static str, method: &Method, status: Option<StatusCode>, elapsed: Duration);
+}
+
+/// Label of the endpoints the crate doesn't wrap.
+pub const OTHER_ENDPOINT: &str = "other";
+
+#[derive(Debug, Default)]
+pub struct EndpointStats {
+    pub requests: AtomicU64,
+    /// Attempts without response or with a non success status.
+    pub failures: AtomicU64,
+    pub elapsed_us: AtomicU64,
+}
+
+/// In memory `Metrics` counting the requests per endpoint and method.
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    stats: RwLock<HashMap<(&'static str, Method), EndpointStats>>,
+}
+
+impl AtomicMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn requests(&self, endpoint: &str, method: &Method) -> u64 {
+        self.read(endpoint, method, |stats| stats.requests.load(Ordering::Relaxed))
+    }
+
+    pub fn failures(&self, endpoint: &str, method: &Method) -> u64 {
+        self.read(endpoint, method, |stats| stats.failures.load(Ordering::Relaxed))
+    }
+
+    pub fn total_elapsed(&self, endpoint: &str, method: &Method) -> Duration {
+        Duration::from_micros(self.read(endpoint, method, |stats| stats.elapsed_us.load(Ordering::Relaxed)))
+    }
+
+    fn read(&self, endpoint: &str, method: &Method, value: impl Fn(&EndpointStats) -> u64) -> u64 {
+        self.stats.read().unwrap()
+            .iter()
+            .find(|((label, verb), _)| *label == endpoint && verb == method)
+            .map_or(0, |(_, stats)| value(stats))
+    }
+}
+
+impl Metrics for AtomicMetrics {
+    fn record(&self, endpoint: &'static str, method: &Method, status: Option<StatusCode>, elapsed: Duration) {
+        let update = |stats: &EndpointStats| {
+            stats.requests.fetch_add(1, Ordering::Relaxed);
+            if !status.is_some_and(|status| status.is_success()) {
+                stats.failures.fetch_add(1, Ordering::Relaxed);
+            }
+            stats.elapsed_us.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        };
+
+        if let Some(stats) = self.stats.read().unwrap().get(&(endpoint, method.clone())) {
+            return update(stats);
+        }
+
+        let mut stats = self.stats.write().unwrap();
+        update(stats.entry((endpoint, method.clone())).or_default());
+    }
+}
+
+/// Totals over the requests of a client and its clones, see
+/// `AlpacaClient::request_counts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct RequestCounts {
+    /// Calls, however many attempts each took.
+    pub requests: u64,
+    /// Calls that failed after their last attempt.
+    pub errors: u64,
+    /// Attempts after the first of a call.
+    pub retries: u64,
+}
+
+impl RequestCounts {
+    /// Counts since `earlier`, taken from the same client.
+    pub fn since(&self, earlier: &RequestCounts) -> RequestCounts {
+        RequestCounts {
+            requests: self.requests.saturating_sub(earlier.requests),
+            errors: self.errors.saturating_sub(earlier.errors),
+            retries: self.retries.saturating_sub(earlier.retries),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RequestCounters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    retries: AtomicU64,
+}
+
+impl RequestCounters {
+    pub(crate) fn record(&self, retries: u32, failed: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.retries.fetch_add(retries.into(), Ordering::Relaxed);
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self) -> RequestCounts {
+        RequestCounts {
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+        }
+    }
+}
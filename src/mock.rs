@@ -0,0 +1,510 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! In-memory `AlpacaApi` implementation for tests.
+//!
+//! `MockAlpaca` holds an account, its positions and one price per symbol.
+//! Market orders fill at once at the symbol price, limit and stop orders
+//! rest until a `tick` crosses them. Every call is recorded for assertions,
+//! and counted as a request of a client that never retries.
+
+use std::collections::HashMap;
+use std::future::{ready, Future};
+use std::sync::Mutex;
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+use crate::{AlpacaApi, AlpacaError, Asset, Clock, CorporateActions, CorporateActionsParams, RequestCounts};
+use crate::{OrderRequest, OrderSide, OrderType, PriceType, TimeInForce};
+use crate::utils::{is_crypto, position_symbol};
+
+/// Calls received by `MockAlpaca`, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockCall {
+    GetAccount,
+    GetPositions,
+    GetPrices { assets: Vec<String>, types: Vec<PriceType> },
+    SubmitOrder(OrderRequest),
+    GetOrderInfo(String),
+    GetAsset(String),
+    GetClock,
+    GetCorporateActions(Vec<String>),
+    GetAccountActivities(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct MockPosition {
+    qty: f64,
+    entry: f64,
+}
+
+#[derive(Debug)]
+struct MockState {
+    cash: f64,
+    positions: HashMap<String, MockPosition>,
+    prices: HashMap<String, f64>,
+    fractionable: HashMap<String, bool>,
+    shorting_enabled: bool,
+    orders: Vec<Value>,
+    calls: Vec<MockCall>,
+    errors: u64,
+    clock: Clock,
+    corporate_actions: CorporateActions,
+    activities: Vec<Value>,
+    submit_delay: Option<std::time::Duration>,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        // Open market until a `set_clock` says otherwise
+        let now = Utc::now().fixed_offset();
+
+        Self {
+            cash: 0.0,
+            positions: HashMap::new(),
+            prices: HashMap::new(),
+            fractionable: HashMap::new(),
+            shorting_enabled: false,
+            orders: Vec::new(),
+            calls: Vec::new(),
+            errors: 0,
+            corporate_actions: CorporateActions::default(),
+            activities: Vec::new(),
+            submit_delay: None,
+            clock: Clock {
+                timestamp: now,
+                is_open: true,
+                next_open: now + Duration::days(1),
+                next_close: now + Duration::hours(1),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MockAlpaca {
+    state: Mutex<MockState>,
+}
+
+fn number(order: &Value, key: &str) -> f64 {
+    order[key].as_str().and_then(|value| value.parse::<f64>().ok()).unwrap_or(0.0)
+}
+
+/// Price at which a resting order fills when the market trades at `price`.
+fn crossing_price(order: &Value, price: f64) -> Option<f64> {
+    let limit = order["limit_price"].as_str().and_then(|p| p.parse::<f64>().ok());
+    let stop = order["stop_price"].as_str().and_then(|p| p.parse::<f64>().ok());
+    let buy = order["side"] == "buy";
+
+    match (order["type"].as_str()?, limit, stop) {
+        ("limit", Some(limit), _) if (buy && price <= limit) || (!buy && price >= limit) => Some(limit),
+        ("stop", _, Some(stop)) if (buy && price >= stop) || (!buy && price <= stop) => Some(price),
+        _ => None,
+    }
+}
+
+impl MockState {
+    /// Fills the rest of `order` at `price`.
+    fn fill(&mut self, order: &mut Value, price: f64) {
+        let rest = number(order, "qty") - number(order, "filled_qty");
+        self.execute(order, rest, price);
+    }
+
+    /// Fills `qty` of `order` at `price`, all of it or a part.
+    fn execute(&mut self, order: &mut Value, qty: f64, price: f64) {
+        // Held like Alpaca does, `BTC/USD` as `BTCUSD`
+        let symbol = position_symbol(order["symbol"].as_str().unwrap_or_default());
+        let position = self.positions.entry(symbol.clone()).or_default();
+
+        let signed = if order["side"] == "buy" { qty } else { -qty };
+        let held = position.qty;
+        self.cash -= signed * price;
+        position.qty += signed;
+
+        // Averaged when adding to the position, reset when it flips side
+        if held == 0.0 || held.signum() == signed.signum() {
+            position.entry = (position.entry * held.abs() + price * qty) / (held.abs() + qty);
+        } else if position.qty != 0.0 && position.qty.signum() != held.signum() {
+            position.entry = price;
+        }
+
+        if position.qty == 0.0 {
+            self.positions.remove(&symbol);
+        }
+
+        let filled_before = number(order, "filled_qty");
+        let filled_qty = filled_before + qty;
+        let average = if filled_before == 0.0 {
+            price
+        } else {
+            (number(order, "filled_avg_price") * filled_before + price * qty) / filled_qty
+        };
+        let complete = filled_qty >= number(order, "qty");
+
+        order["status"] = json!(if complete { "filled" } else { "partially_filled" });
+        order["filled_qty"] = json!(filled_qty.to_string());
+        order["filled_avg_price"] = json!(average.to_string());
+        if complete {
+            order["filled_at"] = json!(Utc::now().to_rfc3339());
+        }
+    }
+
+    /// Price of the asset held as `symbol`.
+    fn held_price(&self, symbol: &str) -> Option<f64> {
+        self.prices.iter()
+            .find(|(asset, _)| position_symbol(asset) == symbol)
+            .map(|(_, price)| *price)
+    }
+
+    fn equity(&self) -> f64 {
+        self.cash + self.positions.iter()
+            .map(|(symbol, position)| position.qty * self.held_price(symbol).unwrap_or(position.entry))
+            .sum::<f64>()
+    }
+}
+
+impl MockAlpaca {
+    pub fn new(cash: f64) -> Self {
+        Self { state: Mutex::new(MockState { cash, ..Default::default() }) }
+    }
+
+    pub fn set_price(&self, symbol: &str, price: f64) {
+        self.state.lock().unwrap().prices.insert(symbol.to_string(), price);
+    }
+
+    pub fn set_position(&self, symbol: &str, qty: f64, entry: f64) {
+        self.state.lock().unwrap().positions.insert(position_symbol(symbol), MockPosition { qty, entry });
+    }
+
+    /// Symbols are not fractionable unless set otherwise.
+    pub fn set_fractionable(&self, symbol: &str, fractionable: bool) {
+        self.state.lock().unwrap().fractionable.insert(symbol.to_string(), fractionable);
+    }
+
+    /// The account of a `new` mock can't short.
+    pub fn set_shorting_enabled(&self, enabled: bool) {
+        self.state.lock().unwrap().shorting_enabled = enabled;
+    }
+
+    pub fn set_clock(&self, clock: Clock) {
+        self.state.lock().unwrap().clock = clock;
+    }
+
+    /// Time the submitted orders take to be answered, on a tokio timer.
+    /// The order is placed at once, the answer comes `delay` later.
+    pub fn set_submit_delay(&self, delay: std::time::Duration) {
+        self.state.lock().unwrap().submit_delay = Some(delay);
+    }
+
+    /// Served by `get_corporate_actions` whatever the dates asked for.
+    pub fn add_corporate_actions(&self, actions: CorporateActions) {
+        self.state.lock().unwrap().corporate_actions.extend(actions);
+    }
+
+    /// Adds a non-trade activity, crediting its `net_amount` to the cash.
+    pub fn add_activity(&self, activity: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.cash += number(&activity, "net_amount");
+        state.activities.push(activity);
+    }
+
+    pub fn cash(&self) -> f64 {
+        self.state.lock().unwrap().cash
+    }
+
+    /// Moves `symbol` to `price` and fills the resting orders it crosses.
+    pub fn tick(&self, symbol: &str, price: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.prices.insert(symbol.to_string(), price);
+
+        let mut orders = std::mem::take(&mut state.orders);
+        for order in orders.iter_mut().filter(|order| order["symbol"] == symbol && matches!(order["status"].as_str(), Some("new" | "partially_filled"))) {
+            if let Some(fill_price) = crossing_price(order, price) {
+                state.fill(order, fill_price);
+            }
+        }
+        state.orders = orders;
+    }
+
+    /// Fills `qty` of the resting order `id` at `price`, leaving it
+    /// partially filled unless that is the rest of it.
+    pub fn fill_partially(&self, id: &str, qty: f64, price: f64) -> Result<(), AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        let mut orders = std::mem::take(&mut state.orders);
+
+        let result = match orders.iter_mut().find(|order| order["id"] == id) {
+            Some(order) if matches!(order["status"].as_str(), Some("new" | "partially_filled")) => {
+                state.execute(order, qty, price);
+                Ok(())
+            },
+            Some(order) => Err(AlpacaError::InvalidOrder { message: format!("order {} is {}", id, order["status"]), status: None, code: None }),
+            None => Err(AlpacaError::NotFound { resource: format!("orders/{}", id) }),
+        };
+        state.orders = orders;
+        result
+    }
+
+    /// Cancels what is left of the resting order `id`.
+    pub fn cancel_order(&self, id: &str) -> Result<(), AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.orders.iter_mut().find(|order| order["id"] == id) {
+            Some(order) if matches!(order["status"].as_str(), Some("new" | "partially_filled")) => {
+                order["status"] = json!("canceled");
+                order["canceled_at"] = json!(Utc::now().to_rfc3339());
+                Ok(())
+            },
+            Some(order) => Err(AlpacaError::InvalidOrder { message: format!("order {} is {}", id, order["status"]), status: None, code: None }),
+            None => Err(AlpacaError::NotFound { resource: format!("orders/{}", id) }),
+        }
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    // The calls recorded already, only the failures are left to count
+    fn counted<T>(&self, result: Result<T, AlpacaError>) -> Result<T, AlpacaError> {
+        if result.is_err() {
+            self.state.lock().unwrap().errors += 1;
+        }
+        result
+    }
+
+    /// All the submitted orders, in Alpaca order format.
+    pub fn orders(&self) -> Vec<Value> {
+        self.state.lock().unwrap().orders.clone()
+    }
+
+    fn account(&self) -> Result<Value, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetAccount);
+
+        Ok(json!({
+            "id": "mock-account",
+            "status": "ACTIVE",
+            "cash": state.cash.to_string(),
+            "buying_power": state.cash.max(0.0).to_string(),
+            "equity": state.equity().to_string(),
+            "shorting_enabled": state.shorting_enabled,
+        }))
+    }
+
+    fn positions(&self) -> Result<Value, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetPositions);
+
+        let positions: Vec<Value> = state.positions.iter().map(|(symbol, position)| {
+            let price = state.held_price(symbol).unwrap_or(position.entry);
+            json!({
+                "symbol": symbol,
+                "side": if position.qty < 0.0 { "short" } else { "long" },
+                "qty": position.qty.to_string(),
+                "qty_available": position.qty.to_string(),
+                "avg_entry_price": position.entry.to_string(),
+                "current_price": price.to_string(),
+                "market_value": (position.qty * price).to_string(),
+            })
+        }).collect();
+
+        Ok(Value::Array(positions))
+    }
+
+    fn prices(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetPrices {
+            assets: assets.iter().map(ToString::to_string).collect(),
+            types: types.to_vec(),
+        });
+
+        let now = Utc::now().to_rfc3339();
+        Ok(assets.iter()
+            .filter_map(|asset| Some((asset.to_string(), *state.prices.get(*asset)?)))
+            .map(|(asset, price)| {
+                let prices = types.iter().map(|price_type| (price_type.clone(), match price_type {
+                    PriceType::Trades => json!({"p": price, "s": 1, "t": now}),
+                    PriceType::Quotes => json!({"bp": price, "bs": 1, "ap": price, "as": 1, "t": now}),
+                    PriceType::Bars => json!({"o": price, "h": price, "l": price, "c": price, "v": 1, "t": now}),
+                })).collect();
+                (asset, prices)
+            })
+            .collect())
+    }
+
+    fn submit(&self, order: &OrderRequest) -> Result<Value, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::SubmitOrder(order.clone()));
+        order.qty.validate()?;
+
+        if is_crypto(&order.symbol) && !matches!(order.time_in_force, TimeInForce::Gtc | TimeInForce::Ioc) {
+            return Err(AlpacaError::InvalidOrder {
+                message: format!("crypto orders take gtc or ioc, not {:?}", order.time_in_force),
+                status: None,
+                code: None,
+            });
+        }
+
+        if matches!(order.order_type, OrderType::StopLimit | OrderType::TrailingStop) {
+            return Err(AlpacaError::InvalidOrder {
+                message: format!("{:?} orders are not supported by MockAlpaca", order.order_type),
+                status: None,
+                code: None,
+            });
+        }
+
+        let price = (order.order_type == OrderType::Market)
+            .then(|| state.prices.get(&order.symbol).copied())
+            .flatten();
+        if order.order_type == OrderType::Market && price.is_none() {
+            return Err(AlpacaError::NotFound { resource: format!("price of {}", order.symbol) });
+        }
+
+        // Generated by the API when the request has none
+        let number = state.orders.len() + 1;
+        let mut submitted = json!({
+            "id": format!("mock-order-{}", number),
+            "client_order_id": order.client_order_id.clone().unwrap_or_else(|| format!("mock-client-{}", number)),
+            "created_at": Utc::now().to_rfc3339(),
+            "symbol": order.symbol,
+            "qty": order.qty.as_str(),
+            "side": if order.side == OrderSide::Buy { "buy" } else { "sell" },
+            "type": serde_json::to_value(order.order_type)?,
+            "time_in_force": serde_json::to_value(order.time_in_force)?,
+            "limit_price": order.limit_price.map(|p| p.to_string()),
+            "stop_price": order.stop_price.map(|p| p.to_string()),
+            "status": "new",
+            "filled_qty": "0",
+            "filled_avg_price": null,
+        });
+
+        if let Some(price) = price {
+            state.fill(&mut submitted, price);
+        } else if let Some(fill_price) = state.prices.get(&order.symbol).and_then(|p| crossing_price(&submitted, *p)) {
+            state.fill(&mut submitted, fill_price);
+        }
+
+        state.orders.push(submitted.clone());
+        Ok(submitted)
+    }
+
+    fn order_info(&self, id: &str) -> Result<Value, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetOrderInfo(id.to_string()));
+
+        state.orders.iter()
+            .find(|order| order["id"] == id)
+            .cloned()
+            .ok_or_else(|| AlpacaError::NotFound { resource: format!("orders/{}", id) })
+    }
+
+    fn clock(&self) -> Result<Clock, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetClock);
+        Ok(state.clock.clone())
+    }
+
+    fn asset(&self, symbol: &str) -> Result<Asset, AlpacaError> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetAsset(symbol.to_string()));
+
+        Ok(Asset {
+            id: format!("mock-asset-{}", symbol),
+            class: "us_equity".to_string(),
+            exchange: "NASDAQ".to_string(),
+            symbol: symbol.to_string(),
+            name: symbol.to_string(),
+            status: "active".to_string(),
+            tradable: true,
+            marginable: true,
+            shortable: true,
+            easy_to_borrow: true,
+            fractionable: state.fractionable.get(symbol).copied().unwrap_or(false),
+        })
+    }
+}
+
+impl AlpacaApi for MockAlpaca {
+    fn request_counts(&self) -> Option<RequestCounts> {
+        let state = self.state.lock().unwrap();
+        Some(RequestCounts { requests: state.calls.len() as u64, errors: state.errors, retries: 0 })
+    }
+
+    fn get_account(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        ready(self.counted(self.account()))
+    }
+
+    fn get_positions(&self) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        ready(self.counted(self.positions()))
+    }
+
+    fn get_prices_multi(
+        &self,
+        assets: &[&str],
+        types: &[PriceType],
+    ) -> impl Future<Output = Result<HashMap<String, HashMap<PriceType, Value>>, AlpacaError>> + Send {
+        ready(self.counted(self.prices(assets, types)))
+    }
+
+    fn submit_order(&self, order: &OrderRequest) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        let delay = self.state.lock().unwrap().submit_delay;
+        let result = self.counted(self.submit(order));
+        async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            result
+        }
+    }
+
+    fn get_order_info(&self, id: &str, _nested: bool) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        ready(self.counted(self.order_info(id)))
+    }
+
+    fn get_asset(&self, symbol: &str) -> impl Future<Output = Result<Asset, AlpacaError>> + Send {
+        ready(self.counted(self.asset(symbol)))
+    }
+
+    fn get_clock(&self) -> impl Future<Output = Result<Clock, AlpacaError>> + Send {
+        ready(self.counted(self.clock()))
+    }
+
+    fn get_corporate_actions(
+        &self,
+        params: &CorporateActionsParams,
+    ) -> impl Future<Output = Result<CorporateActions, AlpacaError>> + Send {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetCorporateActions(params.symbols.clone()));
+        ready(Ok(state.corporate_actions.clone()))
+    }
+
+    fn get_account_activities(
+        &self,
+        activity_types: &[&str],
+        _date: Option<chrono::NaiveDate>,
+    ) -> impl Future<Output = Result<Value, AlpacaError>> + Send {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(MockCall::GetAccountActivities(activity_types.iter().map(ToString::to_string).collect()));
+        let activities = state.activities.iter()
+            .filter(|activity| activity_types.iter().any(|kind| activity["activity_type"] == *kind))
+            .cloned()
+            .collect();
+        ready(Ok(Value::Array(activities)))
+    }
+}
@@ -0,0 +1,1071 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{AlpacaError, CsvRecord};
+
+/// Trade as returned by `/v2/stocks/{symbol}/trades/latest` and the
+/// historical trades endpoint.
+///
+/// The data API uses single letter keys, they are renamed here to
+/// readable field names.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    #[serde(rename = "p")]
+    pub price: f64,
+    #[serde(rename = "s")]
+    pub size: u64,
+    /// Empty for crypto trades, which come without exchange.
+    #[serde(rename = "x", default)]
+    pub exchange: String,
+    #[serde(rename = "c", default, deserialize_with = "conditions")]
+    pub conditions: Vec<String>,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+pub type LatestTrade = Trade;
+
+/// Trade conditions, a list for stocks and crypto but a single code for
+/// option trades.
+fn conditions<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Conditions {
+        Many(Vec<String>),
+        One(String),
+    }
+
+    Ok(match Option::<Conditions>::deserialize(deserializer)? {
+        Some(Conditions::Many(conditions)) => conditions,
+        Some(Conditions::One(condition)) => vec![condition],
+        None => Vec::new(),
+    })
+}
+
+/// Quote as returned by `/v2/stocks/{symbol}/quotes/latest` and the
+/// historical quotes endpoint.
+///
+/// Pre-market quotes may be zero or crossed, they are kept as they come
+/// so `spread()` may be negative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quote {
+    #[serde(rename = "bp")]
+    pub bid_price: f64,
+    #[serde(rename = "bs")]
+    pub bid_size: u64,
+    #[serde(rename = "ap")]
+    pub ask_price: f64,
+    #[serde(rename = "as")]
+    pub ask_size: u64,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+pub type LatestQuote = Quote;
+
+impl Quote {
+    pub fn mid(&self) -> f64 {
+        (self.bid_price + self.ask_price) / 2.0
+    }
+
+    pub fn spread(&self) -> f64 {
+        self.ask_price - self.bid_price
+    }
+}
+
+/// OHLCV bar as returned by the latest and historical bar endpoints.
+///
+/// `vwap` is not provided by every feed, so it is optional.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    #[serde(rename = "o")]
+    pub open: f64,
+    #[serde(rename = "h")]
+    pub high: f64,
+    #[serde(rename = "l")]
+    pub low: f64,
+    #[serde(rename = "c")]
+    pub close: f64,
+    #[serde(rename = "v")]
+    pub volume: f64,
+    #[serde(rename = "n", default)]
+    pub trade_count: u64,
+    #[serde(rename = "vw", default, skip_serializing_if = "Option::is_none")]
+    pub vwap: Option<f64>,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Latest prices of `AlpacaClient::get_prices_checked`, with the requested
+/// symbols the server left out, unknown or without recent data, and the
+/// price types whose request failed.
+#[derive(Debug, Default)]
+pub struct PricesResult {
+    pub data: std::collections::HashMap<String, std::collections::HashMap<crate::PriceType, serde_json::Value>>,
+    /// In the order requested.
+    pub missing: Vec<String>,
+    /// In the order requested, empty when every type was fetched.
+    pub failed: Vec<(crate::PriceType, AlpacaError)>,
+}
+
+impl PricesResult {
+    /// The prices, or the error of the first failed type, or
+    /// `AlpacaError::MissingSymbols` if any symbol is missing.
+    pub fn strict(mut self) -> Result<std::collections::HashMap<String, std::collections::HashMap<crate::PriceType, serde_json::Value>>, AlpacaError> {
+        if !self.failed.is_empty() {
+            return Err(self.failed.swap_remove(0).1);
+        }
+        match self.missing.is_empty() {
+            true => Ok(self.data),
+            false => Err(AlpacaError::MissingSymbols { symbols: self.missing }),
+        }
+    }
+}
+
+/// Record layout of `AlpacaClient::download_bars_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadFormat {
+    /// One JSON object per line, the bar fields plus `symbol`.
+    Ndjson,
+    /// `symbol,t,o,h,l,c,v,n,vw` rows after a header line.
+    Csv,
+}
+
+/// Progress of a bars download. When it is interrupted, passing
+/// `next_page_token` as the `page_token` of the options resumes it after
+/// the last record written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadSummary {
+    pub rows: usize,
+    pub pages: usize,
+    pub last_timestamp: Option<DateTime<Utc>>,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BarRecord<'a> {
+    symbol: &'a str,
+    #[serde(flatten)]
+    bar: &'a Bar,
+}
+
+impl DownloadFormat {
+    pub(crate) fn header(&self) -> Option<String> {
+        match self {
+            DownloadFormat::Ndjson => None,
+            DownloadFormat::Csv => {
+                let mut header = String::new();
+                let columns = std::iter::once("symbol").chain(Bar::columns().iter().copied());
+                crate::csv_export::push_row(&mut header, columns, ',');
+                Some(header)
+            },
+        }
+    }
+
+    /// Appends the line of `bar` to `out`.
+    pub(crate) fn write_record(&self, out: &mut String, symbol: &str, bar: &Bar) -> Result<(), serde_json::Error> {
+        match self {
+            DownloadFormat::Ndjson => {
+                out.push_str(&serde_json::to_string(&BarRecord { symbol, bar })?);
+                out.push('\n');
+            },
+            DownloadFormat::Csv => {
+                let fields = bar.fields();
+                let row = std::iter::once(symbol).chain(fields.iter().map(String::as_str));
+                crate::csv_export::push_row(out, row, ',');
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Items returned by the historical endpoints, used to keep merged
+/// pages in the requested order.
+pub(crate) trait Timestamped {
+    fn timestamp(&self) -> DateTime<Utc>;
+}
+
+impl Timestamped for Trade {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+impl Timestamped for Quote {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+impl Timestamped for Bar {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+}
+
+/// Aggregation period of the bars endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimeFrame {
+    Minute(u32),
+    Hour(u32),
+    Day,
+    Week,
+    Month(u32),
+}
+
+impl fmt::Display for TimeFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Minute(n) => write!(f, "{}Min", n),
+            Self::Hour(n) => write!(f, "{}Hour", n),
+            Self::Day => write!(f, "1Day"),
+            Self::Week => write!(f, "1Week"),
+            Self::Month(n) => write!(f, "{}Month", n),
+        }
+    }
+}
+
+/// Chronological order of the historical data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Sort {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Asc => write!(f, "asc"),
+            Self::Desc => write!(f, "desc"),
+        }
+    }
+}
+
+impl Sort {
+    /// Stable sort of `items` by timestamp in this order.
+    pub(crate) fn apply<T: Timestamped>(&self, items: &mut [T]) {
+        match self {
+            Self::Asc => items.sort_by_key(|item| item.timestamp()),
+            Self::Desc => items.sort_by_key(|item| std::cmp::Reverse(item.timestamp())),
+        }
+    }
+}
+
+/// Options shared by the historical bars, trades and quotes requests.
+///
+/// `limit` is the page size sent to the server, all the pages are
+/// fetched and merged anyway. `page_token` resumes a previous download
+/// from the given page. `max_response_size` bounds the body of each page
+/// instead of the maximum of the client, for the bulk downloads that
+/// need more.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoricalOptions {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub sort: Sort,
+    pub page_token: Option<String>,
+    pub max_response_size: Option<usize>,
+}
+
+impl HistoricalOptions {
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let format = |ts: &DateTime<Utc>| ts.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+
+        let mut query = vec![("sort", self.sort.to_string())];
+        if let Some(start) = &self.start {
+            query.push(("start", format(start)));
+        }
+        if let Some(end) = &self.end {
+            query.push(("end", format(end)));
+        }
+        if let Some(limit) = self.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        query
+    }
+}
+
+/// Corporate action adjustment of the historical bars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Adjustment {
+    Raw,
+    Split,
+    Dividend,
+    #[serde(rename = "spin-off")]
+    SpinOff,
+    All,
+}
+
+impl fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raw => write!(f, "raw"),
+            Self::Split => write!(f, "split"),
+            Self::Dividend => write!(f, "dividend"),
+            Self::SpinOff => write!(f, "spin-off"),
+            Self::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Source of the stock market data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataFeed {
+    Iex,
+    Sip,
+    DelayedSip,
+    Otc,
+}
+
+impl fmt::Display for DataFeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Iex => write!(f, "iex"),
+            Self::Sip => write!(f, "sip"),
+            Self::DelayedSip => write!(f, "delayed_sip"),
+            Self::Otc => write!(f, "otc"),
+        }
+    }
+}
+
+/// How far in the future `end` may be, to absorb the clock skew.
+const END_TOLERANCE: chrono::Duration = chrono::Duration::minutes(1);
+
+/// Parameters of `AlpacaClient::get_bars`, built with
+/// `HistoricalBarsParams::builder` so that they were checked before any
+/// request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoricalBarsParams {
+    timeframe: TimeFrame,
+    options: HistoricalOptions,
+    adjustment: Option<Adjustment>,
+    feed: Option<DataFeed>,
+    asof: Option<NaiveDate>,
+}
+
+impl HistoricalBarsParams {
+    pub fn builder() -> HistoricalBarsParamsBuilder {
+        HistoricalBarsParamsBuilder::default()
+    }
+
+    pub fn timeframe(&self) -> TimeFrame {
+        self.timeframe
+    }
+
+    pub fn options(&self) -> &HistoricalOptions {
+        &self.options
+    }
+
+    /// The query parameters besides those of `options`.
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![("timeframe", self.timeframe.to_string())];
+        if let Some(adjustment) = self.adjustment {
+            query.push(("adjustment", adjustment.to_string()));
+        }
+        if let Some(feed) = self.feed {
+            query.push(("feed", feed.to_string()));
+        }
+        if let Some(asof) = self.asof {
+            query.push(("asof", asof.to_string()));
+        }
+        query
+    }
+}
+
+/// Builder of `HistoricalBarsParams`, daily bars unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct HistoricalBarsParamsBuilder {
+    timeframe: TimeFrame,
+    options: HistoricalOptions,
+    adjustment: Option<Adjustment>,
+    feed: Option<DataFeed>,
+    asof: Option<NaiveDate>,
+}
+
+impl Default for HistoricalBarsParamsBuilder {
+    fn default() -> Self {
+        Self {
+            timeframe: TimeFrame::Day,
+            options: HistoricalOptions::default(),
+            adjustment: None,
+            feed: None,
+            asof: None,
+        }
+    }
+}
+
+impl HistoricalBarsParamsBuilder {
+    pub fn timeframe(mut self, timeframe: TimeFrame) -> Self {
+        self.timeframe = timeframe;
+        self
+    }
+
+    pub fn start(mut self, start: DateTime<Utc>) -> Self {
+        self.options.start = Some(start);
+        self
+    }
+
+    pub fn end(mut self, end: DateTime<Utc>) -> Self {
+        self.options.end = Some(end);
+        self
+    }
+
+    /// From the regular open of `first` to the regular close of `last`,
+    /// both New York dates.
+    pub fn between_dates(self, first: NaiveDate, last: NaiveDate) -> Self {
+        let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+        let close = NaiveTime::from_hms_opt(16, 0, 0).unwrap();
+        self.start(crate::market_hours::new_york_to_utc(first, open))
+            .end(crate::market_hours::new_york_to_utc(last, close))
+    }
+
+    /// From the regular open `days` days ago up to now, or to the close
+    /// when the session is over. Before today's open, the days end with
+    /// yesterday's close instead.
+    pub fn last_days(self, days: u32) -> Self {
+        self.last_days_at(days, Utc::now())
+    }
+
+    pub(crate) fn last_days_at(self, days: u32, now: DateTime<Utc>) -> Self {
+        let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+        let mut last = crate::market_hours::new_york_date(now);
+        if crate::market_hours::new_york_to_utc(last, open) > now {
+            last -= chrono::Duration::days(1);
+        }
+
+        let mut builder = self.between_dates(last - chrono::Duration::days(days.into()), last);
+        builder.options.end = builder.options.end.map(|end| end.min(now));
+        builder
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.options.limit = Some(limit);
+        self
+    }
+
+    pub fn sort(mut self, sort: Sort) -> Self {
+        self.options.sort = sort;
+        self
+    }
+
+    pub fn page_token(mut self, page_token: &str) -> Self {
+        self.options.page_token = Some(page_token.to_string());
+        self
+    }
+
+    pub fn adjustment(mut self, adjustment: Adjustment) -> Self {
+        self.adjustment = Some(adjustment);
+        self
+    }
+
+    pub fn feed(mut self, feed: DataFeed) -> Self {
+        self.feed = Some(feed);
+        self
+    }
+
+    /// Date whose symbol mapping applies, for the renamed symbols.
+    pub fn asof(mut self, asof: NaiveDate) -> Self {
+        self.asof = Some(asof);
+        self
+    }
+
+    /// Checks what the server would refuse:
+    /// - a timeframe out of `[1-59]Min`, `[1-23]Hour`, `1Day`, `1Week`,
+    ///   `[1,2,3,4,6,12]Month`,
+    /// - `start` not before `end`, or `end` in the future,
+    /// - a limit out of the bounds of the endpoint,
+    /// - an adjustment other than `raw` on the `otc` feed,
+    /// - an `asof` date in the future or after `end`.
+    pub fn build(self) -> Result<HistoricalBarsParams, AlpacaError> {
+        let invalid = |message: String| Err(AlpacaError::InvalidParameter(message));
+
+        let valid_timeframe = match self.timeframe {
+            TimeFrame::Minute(n) => (1..=59).contains(&n),
+            TimeFrame::Hour(n) => (1..=23).contains(&n),
+            TimeFrame::Day | TimeFrame::Week => true,
+            TimeFrame::Month(n) => [1, 2, 3, 4, 6, 12].contains(&n),
+        };
+        if !valid_timeframe {
+            return invalid(format!("unsupported timeframe {}", self.timeframe));
+        }
+
+        let now = Utc::now();
+        if let (Some(start), Some(end)) = (self.options.start, self.options.end) {
+            if start >= end {
+                return invalid(format!("start {} is not before end {}", start, end));
+            }
+        }
+        if let Some(end) = self.options.end {
+            if end > now + END_TOLERANCE {
+                return invalid(format!("end {} is in the future", end));
+            }
+        }
+
+        crate::alpaca_client::validate_limit("/v2/stocks/bars", self.options.limit)?;
+
+        if let (Some(DataFeed::Otc), Some(adjustment)) = (self.feed, self.adjustment) {
+            if adjustment != Adjustment::Raw {
+                return invalid(format!("adjustment {} is not served by the otc feed", adjustment));
+            }
+        }
+
+        if let Some(asof) = self.asof {
+            if asof > crate::market_hours::new_york_date(now) {
+                return invalid(format!("asof {} is in the future", asof));
+            }
+            if let Some(end) = self.options.end {
+                if asof > crate::market_hours::new_york_date(end) {
+                    return invalid(format!("asof {} is after the end {}", asof, end));
+                }
+            }
+        }
+
+        Ok(HistoricalBarsParams {
+            timeframe: self.timeframe,
+            options: self.options,
+            adjustment: self.adjustment,
+            feed: self.feed,
+            asof: self.asof,
+        })
+    }
+}
+
+/// Market of the screener endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketType {
+    Stocks,
+    Crypto,
+}
+
+impl fmt::Display for MarketType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stocks => write!(f, "stocks"),
+            Self::Crypto => write!(f, "crypto"),
+        }
+    }
+}
+
+/// Entry of the market movers lists. Crypto symbols come in the slash
+/// format (`BTC/USD`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mover {
+    pub symbol: String,
+    pub price: f64,
+    pub change: f64,
+    pub percent_change: f64,
+}
+
+/// Top gainers and losers as returned by `/v1beta1/screener/{market}/movers`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Movers {
+    #[serde(default)]
+    pub gainers: Vec<Mover>,
+    #[serde(default)]
+    pub losers: Vec<Mover>,
+}
+
+/// Latest crypto order book as returned by
+/// `/v1beta3/crypto/us/latest/orderbooks`.
+///
+/// Both ladders are `(price, size)` pairs, best level first. A side
+/// without orders deserializes to an empty vector.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBook {
+    #[serde(rename = "b", default, deserialize_with = "deserialize_levels")]
+    pub bids: Vec<(f64, f64)>,
+    #[serde(rename = "a", default, deserialize_with = "deserialize_levels")]
+    pub asks: Vec<(f64, f64)>,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+fn deserialize_levels<'de, D>(deserializer: D) -> Result<Vec<(f64, f64)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Level {
+        p: f64,
+        s: f64,
+    }
+
+    let levels: Option<Vec<Level>> = Option::deserialize(deserializer)?;
+    Ok(levels
+        .unwrap_or_default()
+        .into_iter()
+        .map(|level| (level.p, level.s))
+        .collect())
+}
+
+/// Kinds of corporate actions served by `/v1/corporate-actions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorporateActionType {
+    ForwardSplit,
+    ReverseSplit,
+    CashDividend,
+    StockDividend,
+    StockMerger,
+    CashMerger,
+    SpinOff,
+}
+
+impl fmt::Display for CorporateActionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ForwardSplit => write!(f, "forward_split"),
+            Self::ReverseSplit => write!(f, "reverse_split"),
+            Self::CashDividend => write!(f, "cash_dividend"),
+            Self::StockDividend => write!(f, "stock_dividend"),
+            Self::StockMerger => write!(f, "stock_merger"),
+            Self::CashMerger => write!(f, "cash_merger"),
+            Self::SpinOff => write!(f, "spin_off"),
+        }
+    }
+}
+
+/// Filters of the corporate actions request. Empty lists mean no filter.
+#[derive(Debug, Clone, Default)]
+pub struct CorporateActionsParams {
+    pub symbols: Vec<String>,
+    pub types: Vec<CorporateActionType>,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    pub limit: Option<u32>,
+    pub sort: Sort,
+}
+
+impl CorporateActionsParams {
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![("sort", self.sort.to_string())];
+        if !self.symbols.is_empty() {
+            query.push(("symbols", self.symbols.join(",")));
+        }
+        if !self.types.is_empty() {
+            let types: Vec<String> = self.types.iter().map(ToString::to_string).collect();
+            query.push(("types", types.join(",")));
+        }
+        if let Some(start) = &self.start {
+            query.push(("start", start.to_string()));
+        }
+        if let Some(end) = &self.end {
+            query.push(("end", end.to_string()));
+        }
+        if let Some(limit) = self.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        query
+    }
+}
+
+/// Forward or reverse split, `new_rate` shares for every `old_rate`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Split {
+    pub id: String,
+    pub symbol: String,
+    pub new_rate: f64,
+    pub old_rate: f64,
+    pub process_date: NaiveDate,
+    pub ex_date: NaiveDate,
+    pub record_date: Option<NaiveDate>,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Cash dividend paying `rate` per share.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashDividend {
+    pub id: String,
+    pub symbol: String,
+    pub rate: f64,
+    #[serde(default)]
+    pub special: bool,
+    #[serde(default)]
+    pub foreign: bool,
+    pub process_date: NaiveDate,
+    pub ex_date: NaiveDate,
+    pub record_date: Option<NaiveDate>,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Stock dividend paying `rate` new shares per share.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockDividend {
+    pub id: String,
+    pub symbol: String,
+    pub rate: f64,
+    pub process_date: NaiveDate,
+    pub ex_date: NaiveDate,
+    pub record_date: Option<NaiveDate>,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Stock merger, `acquirer_rate` acquirer shares for every
+/// `acquiree_rate` acquiree shares.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockMerger {
+    pub id: String,
+    pub acquirer_symbol: String,
+    pub acquirer_rate: f64,
+    pub acquiree_symbol: String,
+    pub acquiree_rate: f64,
+    pub process_date: NaiveDate,
+    pub effective_date: NaiveDate,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Cash merger, the acquiree is paid `rate` per share.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashMerger {
+    pub id: String,
+    pub acquirer_symbol: Option<String>,
+    pub acquiree_symbol: String,
+    pub rate: f64,
+    pub process_date: NaiveDate,
+    pub effective_date: NaiveDate,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Spin-off, `new_rate` shares of `new_symbol` for every `source_rate`
+/// shares of `source_symbol`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpinOff {
+    pub id: String,
+    pub source_symbol: String,
+    pub source_rate: f64,
+    pub new_symbol: String,
+    pub new_rate: f64,
+    pub process_date: NaiveDate,
+    pub ex_date: NaiveDate,
+    pub record_date: Option<NaiveDate>,
+    pub payable_date: Option<NaiveDate>,
+}
+
+/// Corporate actions grouped by kind, since their schemas differ.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorporateActions {
+    pub forward_splits: Vec<Split>,
+    pub reverse_splits: Vec<Split>,
+    pub cash_dividends: Vec<CashDividend>,
+    pub stock_dividends: Vec<StockDividend>,
+    pub stock_mergers: Vec<StockMerger>,
+    pub cash_mergers: Vec<CashMerger>,
+    pub spin_offs: Vec<SpinOff>,
+}
+
+impl CorporateActions {
+    /// Appends the actions of another page.
+    pub(crate) fn extend(&mut self, other: CorporateActions) {
+        self.forward_splits.extend(other.forward_splits);
+        self.reverse_splits.extend(other.reverse_splits);
+        self.cash_dividends.extend(other.cash_dividends);
+        self.stock_dividends.extend(other.stock_dividends);
+        self.stock_mergers.extend(other.stock_mergers);
+        self.cash_mergers.extend(other.cash_mergers);
+        self.spin_offs.extend(other.spin_offs);
+    }
+}
+
+/// Filters of the news requests.
+///
+/// With `include_content` the article bodies are returned as HTML, see
+/// `crate::utils::strip_html` to get plain text out of them.
+#[derive(Debug, Clone, Default)]
+pub struct NewsParams {
+    pub symbols: Vec<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub sort: Sort,
+    pub include_content: bool,
+    pub exclude_contentless: bool,
+}
+
+impl NewsParams {
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let format = |ts: &DateTime<Utc>| ts.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+
+        let mut query = vec![("sort", self.sort.to_string())];
+        if !self.symbols.is_empty() {
+            query.push(("symbols", self.symbols.join(",")));
+        }
+        if let Some(start) = &self.start {
+            query.push(("start", format(start)));
+        }
+        if let Some(end) = &self.end {
+            query.push(("end", format(end)));
+        }
+        if let Some(limit) = self.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if self.include_content {
+            query.push(("include_content", "true".to_string()));
+        }
+        if self.exclude_contentless {
+            query.push(("exclude_contentless", "true".to_string()));
+        }
+        query
+    }
+}
+
+/// News article as returned by `/v1beta1/news`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewsArticle {
+    pub id: u64,
+    pub headline: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub summary: String,
+    /// HTML body, empty unless the request set `include_content`.
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    #[serde(default)]
+    pub source: String,
+}
+
+/// Single page of news and the token to request the next one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewsPage {
+    #[serde(default)]
+    pub news: Vec<NewsArticle>,
+    pub next_page_token: Option<String>,
+}
+
+/// Market clock as returned by `/v2/clock`.
+///
+/// The timestamps keep the exchange offset they are served with, so
+/// `timestamp.date_naive()` is the current date in New York.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Clock {
+    pub timestamp: DateTime<FixedOffset>,
+    pub is_open: bool,
+    pub next_open: DateTime<FixedOffset>,
+    pub next_close: DateTime<FixedOffset>,
+}
+
+/// Trading day as returned by `/v2/calendar`, in New York time.
+///
+/// `close` is early on the half days. The extended session bounds are
+/// missing from some responses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    #[serde(with = "local_time")]
+    pub open: NaiveTime,
+    #[serde(with = "local_time")]
+    pub close: NaiveTime,
+    #[serde(with = "opt_local_time", default)]
+    pub session_open: Option<NaiveTime>,
+    #[serde(with = "opt_local_time", default)]
+    pub session_close: Option<NaiveTime>,
+}
+
+// `09:30` for the regular session, `0400` for the extended one
+fn parse_local_time<E: serde::de::Error>(text: &str) -> Result<NaiveTime, E> {
+    NaiveTime::parse_from_str(text, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H%M"))
+        .map_err(|e| E::custom(format!("invalid time {:?}: {}", text, e)))
+}
+
+mod local_time {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format("%H:%M").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        parse_local_time(&String::deserialize(deserializer)?)
+    }
+}
+
+mod opt_local_time {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &Option<NaiveTime>, serializer: S) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_str(&time.format("%H%M").to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| parse_local_time(&text))
+            .transpose()
+    }
+}
+
+/// Equity change since the previous close.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DailyChange {
+    pub equity: f64,
+    /// Equity at the previous close.
+    pub last_equity: f64,
+    pub change: f64,
+    /// `change` over `last_equity` in percent, `None` without a previous
+    /// equity to compare with, like on a fresh account.
+    pub change_pct: Option<f64>,
+}
+
+impl DailyChange {
+    pub fn new(equity: f64, last_equity: f64) -> Self {
+        let change = equity - last_equity;
+        let change_pct = (last_equity != 0.0).then(|| change / last_equity * 100.0);
+        Self { equity, last_equity, change, change_pct }
+    }
+}
+
+/// Equity below which the pattern day trader rule restricts day trading.
+pub const PDT_EQUITY_THRESHOLD: f64 = 25_000.0;
+
+/// Pattern day trader related fields of the account.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DayTradeStatus {
+    pub pattern_day_trader: bool,
+    /// Day trades in the last five trading days.
+    pub daytrade_count: u32,
+    #[serde(with = "crate::utils::serde_num::string_or_number")]
+    pub daytrading_buying_power: f64,
+    #[serde(with = "crate::utils::serde_num::string_or_number")]
+    pub equity: f64,
+}
+
+impl DayTradeStatus {
+    /// True when one more day trade would flag the account: three day
+    /// trades already counted with equity under $25k.
+    pub fn at_day_trade_limit(&self) -> bool {
+        self.daytrade_count >= 3 && self.equity < PDT_EQUITY_THRESHOLD
+    }
+}
+
+/// Outcome of the pattern day trader pre-trade check, with its reasoning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PdtDecision {
+    Allowed(String),
+    Blocked(String),
+}
+
+/// Crypto funding wallet as returned by `/v2/wallets`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Wallet {
+    pub asset: String,
+    #[serde(default)]
+    pub chain: Option<String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(with = "crate::utils::serde_num::string_or_number")]
+    pub balance: f64,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub available: Option<f64>,
+}
+
+/// Deposit (`INCOMING`) or withdrawal (`OUTGOING`) of a funding wallet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalletTransfer {
+    pub id: String,
+    #[serde(default)]
+    pub tx_hash: Option<String>,
+    pub direction: String,
+    pub status: String,
+    pub asset: String,
+    #[serde(default)]
+    pub chain: Option<String>,
+    #[serde(with = "crate::utils::serde_num::string_or_number")]
+    pub amount: f64,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub network_fee: Option<f64>,
+    #[serde(default)]
+    pub from_address: Option<String>,
+    #[serde(default)]
+    pub to_address: Option<String>,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl WalletTransfer {
+    pub fn is_withdrawal(&self) -> bool {
+        self.direction.eq_ignore_ascii_case("outgoing")
+    }
+
+    /// True until the transfer is complete or failed.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.status.to_ascii_lowercase().as_str(), "complete" | "failed")
+    }
+}
+
+/// Asset as returned by `/v2/assets` and embedded in watchlists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Asset {
+    pub id: String,
+    pub class: String,
+    pub exchange: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub name: String,
+    pub status: String,
+    pub tradable: bool,
+    #[serde(default)]
+    pub marginable: bool,
+    #[serde(default)]
+    pub shortable: bool,
+    #[serde(default)]
+    pub easy_to_borrow: bool,
+    #[serde(default)]
+    pub fractionable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watchlist {
+    pub id: String,
+    pub account_id: String,
+    pub name: String,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+impl Watchlist {
+    pub fn symbols(&self) -> Vec<&str> {
+        self.assets.iter().map(|asset| asset.symbol.as_str()).collect()
+    }
+}
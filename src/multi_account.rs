@@ -0,0 +1,143 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Several `AlpacaWrapper` accounts sharing one market data client.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::{AlpacaApi, AlpacaClient, AlpacaError, AlpacaWrapper, Position, PriceSource, PriceType};
+
+/// Accounts by label, each with its own positions, cash and orders.
+///
+/// `update_prices` fetches the prices of all the accounts once through
+/// `data` and hands them to every account, so the accounts don't spend
+/// their own market data rate limit on identical quotes.
+#[derive(Debug)]
+pub struct MultiAccountWrapper<C = AlpacaClient> {
+    data: Arc<C>,
+    runtime: Runtime,
+    accounts: BTreeMap<String, AlpacaWrapper<C>>,
+}
+
+impl<C: AlpacaApi> MultiAccountWrapper<C> {
+    pub fn new(data: Arc<C>) -> Self {
+        Self {
+            data,
+            runtime: Runtime::new().unwrap(),
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `account` under `label`, replacing any account with that label.
+    pub fn with_account(mut self, label: &str, account: AlpacaWrapper<C>) -> Self {
+        self.accounts.insert(label.to_string(), account);
+        self
+    }
+
+    /// `with_account` of a wrapper built over `api` without fetching its
+    /// prices, which come with the next `update_prices`.
+    pub fn with_account_api(self, label: &str, api: Arc<C>, assets: Vec<String>) -> Result<Self, AlpacaError> {
+        let options = crate::PriceOptions::default().initial_fetch(false);
+        Ok(self.with_account(label, AlpacaWrapper::with_api_options(api, assets, options)?))
+    }
+
+    pub fn account(&self, label: &str) -> Option<&AlpacaWrapper<C>> {
+        self.accounts.get(label)
+    }
+
+    pub fn labels(&self) -> Vec<&str> {
+        self.accounts.keys().map(String::as_str).collect()
+    }
+
+    fn route(&self, label: &str) -> Result<&AlpacaWrapper<C>, AlpacaError> {
+        self.accounts.get(label)
+            .ok_or_else(|| AlpacaError::InvalidParameter(format!("unknown account {}", label)))
+    }
+
+    /// One request for the assets of all the accounts.
+    pub fn update_prices(&self) {
+        let mut assets: Vec<&str> = self.accounts.values()
+            .flat_map(|account| account.assets().iter().map(String::as_str))
+            .collect();
+        assets.sort_unstable();
+        assets.dedup();
+
+        // The types wanted by any account, each one keeps its own
+        let mut types: Vec<PriceType> = Vec::new();
+        for price_type in self.accounts.values().flat_map(|account| account.price_types()) {
+            if !types.contains(price_type) {
+                types.push(price_type.clone());
+            }
+        }
+        // Snapshots unless an account asked for the latest endpoints
+        let source = match self.accounts.values().any(|account| account.price_source() == PriceSource::Latest) {
+            true => PriceSource::Latest,
+            false => PriceSource::Snapshots,
+        };
+        let fetch = crate::alpaca_wrapper::fetch_prices(self.data.as_ref(), source, &assets, &types);
+        let prices: HashMap<String, HashMap<PriceType, Value>> =
+            match self.runtime.block_on(fetch) {
+                Ok(prices) => prices,
+                Err(e) => {
+                    log::error!("Failed to update shared prices: {}", e);
+                    return;
+                }
+            };
+
+        for account in self.accounts.values() {
+            account.apply_prices(prices.clone());
+        }
+    }
+
+    /// Failed accounts are logged and keep their previous positions.
+    pub fn update_positions(&self) {
+        for (label, account) in &self.accounts {
+            if let Err(e) = account.update_positions() {
+                log::error!("Failed to update the positions of {}: {}", label, e);
+            }
+        }
+    }
+
+    /// Failed accounts are logged and keep their previous cash.
+    pub fn update_cash(&self) {
+        for (label, account) in &self.accounts {
+            if let Err(e) = account.update_cash() {
+                log::error!("Failed to update the cash of {}: {}", label, e);
+            }
+        }
+    }
+
+    pub fn manage_buy_signal(&self, label: &str, ticker: &str) -> Result<Option<Value>, AlpacaError> {
+        Ok(self.route(label)?.manage_buy_signal(ticker))
+    }
+
+    pub fn manage_sell_signal(&self, label: &str, ticker: &str) -> Result<Option<Value>, AlpacaError> {
+        Ok(self.route(label)?.manage_sell_signal(ticker))
+    }
+
+    pub fn total_equity_all(&self) -> f64 {
+        self.accounts.values().map(AlpacaWrapper::equity).sum()
+    }
+
+    pub fn positions_by_account(&self) -> BTreeMap<String, HashMap<String, Position>> {
+        self.accounts.iter()
+            .map(|(label, account)| (label.clone(), account.positions()))
+            .collect()
+    }
+}
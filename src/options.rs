@@ -0,0 +1,142 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Option contract data: the snapshots of the options data API, with their
+//! greeks, and the OCC symbols naming the contracts.
+
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{AlpacaError, Quote, Trade};
+
+/// Greeks of a contract as computed by the data API. Contracts without
+/// recent quotes come without some or all of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OptionGreeks {
+    #[serde(default)]
+    pub delta: Option<f64>,
+    #[serde(default)]
+    pub gamma: Option<f64>,
+    #[serde(default)]
+    pub theta: Option<f64>,
+    #[serde(default)]
+    pub vega: Option<f64>,
+    #[serde(default)]
+    pub rho: Option<f64>,
+}
+
+impl OptionGreeks {
+    pub fn is_empty(&self) -> bool {
+        [self.delta, self.gamma, self.theta, self.vega, self.rho].iter().all(Option::is_none)
+    }
+}
+
+/// Snapshot of a contract as returned by `/v1beta1/options/snapshots`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionSnapshot {
+    #[serde(default)]
+    pub latest_trade: Option<Trade>,
+    #[serde(default)]
+    pub latest_quote: Option<Quote>,
+    #[serde(default)]
+    pub greeks: Option<OptionGreeks>,
+    #[serde(default)]
+    pub implied_volatility: Option<f64>,
+}
+
+impl OptionSnapshot {
+    /// Whether the snapshot carries at least one greek.
+    pub fn has_greeks(&self) -> bool {
+        self.greeks.as_ref().is_some_and(|greeks| !greeks.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    Call,
+    Put,
+}
+
+/// Components of an OCC contract symbol such as `AAPL240419C00170000`:
+/// the underlying, the expiration as `YYMMDD`, `C` or `P` and the strike
+/// in thousandths of a dollar over 8 digits.
+///
+/// `Display` gives back the symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccSymbol {
+    pub underlying: String,
+    pub expiration: NaiveDate,
+    pub option_type: OptionType,
+    pub strike: f64,
+}
+
+impl fmt::Display for OccSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{:08}",
+            self.underlying,
+            self.expiration.format("%y%m%d"),
+            match self.option_type {
+                OptionType::Call => 'C',
+                OptionType::Put => 'P',
+            },
+            (self.strike * 1000.0).round() as u64
+        )
+    }
+}
+
+/// Splits an OCC contract symbol into its components. Padded symbols, with
+/// spaces after a short underlying, are accepted too.
+pub fn parse_occ_symbol(symbol: &str) -> Result<OccSymbol, AlpacaError> {
+    let invalid = || AlpacaError::InvalidParameter(format!("invalid OCC symbol {:?}", symbol));
+
+    // Date, type and strike take the last 15 characters
+    let split = symbol.len().checked_sub(15).filter(|&split| symbol.is_char_boundary(split)).ok_or_else(invalid)?;
+    let (underlying, contract) = symbol.split_at(split);
+    let underlying = underlying.trim_end();
+    if underlying.is_empty()
+        || underlying.len() > 6
+        || !underlying.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    // Sliced by bytes below
+    if !contract.is_ascii() {
+        return Err(invalid());
+    }
+    let (date, rest) = contract.split_at(6);
+    let (option_type, strike) = rest.split_at(1);
+    if !date.chars().chain(strike.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    Ok(OccSymbol {
+        underlying: underlying.to_string(),
+        expiration: NaiveDate::parse_from_str(date, "%y%m%d").map_err(|_| invalid())?,
+        option_type: match option_type {
+            "C" => OptionType::Call,
+            "P" => OptionType::Put,
+            _ => return Err(invalid()),
+        },
+        strike: strike.parse::<u64>().map_err(|_| invalid())? as f64 / 1000.0,
+    })
+}
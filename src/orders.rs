@@ -0,0 +1,734 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{AlpacaError, Sort, is_crypto};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    Market,
+    Limit,
+    Stop,
+    StopLimit,
+    TrailingStop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    #[default]
+    Day,
+    Gtc,
+    Ioc,
+    Fok,
+    /// Executes in the opening auction, see `auction_order_accepted` for
+    /// when it can be submitted.
+    Opg,
+    /// Executes in the closing auction.
+    Cls,
+}
+
+macro_rules! order_statuses {
+    ($($variant:ident => $name:literal),* $(,)?) => {
+        /// Status of an order, with the spelling of the API.
+        ///
+        /// Statuses added to the API later land in `Unknown` instead of
+        /// failing the deserialization.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum OrderStatus {
+            $($variant,)*
+            Unknown(String),
+        }
+
+        impl OrderStatus {
+            pub const ALL: &[OrderStatus] = &[$(OrderStatus::$variant),*];
+
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $name,)*
+                    Self::Unknown(status) => status,
+                }
+            }
+        }
+
+        impl From<&str> for OrderStatus {
+            fn from(status: &str) -> Self {
+                match status {
+                    $($name => Self::$variant,)*
+                    other => Self::Unknown(other.to_string()),
+                }
+            }
+        }
+    };
+}
+
+order_statuses! {
+    New => "new",
+    PartiallyFilled => "partially_filled",
+    Filled => "filled",
+    DoneForDay => "done_for_day",
+    Canceled => "canceled",
+    Expired => "expired",
+    Replaced => "replaced",
+    PendingCancel => "pending_cancel",
+    PendingReplace => "pending_replace",
+    Accepted => "accepted",
+    PendingNew => "pending_new",
+    AcceptedForBidding => "accepted_for_bidding",
+    Stopped => "stopped",
+    Rejected => "rejected",
+    Suspended => "suspended",
+    Calculated => "calculated",
+}
+
+impl OrderStatus {
+    /// The order will not change anymore.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Filled | Self::Canceled | Self::Expired | Self::Replaced | Self::Rejected)
+    }
+
+    /// The order can still get (more) fills.
+    pub fn is_fillable(&self) -> bool {
+        matches!(
+            self,
+            Self::New | Self::PartiallyFilled | Self::Accepted | Self::PendingNew
+                | Self::AcceptedForBidding | Self::Stopped | Self::Calculated
+                | Self::PendingReplace | Self::DoneForDay
+        )
+    }
+
+    /// A cancel request can still be accepted.
+    pub fn is_cancelable(&self) -> bool {
+        self.is_fillable() && !matches!(self, Self::PendingReplace)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for OrderStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderStatus {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+/// Order quantity, sent as the decimal string Alpaca uses for them.
+///
+/// Anything but a positive finite decimal, e.g. `"0"`, `"-1"` or a NaN
+/// float, is refused with `AlpacaError::InvalidParameter` before the
+/// order is sent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Qty(String);
+
+impl Qty {
+    /// Validated quantity from a decimal string such as `"0.531"`.
+    pub fn parse(qty: &str) -> Result<Self, AlpacaError> {
+        let qty = Self(qty.trim().to_string());
+        qty.validate()?;
+        Ok(qty)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// NaN for an invalid quantity.
+    pub fn as_f64(&self) -> f64 {
+        self.0.parse().unwrap_or(f64::NAN)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), AlpacaError> {
+        let digits = self.0.chars().filter(char::is_ascii_digit).count();
+        let dots = self.0.matches('.').count();
+        let decimal = digits > 0 && dots <= 1 && digits + dots == self.0.len();
+
+        if decimal && self.as_f64() > 0.0 {
+            Ok(())
+        } else {
+            Err(AlpacaError::InvalidParameter(
+                format!("qty must be a positive decimal number, got {}", self.0)
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Qty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+macro_rules! qty_from_display {
+    ($($from:ty),*) => {
+        $(impl From<$from> for Qty {
+            fn from(qty: $from) -> Self {
+                Self(qty.to_string())
+            }
+        })*
+    };
+}
+
+qty_from_display!(i32, i64, u32, u64);
+
+/// At most the 9 decimals Alpaca accepts, without trailing zeros, so
+/// that `0.1 + 0.2` is sent as `"0.3"`.
+impl From<f64> for Qty {
+    fn from(qty: f64) -> Self {
+        let text = format!("{:.9}", qty);
+        match text.contains('.') {
+            true => Self(text.trim_end_matches('0').trim_end_matches('.').to_string()),
+            false => Self(text),
+        }
+    }
+}
+
+impl From<&str> for Qty {
+    fn from(qty: &str) -> Self {
+        Self(qty.trim().to_string())
+    }
+}
+
+impl From<String> for Qty {
+    fn from(qty: String) -> Self {
+        Self::from(qty.as_str())
+    }
+}
+
+/// Whether an options leg opens or closes a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionIntent {
+    BuyToOpen,
+    BuyToClose,
+    SellToOpen,
+    SellToClose,
+}
+
+/// Leg of a multi-leg options order, see `OrderRequest::multi_leg`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OptionLeg {
+    /// OCC symbol of the contract, e.g. `AAPL250620C00200000`.
+    pub symbol: String,
+    /// Contracts of the leg per unit of the order `qty`.
+    #[serde(with = "crate::utils::serde_num::string_or_number")]
+    pub ratio_qty: u32,
+    pub side: OrderSide,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_intent: Option<PositionIntent>,
+}
+
+impl OptionLeg {
+    pub fn new(symbol: &str, ratio_qty: u32, side: OrderSide) -> Self {
+        Self { symbol: symbol.to_string(), ratio_qty, side, position_intent: None }
+    }
+
+    pub fn position_intent(mut self, position_intent: PositionIntent) -> Self {
+        self.position_intent = Some(position_intent);
+        self
+    }
+}
+
+/// Body of `POST /v2/orders`.
+///
+/// The constructors make sure the prices required by each order type
+/// are present, the optional fields are set with the chained setters.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub qty: Qty,
+    pub side: OrderSide,
+    #[serde(rename = "type")]
+    pub order_type: OrderType,
+    pub time_in_force: TimeInForce,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    /// Legs of a multi-leg order, sent with `order_class` mleg and without
+    /// the `symbol` and `side` of the order itself.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub legs: Vec<OptionLeg>,
+}
+
+impl OrderRequest {
+    fn new(symbol: &str, qty: impl Into<Qty>, side: OrderSide, order_type: OrderType) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            qty: qty.into(),
+            side,
+            order_type,
+            time_in_force: TimeInForce::default(),
+            limit_price: None,
+            stop_price: None,
+            client_order_id: None,
+            legs: Vec::new(),
+        }
+    }
+
+    fn check_price(name: &str, price: f64) -> Result<(), AlpacaError> {
+        if price.is_finite() && price > 0.0 {
+            Ok(())
+        } else {
+            Err(AlpacaError::InvalidParameter(
+                format!("{} must be a positive number, got {}", name, price)
+            ))
+        }
+    }
+
+    fn check_stop_limit(side: OrderSide, stop_price: f64, limit_price: f64) -> Result<(), AlpacaError> {
+        let sane = match side {
+            OrderSide::Buy => limit_price >= stop_price,
+            OrderSide::Sell => limit_price <= stop_price,
+        };
+        if sane {
+            Ok(())
+        } else {
+            Err(AlpacaError::InvalidParameter(format!(
+                "{:?} stop_limit with limit_price {} on the wrong side of stop_price {}",
+                side, limit_price, stop_price
+            )))
+        }
+    }
+
+    pub fn market(symbol: &str, qty: impl Into<Qty>, side: OrderSide) -> Self {
+        Self::new(symbol, qty, side, OrderType::Market)
+    }
+
+    /// Market order of the opening auction.
+    pub fn market_on_open(symbol: &str, qty: impl Into<Qty>, side: OrderSide) -> Self {
+        Self::market(symbol, qty, side).time_in_force(TimeInForce::Opg)
+    }
+
+    /// Market order of the closing auction.
+    pub fn market_on_close(symbol: &str, qty: impl Into<Qty>, side: OrderSide) -> Self {
+        Self::market(symbol, qty, side).time_in_force(TimeInForce::Cls)
+    }
+
+    pub fn limit(symbol: &str, qty: impl Into<Qty>, side: OrderSide, limit_price: f64) -> Result<Self, AlpacaError> {
+        Self::check_price("limit_price", limit_price)?;
+        Ok(Self { limit_price: Some(limit_price), ..Self::new(symbol, qty, side, OrderType::Limit) })
+    }
+
+    pub fn stop(symbol: &str, qty: impl Into<Qty>, side: OrderSide, stop_price: f64) -> Result<Self, AlpacaError> {
+        Self::check_price("stop_price", stop_price)?;
+        Ok(Self { stop_price: Some(stop_price), ..Self::new(symbol, qty, side, OrderType::Stop) })
+    }
+
+    /// Order that becomes a limit order at `limit_price` once the market
+    /// reaches `stop_price`.
+    ///
+    /// A buy triggers when the price rises to the stop, so its limit can't
+    /// be below the stop; a sell triggers on the way down, so its limit
+    /// can't be above the stop. Either mistake would leave the order
+    /// resting unfillable right after triggering.
+    pub fn stop_limit(
+        symbol: &str,
+        qty: impl Into<Qty>,
+        side: OrderSide,
+        stop_price: f64,
+        limit_price: f64,
+    ) -> Result<Self, AlpacaError> {
+        Self::check_price("stop_price", stop_price)?;
+        Self::check_price("limit_price", limit_price)?;
+        Self::check_stop_limit(side, stop_price, limit_price)?;
+
+        Ok(Self {
+            stop_price: Some(stop_price),
+            limit_price: Some(limit_price),
+            ..Self::new(symbol, qty, side, OrderType::StopLimit)
+        })
+    }
+
+    /// Options order of 2 to 4 `legs` executed together, `qty` times the
+    /// ratio of each leg.
+    ///
+    /// Only market and limit orders are taken, the limit with the net
+    /// price of the legs: positive for a debit and negative for a credit.
+    /// The order keeps the side of its first leg and an empty symbol,
+    /// neither of them is sent.
+    pub fn multi_leg(
+        legs: Vec<OptionLeg>,
+        qty: impl Into<Qty>,
+        order_type: OrderType,
+        limit_price: Option<f64>,
+    ) -> Result<Self, AlpacaError> {
+        let invalid = |message: String| Err(AlpacaError::InvalidParameter(message));
+
+        if !(2..=4).contains(&legs.len()) {
+            return invalid(format!("a multi-leg order takes 2 to 4 legs, got {}", legs.len()));
+        }
+        if let Some(leg) = legs.iter().find(|leg| leg.ratio_qty == 0) {
+            return invalid(format!("ratio_qty of the {} leg must be a positive integer", leg.symbol));
+        }
+
+        match (order_type, limit_price) {
+            (OrderType::Market, None) => {},
+            (OrderType::Limit, Some(price)) if price.is_finite() => {},
+            (OrderType::Limit, price) => {
+                return invalid(format!("a multi-leg limit order needs a finite limit_price, got {:?}", price));
+            },
+            (OrderType::Market, Some(_)) => return invalid("a multi-leg market order takes no limit_price".to_string()),
+            (other, _) => return invalid(format!("a multi-leg order can't be {:?}, only market or limit", other)),
+        }
+
+        let side = legs[0].side;
+        Ok(Self { limit_price, legs, ..Self::new("", qty, side, order_type) })
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: &str) -> Self {
+        self.client_order_id = Some(client_order_id.to_string());
+        self
+    }
+
+    /// JSON body in the shape expected by `make_request`, refusing an
+    /// invalid `qty`.
+    pub(crate) fn body(&self) -> Result<HashMap<String, Value>, AlpacaError> {
+        self.qty.validate()?;
+        let mut body: HashMap<String, Value> = serde_json::from_value(serde_json::to_value(self)?)?;
+        if !self.legs.is_empty() {
+            body.remove("symbol");
+            body.remove("side");
+            body.insert("order_class".to_string(), Value::from("mleg"));
+        }
+        Ok(body)
+    }
+}
+
+/// Body of `PATCH /v2/orders/{id}`, built with `ReplaceOrderRequest::builder`
+/// so that it has been checked against the order it replaces.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplaceOrderRequest {
+    #[serde(skip)]
+    order_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    qty: Option<Qty>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_in_force: Option<TimeInForce>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_order_id: Option<String>,
+}
+
+impl ReplaceOrderRequest {
+    /// Starts a replacement of `order`, the current snapshot of the order.
+    pub fn builder(order: &Order) -> ReplaceOrderBuilder<'_> {
+        ReplaceOrderBuilder {
+            order,
+            qty: None,
+            time_in_force: None,
+            limit_price: None,
+            stop_price: None,
+            client_order_id: None,
+        }
+    }
+
+    pub fn order_id(&self) -> &str {
+        &self.order_id
+    }
+
+    pub fn qty(&self) -> Option<&Qty> {
+        self.qty.as_ref()
+    }
+
+    pub fn time_in_force(&self) -> Option<TimeInForce> {
+        self.time_in_force
+    }
+
+    pub fn limit_price(&self) -> Option<f64> {
+        self.limit_price
+    }
+
+    pub fn stop_price(&self) -> Option<f64> {
+        self.stop_price
+    }
+
+    pub(crate) fn body(&self) -> Result<HashMap<String, Value>, AlpacaError> {
+        Ok(serde_json::from_value(serde_json::to_value(self)?)?)
+    }
+}
+
+/// Changes to an order, validated against it by `build`.
+#[derive(Debug, Clone)]
+pub struct ReplaceOrderBuilder<'a> {
+    order: &'a Order,
+    qty: Option<Qty>,
+    time_in_force: Option<TimeInForce>,
+    limit_price: Option<f64>,
+    stop_price: Option<f64>,
+    client_order_id: Option<String>,
+}
+
+impl ReplaceOrderBuilder<'_> {
+    pub fn qty(mut self, qty: impl Into<Qty>) -> Self {
+        self.qty = Some(qty.into());
+        self
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = Some(time_in_force);
+        self
+    }
+
+    pub fn limit_price(mut self, limit_price: f64) -> Self {
+        self.limit_price = Some(limit_price);
+        self
+    }
+
+    pub fn stop_price(mut self, stop_price: f64) -> Self {
+        self.stop_price = Some(stop_price);
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: &str) -> Self {
+        self.client_order_id = Some(client_order_id.to_string());
+        self
+    }
+
+    /// Checks the changes against the order, rejecting locally what the
+    /// server would answer with a 422:
+    /// - an order that can no longer be replaced,
+    /// - a request that changes nothing,
+    /// - a qty below what already filled,
+    /// - a price field the order type doesn't have, or a stop-limit with
+    ///   its limit on the wrong side of the stop,
+    /// - a crypto order moved to a time in force other than gtc or ioc.
+    pub fn build(self) -> Result<ReplaceOrderRequest, AlpacaError> {
+        let order = self.order;
+        let invalid = |message: String| Err(AlpacaError::InvalidParameter(message));
+
+        if !order.status.is_cancelable() {
+            return invalid(format!("order {} is {} and can't be replaced", order.id, order.status));
+        }
+
+        if self.qty.is_none() && self.time_in_force.is_none() && self.limit_price.is_none()
+            && self.stop_price.is_none() && self.client_order_id.is_none() {
+            return invalid(format!("replacing order {} without any change", order.id));
+        }
+
+        if let Some(qty) = &self.qty {
+            qty.validate()?;
+            let filled = order.filled_qty.unwrap_or(0.0);
+            if qty.as_f64() < filled {
+                return invalid(format!("qty {} is below the {} already filled", qty, filled));
+            }
+        }
+
+        let has_limit = matches!(order.order_type, OrderType::Limit | OrderType::StopLimit);
+        let has_stop = matches!(order.order_type, OrderType::Stop | OrderType::StopLimit);
+        if let Some(limit_price) = self.limit_price {
+            if !has_limit {
+                return invalid(format!("a {:?} order has no limit_price", order.order_type));
+            }
+            OrderRequest::check_price("limit_price", limit_price)?;
+        }
+        if let Some(stop_price) = self.stop_price {
+            if !has_stop {
+                return invalid(format!("a {:?} order has no stop_price", order.order_type));
+            }
+            OrderRequest::check_price("stop_price", stop_price)?;
+        }
+
+        if order.order_type == OrderType::StopLimit {
+            let limit_price = self.limit_price.or(order.limit_price);
+            let stop_price = self.stop_price.or(order.stop_price);
+            if let (Some(limit_price), Some(stop_price)) = (limit_price, stop_price) {
+                OrderRequest::check_stop_limit(order.side, stop_price, limit_price)?;
+            }
+        }
+
+        if let Some(time_in_force) = self.time_in_force {
+            if is_crypto(&order.symbol) && !matches!(time_in_force, TimeInForce::Gtc | TimeInForce::Ioc) {
+                return invalid(format!("crypto orders only take gtc or ioc, got {:?}", time_in_force));
+            }
+        }
+
+        Ok(ReplaceOrderRequest {
+            order_id: order.id.clone(),
+            qty: self.qty,
+            time_in_force: self.time_in_force,
+            limit_price: self.limit_price,
+            stop_price: self.stop_price,
+            client_order_id: self.client_order_id,
+        })
+    }
+}
+
+/// Query parameters of `GET /v2/orders`.
+#[derive(Debug, Clone, Default)]
+pub struct ListOrdersParams {
+    /// `open`, `closed` or `all`, the server default is `open`.
+    pub status: Option<String>,
+    pub limit: Option<u32>,
+    pub after: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub direction: Option<Sort>,
+    /// Return the legs of bracket, OCO and OTO orders nested in their parent.
+    pub nested: bool,
+    pub symbols: Vec<String>,
+}
+
+impl ListOrdersParams {
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let format = |ts: &DateTime<Utc>| ts.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+
+        let mut query = Vec::new();
+        if let Some(status) = &self.status {
+            query.push(("status", status.clone()));
+        }
+        if let Some(limit) = self.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(after) = &self.after {
+            query.push(("after", format(after)));
+        }
+        if let Some(until) = &self.until {
+            query.push(("until", format(until)));
+        }
+        if let Some(direction) = &self.direction {
+            query.push(("direction", direction.to_string()));
+        }
+        if self.nested {
+            query.push(("nested", "true".to_string()));
+        }
+        if !self.symbols.is_empty() {
+            query.push(("symbols", self.symbols.join(",")));
+        }
+        query
+    }
+}
+
+/// Role of a child leg of a bracket or OCO order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrderClassRole {
+    TakeProfit,
+    StopLoss,
+}
+
+/// Order as returned by the orders endpoints.
+///
+/// Legs of bracket, OCO and OTO orders are only present when the order
+/// was requested with `nested`. Those of a multi-leg options order, one
+/// child order per contract, always are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub id: String,
+    pub client_order_id: String,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "crate::utils::opt_utc_timestamp", default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::utils::opt_utc_timestamp", default)]
+    pub submitted_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::utils::opt_utc_timestamp", default)]
+    pub filled_at: Option<DateTime<Utc>>,
+    #[serde(with = "crate::utils::opt_utc_timestamp", default)]
+    pub canceled_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub asset_id: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub order_class: String,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub qty: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub notional: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub filled_qty: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub filled_avg_price: Option<f64>,
+    #[serde(rename = "type")]
+    pub order_type: OrderType,
+    pub side: OrderSide,
+    pub time_in_force: TimeInForce,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub limit_price: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub stop_price: Option<f64>,
+    pub status: OrderStatus,
+    #[serde(default)]
+    pub extended_hours: bool,
+    /// Contracts per unit of the parent, for the legs of a multi-leg order.
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub ratio_qty: Option<u32>,
+    #[serde(default)]
+    pub position_intent: Option<PositionIntent>,
+    #[serde(default)]
+    pub legs: Option<Vec<Order>>,
+}
+
+impl Order {
+    /// Finds the child leg playing `role`, looking into the nested legs
+    /// recursively.
+    ///
+    /// Legs carry no explicit role, so it is derived from their type: the
+    /// take-profit leg is the limit order and the stop-loss leg is the
+    /// stop or stop-limit one.
+    pub fn find_leg(&self, role: OrderClassRole) -> Option<&Order> {
+        let matches = |leg: &Order| match role {
+            OrderClassRole::TakeProfit => leg.order_type == OrderType::Limit,
+            OrderClassRole::StopLoss => {
+                matches!(leg.order_type, OrderType::Stop | OrderType::StopLimit)
+            }
+        };
+
+        self.legs.iter().flatten().find_map(|leg| {
+            if matches(leg) { Some(leg) } else { leg.find_leg(role) }
+        })
+    }
+}
+
+/// How a `cancel_and_confirm` call ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CancelOutcome {
+    /// The order is gone without any fill.
+    Canceled,
+    /// The order filled, fully or partially, before the cancel took effect,
+    /// so the shares are now held.
+    FilledInstead { filled_qty: f64, avg_price: Option<f64> },
+}
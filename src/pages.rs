@@ -0,0 +1,106 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Page sources of the paginated data endpoints.
+
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::{AlpacaClient, AlpacaError};
+
+/// Pages of a paginated endpoint, following `next_page_token`.
+///
+/// The sequential source requests a page when asked for it. The prefetching
+/// one requests the pages from a background task, at most `capacity` pages
+/// ahead of the consumer, so the next request is in flight while the
+/// current page is processed. Dropping it cancels the pending requests.
+pub(crate) enum Pages<'a> {
+    Sequential {
+        client: &'a AlpacaClient,
+        endpoint: &'static str,
+        query: &'a [(&'a str, String)],
+        page_token: Option<String>,
+        max_size: Option<usize>,
+        done: bool,
+    },
+    Prefetch {
+        pages: mpsc::Receiver<Result<Value, AlpacaError>>,
+        task: JoinHandle<()>,
+    },
+}
+
+fn next_page_token(page: &Value) -> Option<String> {
+    page.get("next_page_token").and_then(Value::as_str).map(str::to_string)
+}
+
+impl<'a> Pages<'a> {
+    pub(crate) fn new(
+        client: &'a AlpacaClient,
+        endpoint: &'static str,
+        query: &'a [(&'a str, String)],
+        page_token: Option<String>,
+        max_size: Option<usize>,
+    ) -> Self {
+        if client.prefetch_pages == 0 {
+            return Pages::Sequential { client, endpoint, query, page_token, max_size, done: false };
+        }
+
+        let (sender, pages) = mpsc::channel(client.prefetch_pages);
+        let client = client.clone();
+        let query: Vec<(String, String)> = query.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+
+        let task = tokio::spawn(async move {
+            let query: Vec<(&str, String)> = query.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+            let mut page_token = page_token;
+
+            loop {
+                let page = client.fetch_page(endpoint, &query, page_token.as_deref(), max_size).await;
+                page_token = page.as_ref().ok().and_then(next_page_token);
+                let last = page.is_err() || page_token.is_none();
+
+                // A closed channel means the consumer is done
+                if sender.send(page).await.is_err() || last {
+                    return;
+                }
+            }
+        });
+
+        Pages::Prefetch { pages, task }
+    }
+
+    /// The next page, `None` after the last one or after an error.
+    pub(crate) async fn next(&mut self) -> Option<Result<Value, AlpacaError>> {
+        match self {
+            Pages::Sequential { done: true, .. } => None,
+            Pages::Sequential { client, endpoint, query, page_token, max_size, done } => {
+                let page = client.fetch_page(endpoint, query, page_token.as_deref(), *max_size).await;
+                *page_token = page.as_ref().ok().and_then(next_page_token);
+                *done = page.is_err() || page_token.is_none();
+                Some(page)
+            },
+            Pages::Prefetch { pages, .. } => pages.recv().await,
+        }
+    }
+}
+
+impl Drop for Pages<'_> {
+    fn drop(&mut self) {
+        if let Pages::Prefetch { task, .. } = self {
+            task.abort();
+        }
+    }
+}
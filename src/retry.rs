@@ -0,0 +1,157 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Method aware retry policy for the client requests.
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use reqwest::Method;
+use tokio::time::Instant;
+
+use crate::AlpacaError;
+
+/// Decides which failed requests `AlpacaClient` sends again.
+///
+/// Only the transient failures (timeouts, connection errors, 429 and 5xx)
+/// are retried, and only for the methods in `retry_methods`. Repeating a
+/// POST or PATCH can duplicate an order, so they are left out by default
+/// and never retried without a `client_order_id`, even when listed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each following one.
+    pub backoff: Duration,
+    pub retry_methods: Vec<Method>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            backoff: Duration::from_millis(200),
+            retry_methods: vec![Method::GET, Method::DELETE],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self { max_retries: 0, ..Default::default() }
+    }
+
+    /// POST and PATCH aren't, sending them twice can create two orders.
+    pub fn is_idempotent(method: &Method) -> bool {
+        !matches!(*method, Method::POST | Method::PATCH)
+    }
+
+    /// Whether `method` requests are retried, `opt_in` being the per
+    /// request override for the non idempotent methods, which are only
+    /// retried `keyed` with a `client_order_id`.
+    pub fn retries_method(&self, method: &Method, opt_in: bool, keyed: bool) -> bool {
+        (opt_in || self.retry_methods.contains(method)) && (keyed || Self::is_idempotent(method))
+    }
+
+    /// Same as `AlpacaError::is_retryable`.
+    pub fn is_transient(error: &AlpacaError) -> bool {
+        error.is_retryable()
+    }
+
+    /// Whether to send again a request that failed with `error` on its
+    /// `attempt`-th try, counting from 1.
+    pub fn should_retry(&self, method: &Method, opt_in: bool, keyed: bool, error: &AlpacaError, attempt: u32) -> bool {
+        attempt <= self.max_retries
+            && self.retries_method(method, opt_in, keyed)
+            && Self::is_transient(error)
+    }
+
+    /// Delay before retrying after the `attempt`-th try.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+    }
+}
+
+/// Overall budget of the operation in progress, attempts, backoff sleeps
+/// and body reads included, see `AlpacaClient::within`.
+#[derive(Debug)]
+pub(crate) struct Deadline {
+    at: Instant,
+    budget: Duration,
+    // Requests sent under it so far
+    attempts: AtomicU32,
+    // Failure of the last attempt, reported if the deadline cuts the next
+    last: Mutex<Option<AlpacaError>>,
+}
+
+tokio::task_local! {
+    static DEADLINE: Arc<Deadline>;
+}
+
+impl Deadline {
+    /// Time left of the deadline in scope, `None` without one.
+    pub(crate) fn remaining() -> Option<Duration> {
+        DEADLINE.try_with(|deadline| deadline.at.saturating_duration_since(Instant::now())).ok()
+    }
+
+    pub(crate) fn add_attempt() {
+        let _ = DEADLINE.try_with(|deadline| deadline.attempts.fetch_add(1, Ordering::Relaxed));
+    }
+
+    /// Keeps `error` as the last failure until the next attempt.
+    pub(crate) fn set_last(error: AlpacaError) {
+        let _ = DEADLINE.try_with(|deadline| *deadline.last.lock().unwrap() = Some(error));
+    }
+
+    /// `AlpacaError::DeadlineExceeded` of the deadline in scope, with
+    /// `last` or the failure kept by `set_last`.
+    pub(crate) fn exceeded(last: Option<AlpacaError>) -> AlpacaError {
+        DEADLINE.try_with(|deadline| deadline.error(last))
+            .unwrap_or_else(|_| AlpacaError::Other("no deadline in scope".to_string()))
+    }
+
+    fn error(&self, last: Option<AlpacaError>) -> AlpacaError {
+        AlpacaError::DeadlineExceeded {
+            budget: self.budget,
+            attempts: self.attempts.load(Ordering::Relaxed),
+            last: last.or_else(|| self.last.lock().unwrap().take()).map(Box::new),
+        }
+    }
+
+    /// Runs `operation` with `budget` from now as its deadline, unless the
+    /// deadline already in scope ends first. The operation is dropped when
+    /// its deadline passes.
+    pub(crate) async fn scope<T, F>(budget: Option<Duration>, operation: F) -> Result<T, AlpacaError>
+    where
+        F: Future<Output = Result<T, AlpacaError>>,
+    {
+        let Some(budget) = budget.filter(|budget| Self::remaining().is_none_or(|remaining| *budget < remaining)) else {
+            return operation.await;
+        };
+
+        let deadline = Arc::new(Deadline {
+            at: Instant::now() + budget,
+            budget,
+            attempts: AtomicU32::new(0),
+            last: Mutex::new(None),
+        });
+        match tokio::time::timeout_at(deadline.at, DEADLINE.scope(deadline.clone(), operation)).await {
+            Ok(result) => result,
+            Err(_) => Err(deadline.error(None)),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Summary of a wrapper session, see `AlpacaWrapper::session_report`.
+//!
+//! The parts the wrapper could not follow, like the orders without a
+//! journal, are `None` and serialize as `"not tracked"`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+
+use crate::{AlpacaError, JournalOutcome, JournalRecord, OrderSide, RequestCounts};
+
+const NOT_TRACKED: &str = "not tracked";
+
+fn tracked<T: Serialize, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => serializer.serialize_str(NOT_TRACKED),
+    }
+}
+
+/// Orders of the session by their last known outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct OrderOutcomes {
+    pub submitted: u64,
+    /// Accepted and neither filled nor canceled yet.
+    pub open: u64,
+    /// Filled in part, open or canceled for the rest.
+    pub partially_filled: u64,
+    pub filled: u64,
+    pub canceled: u64,
+    pub rejected: u64,
+}
+
+impl OrderOutcomes {
+    /// Outcomes of the journal `records` of a session, the last record of
+    /// each order winning. A rejected send has no order id and counts
+    /// alone.
+    pub fn from_records(records: &[JournalRecord]) -> Self {
+        let mut outcomes = Self::default();
+        let mut last: BTreeMap<&str, JournalOutcome> = BTreeMap::new();
+
+        for record in records {
+            match (record.outcome, &record.order_id) {
+                (JournalOutcome::Submitted, _) => outcomes.submitted += 1,
+                (outcome, Some(id)) => { last.insert(id, outcome); },
+                (outcome, None) => outcomes.count(outcome),
+            }
+        }
+        for outcome in last.into_values() {
+            outcomes.count(outcome);
+        }
+
+        outcomes
+    }
+
+    fn count(&mut self, outcome: JournalOutcome) {
+        match outcome {
+            JournalOutcome::Submitted => self.submitted += 1,
+            JournalOutcome::Accepted => self.open += 1,
+            JournalOutcome::PartiallyFilled => self.partially_filled += 1,
+            JournalOutcome::Filled => self.filled += 1,
+            JournalOutcome::Canceled => self.canceled += 1,
+            JournalOutcome::Rejected => self.rejected += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportFill {
+    pub timestamp: DateTime<Utc>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub qty: f64,
+    pub price: Option<f64>,
+    pub order_id: Option<String>,
+}
+
+/// Quantity held of a symbol at the start and the end of the session.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct PositionChange {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionReport {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub strategy: Option<String>,
+    /// From the journal, if any.
+    #[serde(serialize_with = "tracked")]
+    pub orders: Option<OrderOutcomes>,
+    /// Quantity filled of each order, from the journal too.
+    #[serde(serialize_with = "tracked")]
+    pub fills: Option<Vec<ReportFill>>,
+    /// Of the fills seen by the wrapper since `started_at`, whatever the
+    /// market sessions in between.
+    pub realized_pnl: f64,
+    /// Of the positions held at the end, against their entry price.
+    pub unrealized_pnl: f64,
+    pub starting_cash: f64,
+    pub ending_cash: f64,
+    pub positions: BTreeMap<String, PositionChange>,
+    /// From the client, if it counts them.
+    #[serde(serialize_with = "tracked")]
+    pub requests: Option<RequestCounts>,
+}
+
+impl SessionReport {
+    /// Writes the report to `path`, as text for a `.txt` file and as
+    /// pretty printed JSON otherwise.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), AlpacaError> {
+        let path = path.as_ref();
+        let contents = match path.extension().is_some_and(|extension| extension == "txt") {
+            true => self.to_string(),
+            false => serde_json::to_string_pretty(self)?,
+        };
+
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for SessionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_not_tracked = |value: Option<String>| value.unwrap_or_else(|| NOT_TRACKED.to_string());
+
+        writeln!(f, "Session {} - {}", self.started_at.to_rfc3339(), self.ended_at.to_rfc3339())?;
+        if let Some(strategy) = &self.strategy {
+            writeln!(f, "Strategy: {}", strategy)?;
+        }
+
+        writeln!(f, "Orders: {}", or_not_tracked(self.orders.map(|orders| format!(
+            "{} submitted, {} filled, {} partially filled, {} open, {} canceled, {} rejected",
+            orders.submitted, orders.filled, orders.partially_filled, orders.open, orders.canceled, orders.rejected,
+        ))))?;
+        match &self.fills {
+            Some(fills) => {
+                writeln!(f, "Fills: {}", fills.len())?;
+                for fill in fills {
+                    let side = if fill.side == OrderSide::Buy { "buy" } else { "sell" };
+                    let price = fill.price.map_or_else(|| "?".to_string(), |price| format!("{:.2}", price));
+                    writeln!(f, "  {} {} {} {} @ {}", fill.timestamp.to_rfc3339(), side, fill.qty, fill.symbol, price)?;
+                }
+            },
+            None => writeln!(f, "Fills: {}", NOT_TRACKED)?,
+        }
+
+        writeln!(f, "Realized P&L: {:.2}", self.realized_pnl)?;
+        writeln!(f, "Unrealized P&L: {:.2}", self.unrealized_pnl)?;
+        writeln!(f, "Cash: {:.2} -> {:.2}", self.starting_cash, self.ending_cash)?;
+        writeln!(f, "Positions:")?;
+        for (symbol, change) in &self.positions {
+            writeln!(f, "  {}: {} -> {}", symbol, change.start, change.end)?;
+        }
+        writeln!(f, "Requests: {}", or_not_tracked(self.requests.map(|counts| format!(
+            "{} ({} errors, {} retries)", counts.requests, counts.errors, counts.retries,
+        ))))
+    }
+}
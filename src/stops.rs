@@ -0,0 +1,101 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Stop-loss watches checked by `AlpacaWrapper` on every price update.
+
+use std::sync::Mutex;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMode {
+    /// Sell the whole held quantity at market.
+    SellMarket,
+    /// Only emit a `StopEvent`.
+    Alert,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopLoss {
+    pub id: u64,
+    pub symbol: String,
+    pub stop_price: f64,
+    pub mode: StopMode,
+}
+
+/// Emitted once per stop when the price crosses it, after which it is
+/// disarmed. `order` is the sell response in `SellMarket` mode, `None`
+/// when nothing was held or the order failed.
+#[derive(Debug, Clone)]
+pub struct StopEvent {
+    pub stop: StopLoss,
+    pub price: f64,
+    pub order: Option<Value>,
+}
+
+#[derive(Debug, Default)]
+struct Book {
+    next_id: u64,
+    stops: Vec<StopLoss>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StopBook {
+    inner: Mutex<Book>,
+}
+
+impl StopBook {
+    pub(crate) fn arm(&self, symbol: &str, stop_price: f64, mode: StopMode) -> u64 {
+        let mut book = self.inner.lock().unwrap();
+        book.next_id += 1;
+
+        let id = book.next_id;
+        book.stops.push(StopLoss { id, symbol: symbol.to_string(), stop_price, mode });
+        id
+    }
+
+    pub(crate) fn disarm(&self, id: u64) -> bool {
+        let mut book = self.inner.lock().unwrap();
+        let before = book.stops.len();
+        book.stops.retain(|stop| stop.id != id);
+        book.stops.len() != before
+    }
+
+    pub(crate) fn active(&self) -> Vec<StopLoss> {
+        self.inner.lock().unwrap().stops.clone()
+    }
+
+    /// Divides the stop prices of `symbol` by `ratio`, returning how many.
+    pub(crate) fn adjust(&self, symbol: &str, ratio: f64) -> usize {
+        let mut book = self.inner.lock().unwrap();
+        let mut adjusted = 0;
+        for stop in book.stops.iter_mut().filter(|stop| stop.symbol == symbol) {
+            stop.stop_price /= ratio;
+            adjusted += 1;
+        }
+        adjusted
+    }
+
+    /// Disarms and returns the stops of `symbol` at or above `price`.
+    pub(crate) fn take_crossed(&self, symbol: &str, price: f64) -> Vec<StopLoss> {
+        let mut book = self.inner.lock().unwrap();
+        let (crossed, armed) = std::mem::take(&mut book.stops)
+            .into_iter()
+            .partition(|stop| stop.symbol == symbol && price <= stop.stop_price);
+
+        book.stops = armed;
+        crossed
+    }
+}
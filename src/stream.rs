@@ -0,0 +1,283 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Messages of the real-time market data stream.
+//!
+//! The crate has no WebSocket client, `StreamMessage::parse_frame` turns
+//! the text frames read from one into typed messages.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::{AlpacaError, Bar, Quote, Trade};
+
+/// Status code of a `TradingStatus`, covering the CTA and UTP codes that
+/// stop or restart trading.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TradingStatusCode {
+    /// `H`, or `2` on CTA.
+    Halt,
+    /// `P`, a limit up-limit down pause.
+    VolatilityPause,
+    /// `Q`, quoting is back but trading isn't yet.
+    QuotationResumption,
+    /// `T`, or `3` on CTA.
+    Resume,
+    /// Any other code, such as the imbalance indications.
+    Unknown(String),
+}
+
+impl TradingStatusCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Halt => "H",
+            Self::VolatilityPause => "P",
+            Self::QuotationResumption => "Q",
+            Self::Resume => "T",
+            Self::Unknown(code) => code,
+        }
+    }
+
+    /// True until the symbol trades again, quotes may come back before.
+    pub fn is_halted(&self) -> bool {
+        matches!(self, Self::Halt | Self::VolatilityPause | Self::QuotationResumption)
+    }
+}
+
+impl From<String> for TradingStatusCode {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "H" | "2" => Self::Halt,
+            "P" => Self::VolatilityPause,
+            "Q" => Self::QuotationResumption,
+            "T" | "3" => Self::Resume,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<TradingStatusCode> for String {
+    fn from(code: TradingStatusCode) -> Self {
+        code.as_str().to_string()
+    }
+}
+
+/// Reason code of a `TradingStatus`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum HaltReason {
+    /// `T1`
+    NewsPending,
+    /// `T2`
+    NewsReleased,
+    /// `T12`, the listing exchange asked for more information.
+    InformationRequested,
+    /// `LUDP`
+    VolatilityPause,
+    /// `H10`
+    SecSuspension,
+    /// `MWC1` to `MWC3` by their level, `MWC0` for a halt carried over
+    /// from the previous day.
+    MarketWideCircuitBreaker(u8),
+    /// Any other code, empty when the message has none.
+    Unknown(String),
+}
+
+impl HaltReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::NewsPending => "T1",
+            Self::NewsReleased => "T2",
+            Self::InformationRequested => "T12",
+            Self::VolatilityPause => "LUDP",
+            Self::SecSuspension => "H10",
+            Self::MarketWideCircuitBreaker(level) => {
+                ["MWC0", "MWC1", "MWC2", "MWC3"].get(*level as usize).copied().unwrap_or("MWC")
+            },
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<String> for HaltReason {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "T1" => Self::NewsPending,
+            "T2" => Self::NewsReleased,
+            "T12" => Self::InformationRequested,
+            "LUDP" => Self::VolatilityPause,
+            "H10" => Self::SecSuspension,
+            "MWC0" => Self::MarketWideCircuitBreaker(0),
+            "MWC1" => Self::MarketWideCircuitBreaker(1),
+            "MWC2" => Self::MarketWideCircuitBreaker(2),
+            "MWC3" => Self::MarketWideCircuitBreaker(3),
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<HaltReason> for String {
+    fn from(reason: HaltReason) -> Self {
+        reason.as_str().to_string()
+    }
+}
+
+/// `T: "s"` message, sent when a symbol is halted or resumes. Limit
+/// up-limit down pauses come this way too, as `VolatilityPause`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradingStatus {
+    #[serde(rename = "S")]
+    pub symbol: String,
+    #[serde(rename = "sc")]
+    pub status_code: TradingStatusCode,
+    #[serde(rename = "sm", default)]
+    pub status_message: String,
+    #[serde(rename = "rc", default = "no_reason")]
+    pub reason: HaltReason,
+    #[serde(rename = "rm", default)]
+    pub reason_message: String,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+fn no_reason() -> HaltReason {
+    HaltReason::Unknown(String::new())
+}
+
+/// `T: "l"` message, the limit up-limit down price band of a symbol. A
+/// pause at the band arrives as a `TradingStatus`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Luld {
+    #[serde(rename = "S")]
+    pub symbol: String,
+    #[serde(rename = "u")]
+    pub limit_up_price: f64,
+    #[serde(rename = "d")]
+    pub limit_down_price: f64,
+    #[serde(rename = "i", default)]
+    pub indicator: String,
+    #[serde(rename = "t", with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Subscriptions active after a subscribe or unsubscribe, as confirmed by
+/// the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Subscriptions {
+    pub trades: Vec<String>,
+    pub quotes: Vec<String>,
+    pub bars: Vec<String>,
+    pub daily_bars: Vec<String>,
+    pub statuses: Vec<String>,
+    pub lulds: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct WithSymbol<T> {
+    #[serde(rename = "S")]
+    symbol: String,
+    #[serde(flatten)]
+    data: T,
+}
+
+/// The messages as sent, before the symbols are split out.
+#[derive(Deserialize)]
+#[serde(tag = "T")]
+enum Tagged {
+    #[serde(rename = "t")]
+    Trade(WithSymbol<Trade>),
+    #[serde(rename = "q")]
+    Quote(WithSymbol<Quote>),
+    #[serde(rename = "b")]
+    Bar(WithSymbol<Bar>),
+    #[serde(rename = "d")]
+    DailyBar(WithSymbol<Bar>),
+    #[serde(rename = "s")]
+    TradingStatus(TradingStatus),
+    #[serde(rename = "l")]
+    Luld(Luld),
+    #[serde(rename = "subscription")]
+    SubscriptionAck(Subscriptions),
+    #[serde(rename = "success")]
+    Success {
+        #[serde(default)]
+        msg: String,
+    },
+    #[serde(rename = "error")]
+    Error {
+        #[serde(default)]
+        code: u64,
+        #[serde(default)]
+        msg: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// One message of a stream frame, selected by its `T` field.
+///
+/// Message types added to the stream later land in `Unknown` with their
+/// raw JSON, and so do the messages failing to decode, without losing
+/// the rest of their frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamMessage {
+    Trade { symbol: String, trade: Trade },
+    Quote { symbol: String, quote: Quote },
+    Bar { symbol: String, bar: Bar },
+    DailyBar { symbol: String, bar: Bar },
+    TradingStatus(TradingStatus),
+    Luld(Luld),
+    SubscriptionAck(Subscriptions),
+    /// Connection and authentication confirmations.
+    Success { msg: String },
+    Error { code: u64, msg: String },
+    Unknown(Value),
+}
+
+impl StreamMessage {
+    /// Parses a text frame, a JSON array of messages.
+    pub fn parse_frame(frame: &str) -> Result<Vec<Self>, AlpacaError> {
+        Ok(serde_json::from_str(frame)?)
+    }
+
+    fn from_value(value: Value) -> Self {
+        match Tagged::deserialize(&value) {
+            Ok(Tagged::Trade(WithSymbol { symbol, data })) => Self::Trade { symbol, trade: data },
+            Ok(Tagged::Quote(WithSymbol { symbol, data })) => Self::Quote { symbol, quote: data },
+            Ok(Tagged::Bar(WithSymbol { symbol, data })) => Self::Bar { symbol, bar: data },
+            Ok(Tagged::DailyBar(WithSymbol { symbol, data })) => Self::DailyBar { symbol, bar: data },
+            Ok(Tagged::TradingStatus(status)) => Self::TradingStatus(status),
+            Ok(Tagged::Luld(luld)) => Self::Luld(luld),
+            Ok(Tagged::SubscriptionAck(subscriptions)) => Self::SubscriptionAck(subscriptions),
+            Ok(Tagged::Success { msg }) => Self::Success { msg },
+            Ok(Tagged::Error { code, msg }) => Self::Error { code, msg },
+            Ok(Tagged::Unknown) => Self::Unknown(value),
+            Err(e) => {
+                log::warn!("Failed to decode the stream message {}: {}", value, e);
+                Self::Unknown(value)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StreamMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from_value(Value::deserialize(deserializer)?))
+    }
+}
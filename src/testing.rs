@@ -0,0 +1,324 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Wiremock fixtures for the tests of code built on this crate, behind
+//! the `test-util` feature.
+//!
+//! `MockAlpacaServer` answers the account, positions, orders, clock and
+//! latest prices requests with the payloads of the builders below, at a
+//! lower priority than any mock mounted on it afterwards.
+//!
+//! ```
+//! use alpaca_rs::testing::{mock_positions, MockAlpacaServer};
+//! use wiremock::matchers::{method, path};
+//! use wiremock::{Mock, ResponseTemplate};
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let server = MockAlpacaServer::start().await;
+//! Mock::given(method("GET"))
+//!     .and(path("/v2/positions"))
+//!     .respond_with(ResponseTemplate::new(200).set_body_json(mock_positions(2)))
+//!     .mount(server.server())
+//!     .await;
+//!
+//! let client = server.client();
+//! assert_eq!(client.get_positions().await.unwrap().as_array().unwrap().len(), 2);
+//! assert_eq!(client.get_latest_trade("AAPL").await.unwrap().price, server.price());
+//! # });
+//! ```
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::{AlpacaClient, RetryPolicy};
+
+/// Keys passing `AlpacaClient::validate_keys`.
+pub const TEST_API_KEY: &str = "PKTEST12345ABCDEFGHI";
+pub const TEST_API_SECRET: &str = "abcdefghijklmnopqrstuvwxyz1234567890ABCDEFG";
+
+/// Price of every symbol in the latest prices of `MockAlpacaServer`.
+pub const DEFAULT_PRICE: f64 = 100.0;
+
+// Below the default priority of the mocks mounted by the tests
+const DEFAULT_MOCK_PRIORITY: u8 = 10;
+
+/// Symbols of the `mock_positions`, in order.
+const POSITION_SYMBOLS: [&str; 6] = ["AAPL", "MSFT", "GOOG", "AMZN", "NVDA", "TSLA"];
+
+/// Client for the given hosts with the test keys, retrying without
+/// backoff so that the retry tests don't wait.
+pub fn test_client(base_url: &str, data_url: &str) -> AlpacaClient {
+    AlpacaClient::from_parts(base_url, data_url, TEST_API_KEY, TEST_API_SECRET)
+        .unwrap()
+        .with_retry_policy(RetryPolicy { backoff: std::time::Duration::ZERO, ..Default::default() })
+}
+
+/// Active cash account with 100000 of cash and no position.
+pub fn mock_account() -> Value {
+    json!({
+        "id": "test-account-id",
+        "account_number": "TEST123456",
+        "status": "ACTIVE",
+        "currency": "USD",
+        "cash": "100000.00",
+        "buying_power": "100000.00",
+        "equity": "100000.00",
+        "last_equity": "100000.00",
+        "multiplier": "1",
+        "pattern_day_trader": false,
+        "daytrade_count": 0,
+        "daytrading_buying_power": "0",
+        "shorting_enabled": false,
+        "trading_blocked": false,
+        "account_blocked": false
+    })
+}
+
+/// `n` long positions of 10 shares bought at 90 and priced at
+/// `DEFAULT_PRICE`, on distinct symbols up to 6 and `SYM<i>` beyond.
+pub fn mock_positions(n: usize) -> Value {
+    let price = DEFAULT_PRICE;
+    (0..n)
+        .map(|i| {
+            let symbol = POSITION_SYMBOLS.get(i).map_or_else(|| format!("SYM{}", i), |symbol| symbol.to_string());
+            json!({
+                "asset_id": format!("asset-{}", i + 1),
+                "symbol": symbol,
+                "exchange": "NASDAQ",
+                "asset_class": "us_equity",
+                "qty": "10",
+                "qty_available": "10",
+                "side": "long",
+                "avg_entry_price": "90",
+                "cost_basis": "900",
+                "current_price": price.to_string(),
+                "market_value": (price * 10.0).to_string(),
+                "unrealized_pl": (price * 10.0 - 900.0).to_string()
+            })
+        })
+        .collect()
+}
+
+/// Market buy of 10 AAPL in `status`, without fills unless `filled`.
+pub fn mock_order(status: &str) -> Value {
+    let filled = status == "filled";
+    json!({
+        "id": "order-1",
+        "client_order_id": "client-order-1",
+        "created_at": "2024-05-01T14:30:00.123456Z",
+        "updated_at": null,
+        "submitted_at": null,
+        "filled_at": if filled { json!("2024-05-01T14:30:00.5Z") } else { Value::Null },
+        "expired_at": null,
+        "canceled_at": null,
+        "failed_at": null,
+        "asset_id": "b0b6dd9d-8b9b-48a9-ba46-b9d54906e415",
+        "symbol": "AAPL",
+        "asset_class": "us_equity",
+        "notional": null,
+        "qty": "10",
+        "filled_qty": if filled { "10" } else { "0" },
+        "filled_avg_price": if filled { json!(DEFAULT_PRICE.to_string()) } else { Value::Null },
+        "order_class": "simple",
+        "order_type": "market",
+        "type": "market",
+        "side": "buy",
+        "time_in_force": "day",
+        "limit_price": null,
+        "stop_price": null,
+        "status": status,
+        "extended_hours": false,
+        "legs": null
+    })
+}
+
+/// Submitted order echoing the fields of the request, accepted.
+struct EchoOrder;
+
+impl Respond for EchoOrder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let Ok(body) = request.body_json::<Value>() else {
+            return ResponseTemplate::new(422).set_body_json(json!({"message": "invalid order body"}));
+        };
+
+        let mut order = mock_order("accepted");
+        order["created_at"] = json!(Utc::now().to_rfc3339());
+        for field in ["symbol", "qty", "side", "time_in_force", "limit_price", "stop_price"] {
+            if !body[field].is_null() {
+                order[field] = body[field].clone();
+            }
+        }
+        if let Some(order_type) = body["type"].as_str() {
+            order["type"] = json!(order_type);
+            order["order_type"] = json!(order_type);
+        }
+        if let Some(client_order_id) = body["client_order_id"].as_str() {
+            order["client_order_id"] = json!(client_order_id);
+        }
+        ResponseTemplate::new(200).set_body_json(order)
+    }
+}
+
+/// Latest trade, quote and minute bar at `price`, timestamped now.
+fn latest(price: f64) -> (Value, Value, Value) {
+    let now = Utc::now().to_rfc3339();
+    (
+        json!({"p": price, "s": 1, "t": now}),
+        json!({"bp": price, "bs": 1, "ap": price, "as": 1, "t": now}),
+        json!({"o": price, "h": price, "l": price, "c": price, "v": 1, "t": now}),
+    )
+}
+
+/// Latest prices of the `symbols` of the query, by symbol under the key
+/// of their type, or of the symbol of the path.
+struct LatestPrices(f64);
+
+impl Respond for LatestPrices {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let segments: Vec<&str> = request.url.path().split('/').collect();
+        let (trade, quote, bar) = latest(self.0);
+        let (key, price) = match segments.iter().find(|segment| matches!(**segment, "trades" | "quotes" | "bars")) {
+            Some(&"trades") => ("trade", trade),
+            Some(&"quotes") => ("quote", quote),
+            _ => ("bar", bar),
+        };
+
+        // `/v2/stocks/{symbol}/{type}/latest` answers a single symbol
+        if let [_, "v2", "stocks", symbol, _, "latest"] = segments[..] {
+            return ResponseTemplate::new(200).set_body_json(json!({"symbol": symbol, key: price}));
+        }
+
+        let prices: serde_json::Map<String, Value> = symbols(request).into_iter()
+            .map(|symbol| (symbol, price.clone()))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(json!({format!("{}s", key): prices}))
+    }
+}
+
+/// Snapshots of the `symbols` of the query, with the crypto ones under
+/// a `snapshots` key as the server does.
+struct Snapshots(f64);
+
+impl Respond for Snapshots {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let snapshots: serde_json::Map<String, Value> = symbols(request).into_iter()
+            .map(|symbol| {
+                let (trade, quote, bar) = latest(self.0);
+                (symbol, json!({"latestTrade": trade, "latestQuote": quote, "minuteBar": bar}))
+            })
+            .collect();
+
+        if request.url.path().starts_with("/v1beta3/crypto/") {
+            ResponseTemplate::new(200).set_body_json(json!({"snapshots": snapshots}))
+        } else {
+            ResponseTemplate::new(200).set_body_json(snapshots)
+        }
+    }
+}
+
+fn symbols(request: &Request) -> Vec<String> {
+    request.url.query_pairs()
+        .find(|(key, _)| key == "symbols")
+        .map(|(_, symbols)| symbols.split(',').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// `MockServer` serving as both the trading and the data host, answering
+/// with `mock_account`, no positions nor orders, an open clock and every
+/// symbol priced at `DEFAULT_PRICE`. Submitted orders are accepted,
+/// echoing their request.
+pub struct MockAlpacaServer {
+    server: MockServer,
+}
+
+impl MockAlpacaServer {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let mock = |http_method: &str| Mock::given(method(http_method));
+
+        mock("GET").and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(mock_account()))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+        mock("GET").and(path("/v2/positions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(mock_positions(0)))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+        mock("GET").and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+        mock("POST").and(path("/v2/orders"))
+            .respond_with(EchoOrder)
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+
+        let now = Utc::now().fixed_offset();
+        mock("GET").and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "timestamp": now.to_rfc3339(),
+                "is_open": true,
+                "next_open": (now + chrono::Duration::days(1)).to_rfc3339(),
+                "next_close": (now + chrono::Duration::hours(1)).to_rfc3339()
+            })))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+
+        mock("GET").and(path_regex(r"^/v2/stocks/([^/]+/)?(trades|quotes|bars)/latest$"))
+            .respond_with(LatestPrices(DEFAULT_PRICE))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+        mock("GET").and(path_regex(r"^/v1beta3/crypto/us/latest/(trades|quotes|bars)$"))
+            .respond_with(LatestPrices(DEFAULT_PRICE))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+        mock("GET").and(path_regex(r"^(/v2/stocks|/v1beta3/crypto/us)/snapshots$"))
+            .respond_with(Snapshots(DEFAULT_PRICE))
+            .with_priority(DEFAULT_MOCK_PRIORITY)
+            .mount(&server)
+            .await;
+
+        Self { server }
+    }
+
+    /// The underlying server, to mount more mocks or inspect the received
+    /// requests.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    pub fn price(&self) -> f64 {
+        DEFAULT_PRICE
+    }
+
+    /// New `test_client` with both hosts pointed at the server.
+    pub fn client(&self) -> AlpacaClient {
+        test_client(&self.server.uri(), &self.server.uri())
+    }
+}
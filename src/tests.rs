@@ -1,53 +1,13 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::*;
     use serde_json::{json,Value};
     use reqwest::StatusCode;
     use wiremock::{Mock, MockServer, ResponseTemplate};
-    use wiremock::http::{Method, HeaderValue, HeaderMap};
-    use wiremock::matchers::{method, path, header, query_param};
-
-    // Helper function to create a test client with mocked URLs
-    async fn create_test_client(
-        mock_base_url: &str,
-        mock_data_url: &str
-    ) -> AlpacaClient {
-        let api_key = "PKTEST12345ABCDEFGHI";
-        let api_secret = "abcdefghijklmnopqrstuvwxyz1234567890ABCDEFG";
-
-        // Override the account endpoint for client initialization
-        let mock_account_response = json!({
-            "id": "test-account-id",
-            "status": "ACTIVE"
-        });
-
-        let mut headers = HeaderMap::with_capacity(3);
-        headers.insert(
-            "APCA-API-KEY-ID",
-            HeaderValue::from_str(api_key).unwrap(),
-        );
-        headers.insert(
-            "APCA-API-SECRET-KEY",
-            HeaderValue::from_str(api_secret).unwrap(),
-        );
-        headers.insert(
-            "CONTENT_TYPE",
-            HeaderValue::from_static("application/json"),
-        );
-
-        let client = reqwest::Client::builder().build().unwrap();
-
-        // We need to create a client manually since we're not calling the real API
-        let alpaca = AlpacaClient {
-            base_url: mock_base_url.to_string(),
-            data_url: mock_data_url.to_string(),
-            headers,
-            client,
-            info: mock_account_response,
-        };
-
-        alpaca
-    }
+    use wiremock::http::Method;
+    use wiremock::matchers::{method, path, header, query_param, query_param_is_missing};
+    use crate::testing::{mock_account, mock_order, mock_positions, test_client, MockAlpacaServer};
 
     #[tokio::test]
     async fn test_validate_keys() {
@@ -82,15 +42,13 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         let result = client.make_request(
                 Method::GET,
                 "/test-endpoint",
-                &client.base_url,
+                OTHER_ENDPOINT,
+                client.base_url(),
                 &[],
                 None,
                 Some(std::time::Duration::from_secs(5)),
@@ -113,15 +71,13 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         let result = client.make_request(
                 Method::GET,
                 "/error-endpoint",
-                &client.base_url,
+                OTHER_ENDPOINT,
+                client.base_url(),
                 &[],
                 None,
                 None,
@@ -129,7 +85,7 @@ mod tests {
 
         assert!(result.is_err());
         match result {
-            Err(AlpacaError::HttpError { status, message }) => {
+            Err(AlpacaError::HttpError { status, message, .. }) => {
                 assert_eq!(status, StatusCode::BAD_REQUEST);
                 assert_eq!(message, "Bad request");
             },
@@ -150,10 +106,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         let query = [("param1", "value1")];
         let body =
@@ -164,7 +117,8 @@ mod tests {
         let result = client.make_request(
                 Method::POST,
                 "/test-with-params",
-                &client.base_url,
+                OTHER_ENDPOINT,
+                client.base_url(),
                 &query,
                 Some(&body),
                 None,
@@ -175,69 +129,28 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_account() {
-        let mock_server = MockServer::start().await;
-
-        // Setup mock response
-        let account_data = json!({
-            "id": "test-account-id",
-            "account_number": "TEST123456",
-            "status": "ACTIVE",
-            "currency": "USD",
-            "buying_power": "500000.00",
-            "cash": "250000.00"
-        });
-
-        Mock::given(method("GET"))
-            .and(path("/v2/account"))
-            .respond_with(ResponseTemplate::new(200)
-                .set_body_json(account_data.clone()))
-            .mount(&mock_server)
-            .await;
-
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let server = MockAlpacaServer::start().await;
 
-        let result = client.get_account().await;
+        let result = server.client().get_account().await;
 
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), account_data);
+        assert_eq!(result.unwrap(), mock_account());
     }
 
     #[tokio::test]
     async fn test_get_positions() {
-        let mock_server = MockServer::start().await;
+        let server = MockAlpacaServer::start().await;
 
-        // Setup mock response
-        let positions_data = json!([
-            {
-                "asset_id": "asset-1",
-                "symbol": "AAPL",
-                "qty": "100",
-                "side": "long",
-                "market_value": "15000.00"
-            },
-            {
-                "asset_id": "asset-2",
-                "symbol": "MSFT",
-                "qty": "50",
-                "side": "long",
-                "market_value": "12500.00"
-            }
-        ]);
+        let positions_data = mock_positions(2);
 
         Mock::given(method("GET"))
             .and(path("/v2/positions"))
             .respond_with(ResponseTemplate::new(200)
                 .set_body_json(positions_data.clone()))
-            .mount(&mock_server)
+            .mount(server.server())
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = server.client();
 
         let result = client.get_positions().await;
 
@@ -247,31 +160,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_place_order() {
-        let mock_server = MockServer::start().await;
-
-        // Setup mock response
-        let order_response = json!({
-            "id": "order-id-123",
-            "client_order_id": "client-order-id-123",
-            "status": "new",
-            "symbol": "AAPL",
-            "qty": "10",
-            "side": "buy",
-            "type": "market",
-            "time_in_force": "ioc"
-        });
-
-        Mock::given(method("POST"))
-            .and(path("/v2/orders"))
-            .respond_with(ResponseTemplate::new(200)
-                .set_body_json(order_response.clone()))
-            .mount(&mock_server)
-            .await;
+        let server = MockAlpacaServer::start().await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = server.client();
 
         let result = client.place_order(
                 "AAPL",
@@ -281,8 +172,11 @@ mod tests {
                 None
             ).await;
 
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), order_response);
+        // The server echoes the request
+        let order = result.unwrap();
+        assert_eq!((order["symbol"].as_str(), order["qty"].as_str()), (Some("AAPL"), Some("10")));
+        assert_eq!((order["side"].as_str(), order["type"].as_str()), (Some("buy"), Some("market")));
+        assert_eq!(order["status"], "accepted");
     }
 
     #[tokio::test]
@@ -308,10 +202,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         let result = client.place_order(
                 "TSLA",
@@ -357,10 +248,7 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                "https://api.example.com",
-                &mock_server.uri()
-            ).await;
+        let client = test_client("https://api.example.com", &mock_server.uri());
 
         let result = client.get_prices(
                 &["AAPL", "MSFT"],
@@ -373,10 +261,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_prices_empty_assets() {
-        let client = create_test_client(
-                "https://api.example.com",
-                "https://data.example.com"
-            ).await;
+        let client = test_client("https://api.example.com", "https://data.example.com");
 
         let result = client.get_prices(
                 &[],
@@ -412,12 +297,9 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
-        let result = client.get_order_info("order-id-123").await;
+        let result = client.get_order_info("order-id-123", false).await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), order_data);
@@ -435,23 +317,26 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         // Set a very short timeout to ensure it triggers
         let result = client.make_request(
                 Method::GET,
                 "/slow-endpoint",
-                &client.base_url,
+                OTHER_ENDPOINT,
+                client.base_url(),
                 &[],
                 None,
                 Some(std::time::Duration::from_millis(100)), // Very short timeout
             ).await;
 
-        // This should result in a timeout error
-        assert!(matches!(result, Err(AlpacaError::Timeout)));
+        // This should result in a timeout error naming the request
+        let error = result.unwrap_err();
+        assert!(matches!(error, AlpacaError::Timeout { .. }));
+
+        let message = error.to_string();
+        assert!(message.contains("GET /slow-endpoint"), "{}", message);
+        assert!(message.contains("100ms"), "{}", message);
     }
 
     #[tokio::test]
@@ -466,20 +351,5986 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let client = create_test_client(
-                &mock_server.uri(),
-                "https://data.example.com"
-            ).await;
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
 
         let result = client.get_account().await;
 
         assert!(result.is_err());
         match result {
-            Err(AlpacaError::HttpError { status, message }) => {
+            Err(AlpacaError::HttpError { status, message, .. }) => {
                 assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
                 assert_eq!(message, "Rate limit exceeded");
             },
             _ => panic!("Expected HttpError but got {:?}", result),
         }
     }
+
+    #[tokio::test]
+    async fn test_get_latest_trade() {
+        let mock_server = MockServer::start().await;
+
+        let trade_data = json!({
+            "symbol": "AAPL",
+            "trade": {
+                "t": "2024-05-01T19:59:59.954062781Z",
+                "x": "V",
+                "p": 169.3,
+                "s": 100,
+                "c": ["@", "T"],
+                "i": 52983525029461u64,
+                "z": "C"
+            }
+        });
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/trades/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(trade_data))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let trade = client.get_latest_trade("AAPL").await.unwrap();
+
+        assert_eq!(trade.price, 169.3);
+        assert_eq!(trade.size, 100);
+        assert_eq!(trade.exchange, "V");
+        assert_eq!(trade.conditions, vec!["@", "T"]);
+        assert_eq!(trade.timestamp.timestamp_subsec_nanos(), 954062781);
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_trade_not_found() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/NOPE/trades/latest"))
+            .respond_with(ResponseTemplate::new(404)
+                .set_body_json(json!({"message": "Not Found"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let result = client.get_latest_trade("NOPE").await;
+
+        assert!(matches!(result, Err(AlpacaError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_quote() {
+        let mock_server = MockServer::start().await;
+
+        let quote_data = json!({
+            "symbol": "AAPL",
+            "quote": {
+                "t": "2024-05-01T19:59:59.999312Z",
+                "ax": "V",
+                "ap": 169.5,
+                "as": 2,
+                "bx": "V",
+                "bp": 169.2,
+                "bs": 3,
+                "c": ["R"],
+                "z": "C"
+            }
+        });
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(quote_data))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let quote = client.get_latest_quote("AAPL").await.unwrap();
+
+        assert_eq!(quote.bid_price, 169.2);
+        assert_eq!(quote.bid_size, 3);
+        assert_eq!(quote.ask_price, 169.5);
+        assert_eq!(quote.ask_size, 2);
+        assert!((quote.mid() - 169.35).abs() < 1e-9);
+        assert!((quote.spread() - 0.3).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_quote_pre_market_zero_bid() {
+        let mock_server = MockServer::start().await;
+
+        let quote_data = json!({
+            "symbol": "AAPL",
+            "quote": {
+                "t": "2024-05-02T08:00:00Z",
+                "ax": "V",
+                "ap": 170.1,
+                "as": 1,
+                "bx": "",
+                "bp": 0,
+                "bs": 0,
+                "c": [],
+                "z": "C"
+            }
+        });
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(quote_data))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let quote = client.get_latest_quote("AAPL").await.unwrap();
+
+        assert_eq!(quote.bid_price, 0.0);
+        assert_eq!(quote.bid_size, 0);
+        assert!((quote.spread() - 170.1).abs() < 1e-9);
+
+        // A crossed quote gives a negative spread instead of an error
+        let crossed = LatestQuote { bid_price: 170.2, ..quote };
+        assert!(crossed.spread() < 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_get_latest_bar() {
+        let mock_server = MockServer::start().await;
+
+        let bar_data = json!({
+            "symbol": "AAPL",
+            "bar": {
+                "t": "2024-05-01T19:59:00Z",
+                "o": 169.12,
+                "h": 169.4,
+                "l": 169.05,
+                "c": 169.3,
+                "v": 1238711,
+                "n": 9552,
+                "vw": 169.235
+            }
+        });
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/bars/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(bar_data))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let bar = client.get_latest_bar("AAPL").await.unwrap();
+
+        assert_eq!(bar.open, 169.12);
+        assert_eq!(bar.high, 169.4);
+        assert_eq!(bar.low, 169.05);
+        assert_eq!(bar.close, 169.3);
+        assert_eq!(bar.volume, 1238711.0);
+        assert_eq!(bar.trade_count, 9552);
+        assert_eq!(bar.vwap, Some(169.235));
+        assert_eq!(bar.timestamp.to_rfc3339(), "2024-05-01T19:59:00+00:00");
+    }
+
+    #[test]
+    fn test_bar_without_vwap() {
+        let bar: Bar = serde_json::from_value(json!({
+            "t": "2024-05-01T19:59:00Z",
+            "o": 169.12,
+            "h": 169.4,
+            "l": 169.05,
+            "c": 169.3,
+            "v": 200,
+            "n": 3
+        })).unwrap();
+
+        assert_eq!(bar.vwap, None);
+        assert_eq!(bar.volume, 200.0);
+    }
+
+    fn bar_json(timestamp: &str, close: f64) -> Value {
+        json!({
+            "t": timestamp,
+            "o": close,
+            "h": close,
+            "l": close,
+            "c": close,
+            "v": 100,
+            "n": 1,
+            "vw": close
+        })
+    }
+
+    #[tokio::test]
+    async fn test_get_bars_desc_pages_stay_ordered() {
+        let mock_server = MockServer::start().await;
+
+        // Second page, requested with the token of the first one
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("sort", "desc"))
+            .and(query_param("page_token", "page2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": {
+                        "AAPL": [bar_json("2024-05-01T14:31:00Z", 2.0),
+                                 bar_json("2024-05-01T14:30:00Z", 1.0)]
+                    },
+                    "next_page_token": null
+                })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("symbols", "AAPL"))
+            .and(query_param("timeframe", "1Min"))
+            .and(query_param("sort", "desc"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": {
+                        "AAPL": [bar_json("2024-05-01T14:33:00Z", 4.0),
+                                 bar_json("2024-05-01T14:32:00Z", 3.0)]
+                    },
+                    "next_page_token": "page2"
+                })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let params = HistoricalBarsParams::builder()
+            .timeframe(TimeFrame::Minute(1))
+            .sort(Sort::Desc)
+            .build()
+            .unwrap();
+        let bars = client.get_bars(&["AAPL"], &params)
+            .await
+            .unwrap();
+
+        let closes: Vec<f64> = bars["AAPL"].iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, vec![4.0, 3.0, 2.0, 1.0]);
+    }
+
+    #[tokio::test]
+    async fn test_get_recent_bars() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("symbols", "AAPL"))
+            .and(query_param("timeframe", "1Day"))
+            .and(query_param("sort", "desc"))
+            .and(query_param("limit", "2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": {
+                        "AAPL": [bar_json("2024-05-02T04:00:00Z", 2.0),
+                                 bar_json("2024-05-01T04:00:00Z", 1.0)]
+                    },
+                    "next_page_token": "more"
+                })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let bars = client.get_recent_bars("AAPL", TimeFrame::Day, 2).await.unwrap();
+
+        let closes: Vec<f64> = bars.iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_validate_limit() {
+        use crate::alpaca_client::validate_limit;
+
+        for endpoint in ["/v2/stocks/bars", "/v2/stocks/trades", "/v2/stocks/quotes"] {
+            assert!(validate_limit(endpoint, None).is_ok());
+            assert!(validate_limit(endpoint, Some(1)).is_ok());
+            assert!(validate_limit(endpoint, Some(10000)).is_ok());
+
+            match validate_limit(endpoint, Some(10001)) {
+                Err(AlpacaError::InvalidParameter(message)) => assert!(message.contains("10000")),
+                result => panic!("Expected InvalidParameter but got {:?}", result),
+            }
+            assert!(matches!(
+                validate_limit(endpoint, Some(0)),
+                Err(AlpacaError::InvalidParameter(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_bars_invalid_limit() {
+        let client = test_client("https://api.example.com", "https://data.example.com");
+
+        let result = HistoricalBarsParams::builder().limit(0).build();
+        assert!(matches!(result, Err(AlpacaError::InvalidParameter(_))));
+
+        let options = HistoricalOptions { limit: Some(0), ..Default::default() };
+        let result = client.get_trades(&["AAPL"], &options).await;
+        assert!(matches!(result, Err(AlpacaError::InvalidParameter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_movers() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/screener/stocks/movers"))
+            .and(query_param("top", "2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "gainers": [
+                        {"symbol": "AGRI", "percent_change": 145.56, "change": 2.46, "price": 4.15},
+                        {"symbol": "GRI", "percent_change": 58.7, "change": 0.54, "price": 1.46}
+                    ],
+                    "losers": [
+                        {"symbol": "MTC", "percent_change": -42.1, "change": -1.35, "price": 1.86}
+                    ],
+                    "market_type": "stocks",
+                    "last_updated": "2024-05-01T19:59:59.99Z"
+                })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/screener/crypto/movers"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "gainers": [
+                        {"symbol": "BTC/USD", "percent_change": 3.2, "change": 1980.5, "price": 63900.0}
+                    ],
+                    "losers": [
+                        {"symbol": "DOGE/USD", "percent_change": -5.1, "change": -0.008, "price": 0.149}
+                    ],
+                    "market_type": "crypto",
+                    "last_updated": "2024-05-01T19:59:59.99Z"
+                })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let movers = client.get_movers(MarketType::Stocks, Some(2)).await.unwrap();
+        assert_eq!(movers.gainers.len(), 2);
+        assert_eq!(movers.gainers[0].symbol, "AGRI");
+        assert_eq!(movers.gainers[0].percent_change, 145.56);
+        assert_eq!(movers.losers[0].change, -1.35);
+
+        let movers = client.get_movers(MarketType::Crypto, None).await.unwrap();
+        assert_eq!(movers.gainers[0].symbol, "BTC/USD");
+        assert_eq!(movers.losers[0].symbol, "DOGE/USD");
+
+        assert!(matches!(
+            client.get_movers(MarketType::Stocks, Some(51)).await,
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            client.get_movers(MarketType::Stocks, Some(0)).await,
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_crypto_orderbooks() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta3/crypto/us/latest/orderbooks"))
+            .and(query_param("symbols", "BTC/USD,ETH/USD"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "orderbooks": {
+                        "BTC/USD": {
+                            "t": "2024-05-01T23:59:59.012345678Z",
+                            "b": [{"p": 63000.1, "s": 0.5}, {"p": 62999.0, "s": 1.25}],
+                            "a": [{"p": 63001.4, "s": 0.3}, {"p": 63002.0, "s": 2.0}]
+                        },
+                        "ETH/USD": {
+                            "t": "2024-05-01T23:59:58Z",
+                            "b": [{"p": 3000.5, "s": 4.0}],
+                            "a": []
+                        }
+                    }
+                })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let books = client.get_crypto_orderbooks(&["BTC/USD", "ETH/USD"]).await.unwrap();
+
+        let btc = &books["BTC/USD"];
+        assert_eq!(btc.bids, vec![(63000.1, 0.5), (62999.0, 1.25)]);
+        assert_eq!(btc.asks, vec![(63001.4, 0.3), (63002.0, 2.0)]);
+
+        let eth = &books["ETH/USD"];
+        assert_eq!(eth.bids, vec![(3000.5, 4.0)]);
+        assert!(eth.asks.is_empty());
+    }
+
+    #[test]
+    fn test_orderbook_missing_side() {
+        let book: OrderBook = serde_json::from_value(json!({
+            "t": "2024-05-01T23:59:58Z",
+            "b": null
+        })).unwrap();
+
+        assert!(book.bids.is_empty());
+        assert!(book.asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_option_bars_multi_page() {
+        let mock_server = MockServer::start().await;
+        let contract = "AAPL240628C00190000";
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/options/bars"))
+            .and(query_param("page_token", "next"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": { contract: [bar_json("2024-06-24T13:31:00Z", 2.1)] },
+                    "next_page_token": null
+                })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/options/bars"))
+            .and(query_param("symbols", contract))
+            .and(query_param("timeframe", "1Min"))
+            .and(query_param("start", "2024-06-24T00:00:00Z"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": { contract: [bar_json("2024-06-24T13:30:00Z", 2.3)] },
+                    "next_page_token": "next"
+                })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let options = HistoricalOptions {
+            start: "2024-06-24T00:00:00Z".parse().ok(),
+            ..Default::default()
+        };
+        let bars = client.get_option_bars(&[contract], TimeFrame::Minute(1), &options)
+            .await
+            .unwrap();
+
+        let closes: Vec<f64> = bars[contract].iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, vec![2.3, 2.1]);
+    }
+
+    #[tokio::test]
+    async fn test_get_option_bars_chunked() {
+        let mock_server = MockServer::start().await;
+
+        let symbols: Vec<String> = (0..101).map(|i| format!("AAPL240628C{:08}", i)).collect();
+        let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+
+        // 100 contracts at most per request, even with larger stock chunks
+        for chunk in [&symbols[..100], &symbols[100..]] {
+            Mock::given(method("GET"))
+                .and(path("/v1beta1/options/bars"))
+                .and(query_param("symbols", chunk.join(",")))
+                .respond_with(ResponseTemplate::new(200)
+                    .set_body_json(json!({
+                        "bars": { chunk[0]: [bar_json("2024-06-24T13:30:00Z", 2.3)] },
+                        "next_page_token": null
+                    })))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let bars = client.get_option_bars(&symbols, TimeFrame::Day, &Default::default()).await.unwrap();
+        assert_eq!(bars.len(), 2);
+        assert!(bars.contains_key(symbols[0]) && bars.contains_key(symbols[100]));
+
+        // A page token can't resume several chunks
+        let options = HistoricalOptions { page_token: Some("next".to_string()), ..Default::default() };
+        let result = client.get_option_bars(&symbols, TimeFrame::Day, &options).await;
+        assert!(matches!(result, Err(AlpacaError::InvalidParameter(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_corporate_actions() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1/corporate-actions"))
+            .and(query_param("page_token", "page2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "corporate_actions": {
+                        "cash_dividends": [{
+                            "id": "f9b2d3c1-0c5e-4f2b-8d5c-2a6f7a7f9e01",
+                            "symbol": "AAPL",
+                            "cusip": "037833100",
+                            "rate": 0.25,
+                            "special": false,
+                            "foreign": false,
+                            "process_date": "2024-05-16",
+                            "ex_date": "2024-05-10",
+                            "record_date": "2024-05-13",
+                            "payable_date": "2024-05-16"
+                        }]
+                    },
+                    "next_page_token": null
+                })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1/corporate-actions"))
+            .and(query_param("symbols", "AAPL,NVDA"))
+            .and(query_param("types", "forward_split,cash_dividend"))
+            .and(query_param("start", "2024-01-01"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "corporate_actions": {
+                        "forward_splits": [{
+                            "id": "5b8f1c1e-3b1a-4b1e-9c58-8c1f4f9e2d11",
+                            "symbol": "NVDA",
+                            "cusip": "67066G104",
+                            "new_rate": 10,
+                            "old_rate": 1,
+                            "process_date": "2024-06-10",
+                            "ex_date": "2024-06-10",
+                            "record_date": "2024-06-06",
+                            "payable_date": "2024-06-07"
+                        }]
+                    },
+                    "next_page_token": "page2"
+                })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let params = CorporateActionsParams {
+            symbols: vec!["AAPL".to_string(), "NVDA".to_string()],
+            types: vec![CorporateActionType::ForwardSplit, CorporateActionType::CashDividend],
+            start: "2024-01-01".parse().ok(),
+            ..Default::default()
+        };
+        let actions = client.get_corporate_actions(&params).await.unwrap();
+
+        let split = &actions.forward_splits[0];
+        assert_eq!(split.symbol, "NVDA");
+        assert_eq!(split.new_rate, 10.0);
+        assert_eq!(split.old_rate, 1.0);
+        assert_eq!(split.ex_date.to_string(), "2024-06-10");
+
+        let dividend = &actions.cash_dividends[0];
+        assert_eq!(dividend.symbol, "AAPL");
+        assert_eq!(dividend.rate, 0.25);
+        assert_eq!(dividend.payable_date.map(|d| d.to_string()).as_deref(), Some("2024-05-16"));
+
+        assert!(actions.reverse_splits.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_logo() {
+        let mock_server = MockServer::start().await;
+
+        // PNG signature followed by a few non UTF-8 bytes
+        let image: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff, 0xfe];
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/logos/AAPL"))
+            .and(query_param("placeholder", "true"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_raw(image.clone(), "image/png"))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/logos/NOPE"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let logo = client.get_logo("AAPL", true).await.unwrap();
+        assert_eq!(logo.as_ref(), image.as_slice());
+
+        let result = client.get_logo("NOPE", false).await;
+        assert!(matches!(result, Err(AlpacaError::NotFound { .. })));
+    }
+
+    fn news_json(id: u64, content: &str) -> Value {
+        json!({
+            "id": id,
+            "headline": format!("Headline {}", id),
+            "author": "Benzinga Newsdesk",
+            "created_at": "2024-05-01T20:31:04Z",
+            "updated_at": "2024-05-01T20:31:05Z",
+            "summary": "",
+            "content": content,
+            "url": format!("https://www.benzinga.com/news/{}", id),
+            "images": [],
+            "symbols": ["AAPL"],
+            "source": "benzinga"
+        })
+    }
+
+    #[tokio::test]
+    async fn test_get_news_all_pagination() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/news"))
+            .and(query_param("page_token", "page2"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "news": [news_json(3, "<p>c</p>"), news_json(4, "<p>d</p>")],
+                    "next_page_token": "page3"
+                })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/news"))
+            .and(query_param("symbols", "AAPL"))
+            .and(query_param("include_content", "true"))
+            .and(query_param("exclude_contentless", "true"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "news": [news_json(1, "<p>a</p>"), news_json(2, "<p>b</p>")],
+                    "next_page_token": "page2"
+                })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let params = NewsParams {
+            symbols: vec!["AAPL".to_string()],
+            include_content: true,
+            exclude_contentless: true,
+            ..Default::default()
+        };
+
+        // Stops on the second page without requesting the third one
+        let articles = client.get_news_all(&params, 3).await.unwrap();
+        let ids: Vec<u64> = articles.iter().map(|article| article.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(articles[2].content, "<p>c</p>");
+
+        let page = client.get_news(&params, Some("page2")).await.unwrap();
+        assert_eq!(page.news.len(), 2);
+        assert_eq!(page.next_page_token.as_deref(), Some("page3"));
+    }
+
+    #[test]
+    fn test_strip_html() {
+        let html = "<p>Apple&nbsp;Inc. <strong>(NASDAQ: AAPL)</strong> reported Q2 results.</p>\
+                    <!-- ad --><script>track();</script>\
+                    <ul><li>Revenue: $90.8B</li><li>EPS &amp; guidance &gt; estimates</li></ul>\
+                    <p>CEO said: &quot;We&#39;re proud.&quot;<br/>More soon.</p>";
+
+        assert_eq!(
+            strip_html(html),
+            "Apple Inc. (NASDAQ: AAPL) reported Q2 results.\n\
+             Revenue: $90.8B\n\
+             EPS & guidance > estimates\n\
+             CEO said: \"We're proud.\"\n\
+             More soon."
+        );
+    }
+
+    #[test]
+    fn test_stop_limit_order_body() {
+        let buy = OrderRequest::stop_limit("AAPL", 10, OrderSide::Buy, 150.0, 150.5)
+            .unwrap()
+            .time_in_force(TimeInForce::Gtc);
+        assert_eq!(serde_json::to_value(&buy).unwrap(), json!({
+            "symbol": "AAPL",
+            "qty": "10",
+            "side": "buy",
+            "type": "stop_limit",
+            "time_in_force": "gtc",
+            "stop_price": 150.0,
+            "limit_price": 150.5
+        }));
+
+        let sell = OrderRequest::stop_limit("AAPL", 10, OrderSide::Sell, 140.0, 139.5).unwrap();
+        assert_eq!(serde_json::to_value(&sell).unwrap(), json!({
+            "symbol": "AAPL",
+            "qty": "10",
+            "side": "sell",
+            "type": "stop_limit",
+            "time_in_force": "day",
+            "stop_price": 140.0,
+            "limit_price": 139.5
+        }));
+
+        // Limit on the wrong side of the stop for the direction
+        assert!(matches!(
+            OrderRequest::stop_limit("AAPL", 10, OrderSide::Buy, 150.0, 149.0),
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            OrderRequest::stop_limit("AAPL", 10, OrderSide::Sell, 140.0, 141.0),
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            OrderRequest::stop_limit("AAPL", 10, OrderSide::Sell, 0.0, 0.0),
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_submit_stop_limit_order() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .and(wiremock::matchers::body_partial_json(json!({
+                "type": "stop_limit",
+                "stop_price": 140.0,
+                "limit_price": 139.5
+            })))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"id": "order-id-125", "status": "new"})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let order = OrderRequest::stop_limit("AAPL", 10, OrderSide::Sell, 140.0, 139.5).unwrap();
+        let result = client.submit_order(&order).await.unwrap();
+
+        assert_eq!(result["id"], "order-id-125");
+    }
+
+    fn order_json(id: &str, order_type: &str, side: &str, status: &str) -> Value {
+        let mut order = mock_order(status);
+        order["id"] = json!(id);
+        order["client_order_id"] = json!(format!("client-{}", id));
+        order["type"] = json!(order_type);
+        order["order_type"] = json!(order_type);
+        order["side"] = json!(side);
+        order["time_in_force"] = json!("gtc");
+        order["filled_at"] = Value::Null;
+        order["filled_qty"] = json!("0");
+        order["filled_avg_price"] = Value::Null;
+        order
+    }
+
+    fn bracket_order_json() -> Value {
+        let mut take_profit = order_json("3e2e0c8b-4e1b-44e7-95d1-7d1f0f6d1a11", "limit", "sell", "new");
+        take_profit["order_class"] = json!("bracket");
+        take_profit["limit_price"] = json!("180");
+
+        let mut stop_loss = order_json("7a9d5e2f-2c3b-4d1e-8f7a-6b5c4d3e2f1a", "stop", "sell", "held");
+        stop_loss["order_class"] = json!("bracket");
+        stop_loss["stop_price"] = json!("160");
+
+        let mut order = order_json("61e69015-8549-4bfd-b9c3-01e75843f47d", "limit", "buy", "filled");
+        order["order_class"] = json!("bracket");
+        order["updated_at"] = json!("2024-05-01T14:30:00.2345Z");
+        order["submitted_at"] = json!("2024-05-01T14:30:00.1Z");
+        order["filled_at"] = json!("2024-05-01T14:30:01Z");
+        order["filled_qty"] = json!("10");
+        order["filled_avg_price"] = json!("169.98");
+        order["limit_price"] = json!("170");
+        order["legs"] = json!([take_profit, stop_loss]);
+        order
+    }
+
+    #[test]
+    fn test_order_nested_legs() {
+        let order: Order = serde_json::from_value(bracket_order_json()).unwrap();
+
+        assert_eq!(order.qty, Some(10.0));
+        assert_eq!(order.filled_avg_price, Some(169.98));
+        assert_eq!(order.limit_price, Some(170.0));
+        assert_eq!(order.legs.as_ref().map(Vec::len), Some(2));
+
+        let take_profit = order.find_leg(OrderClassRole::TakeProfit).unwrap();
+        assert_eq!(take_profit.id, "3e2e0c8b-4e1b-44e7-95d1-7d1f0f6d1a11");
+        assert_eq!(take_profit.limit_price, Some(180.0));
+
+        let stop_loss = order.find_leg(OrderClassRole::StopLoss).unwrap();
+        assert_eq!(stop_loss.id, "7a9d5e2f-2c3b-4d1e-8f7a-6b5c4d3e2f1a");
+        assert_eq!(stop_loss.stop_price, Some(160.0));
+        assert_eq!(stop_loss.filled_avg_price, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_orders_nested() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders"))
+            .and(query_param("status", "all"))
+            .and(query_param("nested", "true"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!([bracket_order_json()])))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/61e69015-8549-4bfd-b9c3-01e75843f47d"))
+            .and(query_param("nested", "true"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(bracket_order_json()))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let params = ListOrdersParams {
+            status: Some("all".to_string()),
+            nested: true,
+            ..Default::default()
+        };
+        let orders = client.list_orders(&params).await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert!(orders[0].find_leg(OrderClassRole::StopLoss).is_some());
+
+        let order = client.get_order("61e69015-8549-4bfd-b9c3-01e75843f47d", true).await.unwrap();
+        assert_eq!(order, orders[0]);
+    }
+
+    async fn mount_pdt_account(mock_server: &MockServer, daytrade_count: u32) {
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "id": "test-account-id",
+                    "status": "ACTIVE",
+                    "cash": "8000.00",
+                    "equity": "12000.50",
+                    "pattern_day_trader": false,
+                    "daytrade_count": daytrade_count,
+                    "daytrading_buying_power": "0"
+                })))
+            .mount(mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "timestamp": "2024-05-01T15:10:00.123456789-04:00",
+                    "is_open": true,
+                    "next_open": "2024-05-02T09:30:00-04:00",
+                    "next_close": "2024-05-01T16:00:00-04:00"
+                })))
+            .mount(mock_server)
+            .await;
+
+        // A buy of AAPL filled this morning
+        let mut filled = order_json("bought-this-morning", "market", "buy", "filled");
+        filled["filled_qty"] = json!("10");
+        Mock::given(method("GET"))
+            .and(path("/v2/orders"))
+            .and(query_param("status", "closed"))
+            .and(query_param("after", "2024-05-01T04:00:00Z"))
+            .and(query_param("symbols", "AAPL"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!([filled])))
+            .mount(mock_server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_day_trade_status() {
+        let mock_server = MockServer::start().await;
+        mount_pdt_account(&mock_server, 3).await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let status = client.day_trade_status().await.unwrap();
+        assert!(!status.pattern_day_trader);
+        assert_eq!(status.daytrade_count, 3);
+        assert_eq!(status.equity, 12000.5);
+        assert_eq!(status.daytrading_buying_power, 0.0);
+        assert!(status.at_day_trade_limit());
+    }
+
+    #[tokio::test]
+    async fn test_pdt_guard_blocks_fourth_day_trade() {
+        let mock_server = MockServer::start().await;
+        mount_pdt_account(&mock_server, 3).await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"id": "order-id-126", "status": "new"})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let sell = OrderRequest::market("AAPL", 10, OrderSide::Sell);
+        assert!(matches!(client.pdt_check(&sell).await.unwrap(), PdtDecision::Blocked(_)));
+
+        let result = client.submit_order_pdt_guarded(&sell, false).await;
+        assert!(matches!(result, Err(AlpacaError::DayTradeBlocked { .. })));
+
+        // Adding to the position is not a day trade
+        let buy = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        assert!(matches!(client.pdt_check(&buy).await.unwrap(), PdtDecision::Allowed(_)));
+
+        // The override submits anyway, this is the single POST expected
+        let result = client.submit_order_pdt_guarded(&sell, true).await.unwrap();
+        assert_eq!(result["id"], "order-id-126");
+    }
+
+    #[tokio::test]
+    async fn test_pdt_guard_allows_below_limit() {
+        let mock_server = MockServer::start().await;
+        mount_pdt_account(&mock_server, 2).await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let sell = OrderRequest::market("AAPL", 10, OrderSide::Sell);
+        assert!(matches!(client.pdt_check(&sell).await.unwrap(), PdtDecision::Allowed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_get_wallets() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/wallets"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!([
+                    {
+                        "asset": "USDC",
+                        "chain": "ETH",
+                        "address": "0x42a76C83014e886e639768D84EAF3573b1876844",
+                        "balance": "1250.50",
+                        "available": "1200.00"
+                    },
+                    {
+                        "asset": "BTC",
+                        "chain": "BTC",
+                        "address": "bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh",
+                        "balance": "0.03125",
+                        "available": null
+                    }
+                ])))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let wallets = client.get_wallets().await.unwrap();
+        assert_eq!(wallets.len(), 2);
+        assert_eq!(wallets[0].asset, "USDC");
+        assert_eq!(wallets[0].balance, 1250.5);
+        assert_eq!(wallets[0].available, Some(1200.0));
+        assert_eq!(wallets[1].balance, 0.03125);
+        assert_eq!(wallets[1].available, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_wallet_transactions() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/wallets/transfers"))
+            .and(query_param("asset", "USDC"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!([
+                    {
+                        "id": "7a3c1e9b-7a3c-4e1b-9f8d-2c5b6a7d8e9f",
+                        "tx_hash": "0x5e1f2c9b3a1d4f6e8b7c9d0a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0",
+                        "direction": "INCOMING",
+                        "status": "COMPLETE",
+                        "asset": "USDC",
+                        "chain": "ETH",
+                        "amount": "500",
+                        "network_fee": "0",
+                        "from_address": "0x1111111111111111111111111111111111111111",
+                        "to_address": "0x42a76C83014e886e639768D84EAF3573b1876844",
+                        "created_at": "2024-04-30T12:00:00Z"
+                    },
+                    {
+                        "id": "9b8c7d6e-5f4a-4b3c-8d2e-1f0a9b8c7d6e",
+                        "tx_hash": null,
+                        "direction": "OUTGOING",
+                        "status": "PENDING",
+                        "asset": "USDC",
+                        "chain": "ETH",
+                        "amount": "250.25",
+                        "network_fee": "1.75",
+                        "from_address": "0x42a76C83014e886e639768D84EAF3573b1876844",
+                        "to_address": "0x2222222222222222222222222222222222222222",
+                        "created_at": "2024-05-01T09:15:00.5Z"
+                    }
+                ])))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let transfers = client.get_wallet_transactions("USDC").await.unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert!(!transfers[0].is_withdrawal());
+        assert!(!transfers[0].is_pending());
+
+        let withdrawal = &transfers[1];
+        assert!(withdrawal.is_withdrawal());
+        assert!(withdrawal.is_pending());
+        assert_eq!(withdrawal.amount, 250.25);
+        assert_eq!(withdrawal.network_fee, Some(1.75));
+        assert_eq!(withdrawal.tx_hash, None);
+    }
+
+    fn watchlist_json(symbols: &[&str]) -> Value {
+        let assets: Vec<Value> = symbols.iter().map(|symbol| json!({
+            "id": format!("asset-{}", symbol),
+            "class": "us_equity",
+            "exchange": "NASDAQ",
+            "symbol": symbol,
+            "name": format!("{} Inc.", symbol),
+            "status": "active",
+            "tradable": true,
+            "marginable": true,
+            "shortable": true,
+            "easy_to_borrow": true,
+            "fractionable": true
+        })).collect();
+
+        json!({
+            "id": "watchlist-1",
+            "account_id": "test-account-id",
+            "name": "Morning scan",
+            "created_at": "2024-04-01T12:00:00Z",
+            "updated_at": "2024-05-01T12:00:00Z",
+            "assets": assets
+        })
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_add_and_remove() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .and(wiremock::matchers::body_json(json!({"symbol": "MSFT"})))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(watchlist_json(&["AAPL", "MSFT"])))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/v2/watchlists/watchlist-1/AAPL"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(watchlist_json(&["MSFT"])))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let watchlist = client.add_to_watchlist("watchlist-1", "MSFT").await.unwrap();
+        assert_eq!(watchlist.symbols(), vec!["AAPL", "MSFT"]);
+
+        let watchlist = client.remove_from_watchlist("watchlist-1", "AAPL").await.unwrap();
+        assert_eq!(watchlist.symbols(), vec!["MSFT"]);
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_duplicate_add() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .respond_with(ResponseTemplate::new(422)
+                .set_body_json(json!({"code": 42210000, "message": "symbol already exists"})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(watchlist_json(&["AAPL"])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let watchlist = client.add_to_watchlist("watchlist-1", "AAPL").await.unwrap();
+        assert_eq!(watchlist.symbols(), vec!["AAPL"]);
+    }
+
+    async fn mount_buying_power_account(mock_server: &MockServer, buying_power: &str) {
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "id": "test-account-id",
+                    "status": "ACTIVE",
+                    "buying_power": buying_power
+                })))
+            .mount(mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/positions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .mount(mock_server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_buying_power_blocks_buy() {
+        let mock_server = MockServer::start().await;
+        mount_buying_power_account(&mock_server, "1000.00").await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "never"})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let order = OrderRequest::limit("AAPL", 10, OrderSide::Buy, 150.0).unwrap();
+        let result = client.submit_order_checked(&order, None).await;
+
+        match result {
+            Err(AlpacaError::InsufficientBuyingPower { needed, available, status, code }) => {
+                assert_eq!(needed, 1500.0);
+                assert_eq!(available, 1000.0);
+                // Checked before sending
+                assert_eq!((status, code), (None, None));
+            },
+            other => panic!("Expected InsufficientBuyingPower, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buying_power_allows_buy() {
+        let mock_server = MockServer::start().await;
+        mount_buying_power_account(&mock_server, "5000.00").await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "symbol": "AAPL",
+                    "quote": {
+                        "bp": 169.2, "bs": 3, "ap": 169.5, "as": 1,
+                        "t": "2024-05-01T15:59:59.5Z"
+                    }
+                })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "accepted"})))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let order = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        let result = client.submit_order_checked(&order, None).await.unwrap();
+        assert_eq!(result["id"], "accepted");
+
+        // Served from the cache until the next submission drops it
+        assert_eq!(client.buying_power(Some(std::time::Duration::from_secs(60))).await.unwrap(), 5000.0);
+        client.submit_order_checked(&order, Some(std::time::Duration::from_secs(60))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buying_power_without_ask() {
+        let mock_server = MockServer::start().await;
+        mount_buying_power_account(&mock_server, "1000.00").await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "symbol": "AAPL",
+                "quote": {"bp": 169.2, "bs": 3, "ap": 0.0, "as": 0, "t": "2024-05-01T15:59:59.5Z"}
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/trades/latest"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "symbol": "AAPL",
+                "trade": {"t": "2024-05-01T15:59:58Z", "x": "V", "p": 170.0, "s": 100, "c": ["@"], "i": 1, "z": "C"}
+            })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        // Priced at the last trade instead of passing at zero cost
+        let order = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        match client.buying_power_check(&order, None).await {
+            Err(AlpacaError::InsufficientBuyingPower { needed, .. }) => assert_eq!(needed, 1700.0),
+            other => panic!("Expected InsufficientBuyingPower, got {:?}", other),
+        }
+        client.buying_power_check(&OrderRequest::market("AAPL", 5, OrderSide::Buy), None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buying_power_ignores_sell() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"buying_power": "0"})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "sold"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let order = OrderRequest::limit("AAPL", 10, OrderSide::Sell, 150.0).unwrap();
+        let result = client.submit_order_checked(&order, None).await.unwrap();
+        assert_eq!(result["id"], "sold");
+    }
+
+    fn clock_json(is_open: bool) -> Value {
+        json!({
+            "timestamp": "2024-05-01T09:29:59.700-04:00",
+            "is_open": is_open,
+            "next_open": "2024-05-01T09:30:00-04:00",
+            "next_close": "2024-05-01T16:00:00-04:00"
+        })
+    }
+
+    #[tokio::test]
+    async fn test_market_guard_reject() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(clock_json(false)))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "never"})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        let guard = MarketGuard::new(std::sync::Arc::new(client), MarketClosedMode::Reject);
+
+        let order = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+
+        match guard.submit(&order).await {
+            Err(AlpacaError::MarketClosed { next_open }) => {
+                assert_eq!(next_open.to_rfc3339(), "2024-05-01T09:30:00-04:00");
+            },
+            other => panic!("Expected MarketClosed, got {:?}", other),
+        }
+        assert!(guard.queued().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_market_guard_queue() {
+        let mock_server = MockServer::start().await;
+
+        // Closed for both submissions and the first check of the
+        // background task, which then sleeps 300ms until the open.
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(clock_json(false)))
+            .up_to_n_times(3)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(clock_json(true)))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .and(wiremock::matchers::body_partial_json(json!({"symbol": "MSFT"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "msft-order"})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        let guard = MarketGuard::new(std::sync::Arc::new(client), MarketClosedMode::Queue);
+        let mut events = guard.subscribe();
+
+        let aapl = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        let msft = OrderRequest::market("MSFT", 5, OrderSide::Buy);
+
+        let Submission::Queued(aapl_id) = guard.submit(&aapl).await.unwrap() else {
+            panic!("AAPL order should be queued");
+        };
+        let Submission::Queued(msft_id) = guard.submit(&msft).await.unwrap() else {
+            panic!("MSFT order should be queued");
+        };
+
+        let queued = guard.queued();
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[1].order, msft);
+
+        assert!(guard.cancel(aapl_id));
+        assert!(!guard.cancel(aapl_id));
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .expect("queued order was not submitted at the open")
+            .unwrap();
+
+        match event {
+            GuardEvent::Submitted { id, order, response } => {
+                assert_eq!(id, msft_id);
+                assert_eq!(order.symbol, "MSFT");
+                assert_eq!(response["id"], "msft-order");
+            },
+            other => panic!("Expected Submitted, got {:?}", other),
+        }
+        assert!(guard.queued().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_error_names_endpoint() {
+        // Nothing listens on the discard port
+        let client = test_client("http://127.0.0.1:9", "https://data.example.com");
+
+        let error = client.get_positions().await.unwrap_err();
+        assert!(matches!(error, AlpacaError::ConnectionError { .. }));
+
+        let message = error.to_string();
+        assert!(message.contains("GET /v2/positions"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_order_not_found() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/gone-order"))
+            .respond_with(ResponseTemplate::new(404)
+                .set_body_json(json!({"code": 40410000, "message": "order not found"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        match client.get_order("gone-order", false).await {
+            Err(AlpacaError::NotFound { resource }) => assert_eq!(resource, "orders/gone-order"),
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_position_absent() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/positions/AAPL"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"symbol": "AAPL", "qty": "10", "side": "long"})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/positions/MSFT"))
+            .respond_with(ResponseTemplate::new(404)
+                .set_body_json(json!({"code": 40410000, "message": "position does not exist"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let position = client.get_position("AAPL").await.unwrap().unwrap();
+        assert_eq!(position["qty"], "10");
+        assert!(client.get_position("MSFT").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_map_api_error() {
+        let error = crate::alpaca_client::map_api_error(
+            StatusCode::FORBIDDEN,
+            "/v2/orders",
+            json!({
+                "code": 40310000,
+                "message": "insufficient buying power",
+                "buying_power": "120.50",
+                "cost_basis": "1500"
+            }).to_string(),
+        );
+        match error {
+            AlpacaError::InsufficientBuyingPower { needed, available, status, code } => {
+                assert_eq!(needed, 1500.0);
+                assert_eq!(available, 120.5);
+                assert_eq!((status, code), (Some(StatusCode::FORBIDDEN), Some(40310000)));
+            },
+            other => panic!("Expected InsufficientBuyingPower, got {:?}", other),
+        }
+
+        let error = crate::alpaca_client::map_api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "/v2/orders",
+            json!({"code": 42210000, "message": "asset \"XYZ\" is not tradable"}).to_string(),
+        );
+        assert!(matches!(error, AlpacaError::AssetNotTradable { ref message, status: Some(StatusCode::UNPROCESSABLE_ENTITY), code: Some(42210000) }
+            if message.contains("XYZ")));
+
+        let error = crate::alpaca_client::map_api_error(
+            StatusCode::BAD_REQUEST,
+            "/v2/orders",
+            json!({"code": 40010001, "message": "qty must be > 0"}).to_string(),
+        );
+        assert!(matches!(error, AlpacaError::InvalidOrder { ref message, status: Some(StatusCode::BAD_REQUEST), code: Some(40010001) }
+            if message == "qty must be > 0"));
+
+        let error = crate::alpaca_client::map_api_error(
+            StatusCode::FORBIDDEN,
+            "/v2/orders",
+            json!({"code": 40310100, "message": "trade denied due to pattern day trading protection"}).to_string(),
+        );
+        match error {
+            AlpacaError::ApiError { code, message, status, endpoint } => {
+                assert_eq!(code, 40310100);
+                assert_eq!(endpoint, "/v2/orders");
+                assert_eq!(status, StatusCode::FORBIDDEN);
+                assert!(message.contains("pattern day trading"));
+            },
+            other => panic!("Expected ApiError, got {:?}", other),
+        }
+
+        // Bodies without a code keep the plain HTTP error
+        let error = crate::alpaca_client::map_api_error(StatusCode::BAD_GATEWAY, "/v2/account", "bad gateway".to_string());
+        assert!(matches!(error, AlpacaError::HttpError { status: StatusCode::BAD_GATEWAY, ref endpoint, .. } if endpoint == "/v2/account"));
+    }
+
+    #[tokio::test]
+    async fn test_submit_order_invalid_order_code() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(422)
+                .set_body_json(json!({"code": 40010001, "message": "limit_price must be positive"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let order = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        let result = client.submit_order(&order).await;
+        assert!(matches!(result, Err(AlpacaError::InvalidOrder { .. })));
+    }
+
+    #[test]
+    fn test_retry_policy_defaults() {
+        let policy = RetryPolicy::default();
+
+        assert!(policy.retries_method(&Method::GET, false, false));
+        assert!(policy.retries_method(&Method::DELETE, false, false));
+        assert!(!policy.retries_method(&Method::POST, false, false));
+        assert!(!policy.retries_method(&Method::PATCH, false, false));
+        assert!(policy.retries_method(&Method::POST, true, true));
+        assert!(!policy.retries_method(&Method::POST, true, false));
+
+        let timeout = AlpacaError::Timeout {
+            method: Method::GET,
+            endpoint: "/v2/account".to_string(),
+            timeout: std::time::Duration::from_secs(1),
+        };
+        assert!(policy.should_retry(&Method::GET, false, false, &timeout, 1));
+        assert!(!policy.should_retry(&Method::GET, false, false, &timeout, 3));
+        assert!(!policy.should_retry(&Method::POST, false, false, &timeout, 1));
+
+        let bad_request = AlpacaError::HttpError { status: StatusCode::BAD_REQUEST, endpoint: String::new(), message: String::new() };
+        assert!(!policy.should_retry(&Method::GET, false, false, &bad_request, 1));
+
+        // Listing POST still takes a client_order_id for retrying it
+        let policy = RetryPolicy { retry_methods: vec![Method::GET, Method::POST], ..Default::default() };
+        assert!(!policy.should_retry(&Method::POST, false, false, &timeout, 1));
+        assert!(policy.should_retry(&Method::POST, false, true, &timeout, 1));
+
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1), std::time::Duration::from_millis(200));
+        assert_eq!(policy.delay(2), std::time::Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn test_retry_only_idempotent() {
+        let mock_server = MockServer::start().await;
+
+        for verb in ["GET", "POST"] {
+            Mock::given(method(verb))
+                .and(path("/slow-endpoint"))
+                .respond_with(ResponseTemplate::new(200)
+                    .set_body_json(json!({}))
+                    .set_delay(std::time::Duration::from_secs(2)))
+                .expect(if verb == "GET" { 3 } else { 1 })
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+        assert_eq!(client.retry_policy().max_retries, 2);
+
+        for verb in [Method::GET, Method::POST] {
+            let result = client.make_request(
+                    verb,
+                    "/slow-endpoint",
+                    OTHER_ENDPOINT,
+                    client.base_url(),
+                    &[],
+                    None,
+                    Some(std::time::Duration::from_millis(100)),
+                ).await;
+            assert!(matches!(result, Err(AlpacaError::Timeout { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_post_requires_client_order_id() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .and(wiremock::matchers::body_partial_json(json!({"client_order_id": "signal-42"})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "accepted"})))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let anonymous = OrderRequest::market("AAPL", 10, OrderSide::Buy);
+        let result = client.submit_order_retrying(&anonymous).await;
+        assert!(matches!(result, Err(AlpacaError::InvalidParameter(_))));
+
+        let tagged = anonymous.client_order_id("signal-42");
+        let result = client.submit_order_retrying(&tagged).await.unwrap();
+        assert_eq!(result["id"], "accepted");
+
+        // Bounded by the maximum response size like the other requests
+        let result = client.with_max_response_size(8).submit_order_retrying(&tagged).await;
+        assert!(matches!(result, Err(AlpacaError::ResponseTooLarge { limit: 8, .. })), "{:?}", result);
+    }
+
+    #[test]
+    fn test_circuit_breaker_transitions() {
+        use std::time::{Duration, Instant};
+
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        let start = Instant::now();
+        let server_error = || Err::<(), _>(AlpacaError::HttpError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            endpoint: String::new(),
+            message: String::new(),
+        });
+        let client_error = || Err::<(), _>(AlpacaError::HttpError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            endpoint: String::new(),
+            message: String::new(),
+        });
+
+        // 4xx answers don't count and reset the streak
+        breaker.record_at(&server_error(), start);
+        breaker.record_at(&server_error(), start);
+        breaker.record_at(&client_error(), start);
+        breaker.record_at(&server_error(), start);
+        assert_eq!(breaker.state_at(start), CircuitState::Closed);
+
+        breaker.record_at(&server_error(), start);
+        breaker.record_at(&server_error(), start);
+        let retry_at = start + Duration::from_secs(60);
+        assert_eq!(breaker.state_at(start), CircuitState::Open { retry_at });
+        assert!(matches!(
+            breaker.acquire_at(start + Duration::from_secs(30)),
+            Err(AlpacaError::CircuitOpen { retry_at: at }) if at == retry_at
+        ));
+
+        // Cooldown over: one probe goes through, the rest fail fast
+        let later = start + Duration::from_secs(61);
+        assert_eq!(breaker.state_at(later), CircuitState::HalfOpen);
+        let probe = breaker.acquire_at(later).unwrap();
+        assert!(matches!(breaker.acquire_at(later), Err(AlpacaError::CircuitOpen { .. })));
+
+        // A failed probe reopens at once
+        probe.record_at(&server_error(), later);
+        let retry_at = later + Duration::from_secs(60);
+        assert_eq!(breaker.state_at(later), CircuitState::Open { retry_at });
+
+        let much_later = retry_at + Duration::from_secs(1);
+        breaker.acquire_at(much_later).unwrap().record_at(&Ok(()), much_later);
+        assert_eq!(breaker.state_at(much_later), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_shared_by_clones() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_retry_policy(RetryPolicy::none())
+            .with_circuit_breaker(2, std::time::Duration::from_secs(60));
+        let clone = client.clone();
+
+        assert_eq!(client.circuit_state(), Some(CircuitState::Closed));
+        assert!(client.get_account().await.is_err());
+        assert!(clone.get_account().await.is_err());
+
+        assert!(matches!(clone.circuit_state(), Some(CircuitState::Open { .. })));
+        assert!(matches!(client.get_account().await, Err(AlpacaError::CircuitOpen { .. })));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_request_span() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "traced"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let order = OrderRequest::market("AAPL", 1, OrderSide::Buy);
+        client.submit_order(&order).await.unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("submit_order{symbol=AAPL side=Buy}"), "{}", output);
+        let request_span = format!(
+            "alpaca_request{{environment=custom ({}) method=POST endpoint=\"/v2/orders\" attempt=1 status=200 elapsed_ms=",
+            mock_server.uri()
+        );
+        assert!(output.contains(&request_span), "{}", output);
+        assert!(!output.contains("abcdefghijklmnopqrstuvwxyz1234567890ABCDEFG"), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn test_redact_credentials() {
+        let secret = "abcdefghijklmnopqrstuvwxyz1234567890ABCDEFG";
+        let mock_server = MockServer::start().await;
+
+        // A misbehaving proxy echoing the request headers back
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(400)
+                .set_body_string(format!("bad request, APCA-API-SECRET-KEY: {}", secret)))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let error = AlpacaError::Other(format!("auth failed for PKTEST12345ABCDEFGHI/{}", secret));
+        let redacted = client.redact(&error);
+        assert_eq!(redacted, "Other error: auth failed for ***/***");
+
+        let error = client.get_account().await.unwrap_err();
+        assert!(!error.to_string().contains(secret), "{}", error);
+        assert!(error.to_string().contains("APCA-API-SECRET-KEY: ***"), "{}", error);
+
+        let dumped = format!("{:?} {}", client, serde_json::to_string(&client).unwrap());
+        assert!(!dumped.contains(secret), "{}", dumped);
+        assert!(!dumped.contains("PKTEST12345ABCDEFGHI"), "{}", dumped);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_per_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-1", "market", "buy", "filled")))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-2"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(clock_json(true)))
+            .mount(&mock_server)
+            .await;
+
+        let metrics = std::sync::Arc::new(AtomicMetrics::new());
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_metrics(metrics.clone());
+
+        client.get_order("order-1", false).await.unwrap();
+        client.get_order("order-2", false).await.unwrap_err();
+        client.get_clock().await.unwrap();
+
+        assert_eq!(metrics.requests("/v2/orders/{id}", &Method::GET), 2);
+        assert_eq!(metrics.failures("/v2/orders/{id}", &Method::GET), 1);
+        assert_eq!(metrics.requests("/v2/clock", &Method::GET), 1);
+        assert_eq!(metrics.failures("/v2/clock", &Method::GET), 0);
+        assert_eq!(metrics.requests("/v2/orders/{id}", &Method::DELETE), 0);
+        assert_eq!(metrics.requests(OTHER_ENDPOINT, &Method::GET), 0);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_labels() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(wiremock::matchers::any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(&mock_server)
+            .await;
+
+        let metrics = std::sync::Arc::new(AtomicMetrics::new());
+        let client = test_client(&mock_server.uri(), &mock_server.uri())
+            .with_metrics(metrics.clone());
+
+        client.get_prices(&["AAPL", "BTC/USD"], PriceType::Trades).await.unwrap();
+        let _ = client.remove_from_watchlist("w1", "AAPL").await;
+        let _ = client.get_order("61e69015", false).await;
+        client.request::<Value>(Method::GET, ApiHost::Data, "/v1beta1/ratings", &[], None::<&()>).await.unwrap();
+
+        assert_eq!(metrics.requests("/v2/stocks/{price_type}/latest", &Method::GET), 1);
+        assert_eq!(metrics.requests("/v1beta3/crypto/us/latest/{price_type}", &Method::GET), 1);
+        assert_eq!(metrics.requests("/v2/watchlists/{id}/{symbol}", &Method::DELETE), 1);
+        assert_eq!(metrics.requests("/v2/orders/{id}", &Method::GET), 1);
+        // Paths the crate doesn't wrap
+        assert_eq!(metrics.requests(OTHER_ENDPOINT, &Method::GET), 1);
+    }
+
+    #[tokio::test]
+    async fn test_make_request_without_json_body() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/status"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("OK"))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        for (verb, endpoint, expected) in [
+            (Method::DELETE, "/v2/orders/order-1", Value::Null),
+            (Method::DELETE, "/v2/watchlists/watchlist-1", Value::Null),
+            (Method::GET, "/v2/status", json!("OK")),
+        ] {
+            let result = client.make_request(verb, endpoint, OTHER_ENDPOINT, client.base_url(), &[], None, None).await;
+            assert_eq!(result.unwrap(), expected, "{}", endpoint);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_too_large() {
+        let mock_server = MockServer::start().await;
+
+        let bars: Vec<Value> = (0..200)
+            .map(|i| bar_json(&format!("2024-01-01T00:{:02}:00Z", i % 60), 100.0 + i as f64))
+            .collect();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"bars": {"AAPL": bars}, "next_page_token": null})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri())
+            .with_max_response_size(1024);
+
+        let result = client.make_request(Method::GET, "/v2/stocks/bars", "/v2/stocks/bars", client.data_url(), &[], None, None).await;
+        match result {
+            Err(AlpacaError::ResponseTooLarge { limit, .. }) => assert_eq!(limit, 1024),
+            other => panic!("Expected ResponseTooLarge, got {:?}", other),
+        }
+
+        // Bulk paths can raise the limit per request
+        let result = client.make_request_with_limit(
+                Method::GET, "/v2/stocks/bars", "/v2/stocks/bars", client.data_url(), &[], None, None, 1024 * 1024
+            ).await.unwrap();
+        assert_eq!(result["bars"]["AAPL"].as_array().unwrap().len(), 200);
+
+        // Also through the options of the bulk downloads
+        let mut output: Vec<u8> = Vec::new();
+        let result = client.download_bars_to(&["AAPL"], TimeFrame::Minute(1), &HistoricalOptions::default(), &mut output, DownloadFormat::Csv).await;
+        assert!(matches!(result, Err(AlpacaError::DownloadInterrupted { ref source, .. })
+            if matches!(**source, AlpacaError::ResponseTooLarge { limit: 1024, .. })), "{:?}", result);
+
+        let options = HistoricalOptions { max_response_size: Some(1024 * 1024), ..Default::default() };
+        let mut output: Vec<u8> = Vec::new();
+        let summary = client.download_bars_to(&["AAPL"], TimeFrame::Minute(1), &options, &mut output, DownloadFormat::Csv).await.unwrap();
+        assert_eq!(summary.rows, 200);
+    }
+
+    #[tokio::test]
+    async fn test_get_prices_multi_partial_failure() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/trades/latest"))
+            .and(query_param("symbols", "AAPL,MSFT"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "trades": {
+                        "AAPL": {"p": 170.0, "s": 10, "t": "2024-05-01T15:59:59Z"},
+                        "MSFT": {"p": 400.0, "s": 5, "t": "2024-05-01T15:59:58Z"}
+                    }
+                })))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/quotes/latest"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("Internal Server Error"))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "bars": {"AAPL": bar_json("2024-05-01T15:59:00Z", 170.2)}
+                })))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let prices = client.get_prices_multi(
+                &["AAPL", "MSFT"],
+                &[PriceType::Trades, PriceType::Quotes, PriceType::Bars],
+            ).await.unwrap();
+
+        assert_eq!(prices["AAPL"][&PriceType::Trades]["p"], 170.0);
+        assert_eq!(prices["AAPL"][&PriceType::Bars]["c"], 170.2);
+        assert!(!prices["AAPL"].contains_key(&PriceType::Quotes));
+        assert_eq!(prices["MSFT"].len(), 1);
+
+        // Nothing succeeded: the error comes through
+        let result = client.get_prices_multi(&["AAPL"], &[PriceType::Quotes]).await;
+        assert!(matches!(result, Err(AlpacaError::HttpError { status: StatusCode::INTERNAL_SERVER_ERROR, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_get_prices_chunked() {
+        let mock_server = MockServer::start().await;
+
+        for chunk in ["AAPL,MSFT", "NVDA,AMZN", "GOOG"] {
+            let trades: serde_json::Map<String, Value> = chunk.split(',')
+                .map(|symbol| (symbol.to_string(), json!({"p": 100.0, "s": 1, "t": "2024-05-01T15:59:59Z"})))
+                .collect();
+
+            Mock::given(method("GET"))
+                .and(path("/v2/stocks/trades/latest"))
+                .and(query_param("symbols", chunk))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"trades": trades})))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = test_client("https://api.example.com", &mock_server.uri())
+            .with_symbol_chunking(2, 2);
+
+        let prices = client.get_prices(&["AAPL", "MSFT", "NVDA", "AMZN", "GOOG"], PriceType::Trades)
+            .await
+            .unwrap();
+
+        let trades = prices["trades"].as_object().unwrap();
+        assert_eq!(trades.len(), 5);
+        assert_eq!(trades["GOOG"]["p"], 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_failure_names_symbols() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta3/crypto/us/latest/orderbooks"))
+            .and(query_param("symbols", "BTC/USD"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"orderbooks": {}})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta3/crypto/us/latest/orderbooks"))
+            .and(query_param("symbols", "NOPE/USD"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("invalid symbol"))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri())
+            .with_symbol_chunking(1, 4);
+
+        match client.get_crypto_orderbooks(&["BTC/USD", "NOPE/USD"]).await {
+            Err(AlpacaError::ChunkFailed { symbols, source }) => {
+                assert_eq!(symbols, vec!["NOPE/USD"]);
+                assert!(matches!(*source, AlpacaError::HttpError { status: StatusCode::BAD_REQUEST, .. }));
+            },
+            other => panic!("Expected ChunkFailed, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_asset_cache() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/assets/AAPL"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(watchlist_json(&["AAPL"])["assets"][0].clone()))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+        let clone = client.clone();
+        let ttl = std::time::Duration::from_secs(60);
+
+        let asset = client.get_asset_cached("AAPL", ttl).await.unwrap();
+        assert!(asset.tradable && asset.fractionable);
+
+        // Served from the cache, also for the clones
+        assert_eq!(clone.get_asset_cached("AAPL", ttl).await.unwrap(), asset);
+        assert_eq!(client.get_asset_cached("AAPL", ttl).await.unwrap(), asset);
+
+        client.invalidate_asset_cache().await;
+        assert_eq!(clone.get_asset_cached("AAPL", ttl).await.unwrap(), asset);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_identical_gets() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "symbol": "AAPL",
+                    "quote": {"bp": 169.2, "bs": 3, "ap": 169.5, "as": 1, "t": "2024-05-01T15:59:59.5Z"}
+                }))
+                .set_delay(std::time::Duration::from_millis(200)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/MSFT/quotes/latest"))
+            .respond_with(ResponseTemplate::new(500)
+                .set_body_string("Internal Server Error")
+                .set_delay(std::time::Duration::from_millis(200)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri())
+            .with_retry_policy(RetryPolicy::none())
+            .with_request_coalescing(true);
+
+        let quotes = futures::future::join_all((0..10).map(|_| client.get_latest_quote("AAPL"))).await;
+        for quote in quotes {
+            assert_eq!(quote.unwrap().ask_price, 169.5);
+        }
+
+        let errors = futures::future::join_all((0..10).map(|_| client.get_latest_quote("MSFT"))).await;
+        for error in errors {
+            assert!(error.unwrap_err().to_string().contains("500"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_make_request_raw() {
+        let mock_server = MockServer::start().await;
+        let image = vec![0x89, b'P', b'N', b'G', 0x00, 0xff];
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"id": "raw"}"#, "application/json"))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/logos/AAPL"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(image.clone(), "image/png"))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/positions"))
+            .respond_with(ResponseTemplate::new(403).set_body_string("forbidden"))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let (status, headers, bytes) = client
+            .make_request_raw(Method::GET, "/v2/account", client.base_url(), &[], None, None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(bytes.as_ref(), br#"{"id": "raw"}"#);
+
+        let (_, headers, bytes) = client
+            .make_request_raw(Method::GET, "/v1beta1/logos/AAPL", client.data_url(), &[], None, None)
+            .await
+            .unwrap();
+        assert_eq!(headers["content-type"], "image/png");
+        assert_eq!(bytes.as_ref(), image.as_slice());
+
+        let result = client
+            .make_request_raw(Method::GET, "/v2/positions", client.base_url(), &[], None, None)
+            .await;
+        match result {
+            Err(AlpacaError::HttpError { status, message, .. }) => {
+                assert_eq!(status, StatusCode::FORBIDDEN);
+                assert_eq!(message, "forbidden");
+            },
+            other => panic!("Expected HttpError, got {:?}", other),
+        }
+    }
+
+    async fn mount_bar_pages(mock_server: &MockServer, fail_page3: bool) {
+        let pages = [
+            (None, json!({"AAPL": [bar_json("2024-01-02T14:30:00Z", 185.0)],
+                          "MSFT": [bar_json("2024-01-02T14:30:00Z", 370.0)]}), Some("p2")),
+            (Some("p2"), json!({"AAPL": [bar_json("2024-01-02T14:31:00Z", 185.5)]}), Some("p3")),
+            (Some("p3"), json!({"MSFT": [bar_json("2024-01-02T14:31:00Z", 370.5)]}), None),
+        ];
+
+        for (token, bars, next) in pages.into_iter().rev() {
+            let mut mock = Mock::given(method("GET"))
+                .and(path("/v2/stocks/bars"))
+                .and(query_param("symbols", "AAPL,MSFT"));
+            if let Some(token) = token {
+                mock = mock.and(query_param("page_token", token));
+            }
+
+            let response = if fail_page3 && token == Some("p3") {
+                ResponseTemplate::new(400).set_body_string("bad page token")
+            } else {
+                ResponseTemplate::new(200).set_body_json(json!({"bars": bars, "next_page_token": next}))
+            };
+            mock.respond_with(response).mount(mock_server).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_bars_ndjson() {
+        let mock_server = MockServer::start().await;
+        mount_bar_pages(&mock_server, false).await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let mut output: Vec<u8> = Vec::new();
+        let summary = client.download_bars_to(
+                &["AAPL", "MSFT"],
+                TimeFrame::Minute(1),
+                &HistoricalOptions::default(),
+                &mut output,
+                DownloadFormat::Ndjson,
+            ).await.unwrap();
+
+        assert_eq!(summary.rows, 4);
+        assert_eq!(summary.pages, 3);
+        assert_eq!(summary.last_timestamp.unwrap().to_rfc3339(), "2024-01-02T14:31:00+00:00");
+        assert_eq!(summary.next_page_token, None);
+
+        let lines: Vec<Value> = String::from_utf8(output).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["symbol"], "AAPL");
+        assert_eq!(lines[3]["symbol"], "MSFT");
+        assert_eq!(lines[3]["c"], 370.5);
+    }
+
+    #[tokio::test]
+    async fn test_download_bars_interrupted() {
+        let mock_server = MockServer::start().await;
+        mount_bar_pages(&mock_server, true).await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let mut output: Vec<u8> = Vec::new();
+        let result = client.download_bars_to(
+                &["AAPL", "MSFT"],
+                TimeFrame::Minute(1),
+                &HistoricalOptions::default(),
+                &mut output,
+                DownloadFormat::Csv,
+            ).await;
+
+        match result {
+            Err(AlpacaError::DownloadInterrupted { summary, source }) => {
+                assert_eq!(summary.rows, 3);
+                assert_eq!(summary.pages, 2);
+                assert_eq!(summary.next_page_token.as_deref(), Some("p3"));
+                assert!(matches!(*source, AlpacaError::HttpError { .. }));
+            },
+            other => panic!("Expected DownloadInterrupted, got {:?}", other),
+        }
+
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "symbol,t,o,h,l,c,v,n,vw");
+        assert_eq!(lines[1], "AAPL,2024-01-02T14:30:00Z,185,185,185,185,100,1,185");
+        assert_eq!(lines.len(), 4);
+        assert!(csv.ends_with('\n'));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_prefetch_overlaps_pages() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        for prefetch in [0, 1] {
+            let mock_server = MockServer::start().await;
+            let requests = Arc::new(AtomicUsize::new(0));
+
+            let counter = requests.clone();
+            Mock::given(method("GET"))
+                .and(path("/v2/stocks/bars"))
+                .respond_with(move |_: &wiremock::Request| {
+                    let page = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    let next = (page < 3).then(|| format!("p{}", page + 1));
+                    ResponseTemplate::new(200).set_body_json(json!({"page": page, "next_page_token": next}))
+                })
+                .mount(&mock_server)
+                .await;
+
+            let client = test_client("https://api.example.com", &mock_server.uri())
+                .with_prefetch(prefetch);
+
+            // Simulates a slow consumer and records how many pages were
+            // requested by the time it is done with each of them.
+            let mut seen = Vec::new();
+            client.for_each_page("/v2/stocks/bars", &[], None, None, |page| {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                seen.push((page["page"].as_u64().unwrap(), requests.load(Ordering::SeqCst)));
+                Ok(true)
+            }).await.unwrap();
+
+            let order: Vec<u64> = seen.iter().map(|(page, _)| *page).collect();
+            assert_eq!(order, vec![1, 2, 3]);
+
+            if prefetch == 0 {
+                assert_eq!(seen[0].1, 1, "sequential pages must not overlap");
+            } else {
+                assert!(seen[0].1 >= 2, "page 2 should be in flight during page 1: {:?}", seen);
+            }
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_prefetch_stops_on_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("page_token", "p2"))
+            .respond_with(ResponseTemplate::new(400).set_body_string("bad page token"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"bars": {"AAPL": [bar_json("2024-01-02T14:30:00Z", 185.0)]}, "next_page_token": "p2"})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri())
+            .with_prefetch(2);
+
+        let result = client.get_bars(&["AAPL"], &HistoricalBarsParams::builder().timeframe(TimeFrame::Minute(1)).build().unwrap()).await;
+        assert!(matches!(result, Err(AlpacaError::HttpError { status: StatusCode::BAD_REQUEST, .. })));
+    }
+
+    #[test]
+    fn test_mock_wrapper_signal_flow() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+
+        let bought = wrapper.manage_buy_signal("AAPL").expect("buy order");
+        assert_eq!(bought["status"], "filled");
+        assert_eq!(bought["qty"], "10");
+        assert_eq!(mock.cash(), 0.0);
+
+        // Nothing to sell until the price is above the entry
+        wrapper.update_positions().unwrap();
+        assert!(wrapper.manage_sell_signal("AAPL").is_none());
+
+        mock.set_price("AAPL", 110.0);
+        wrapper.update_prices();
+        let sold = wrapper.manage_sell_signal("AAPL").expect("sell order");
+        assert_eq!(sold["side"], "sell");
+        assert_eq!(mock.cash(), 1100.0);
+
+        let submitted: Vec<OrderRequest> = mock.calls().into_iter()
+            .filter_map(|call| match call {
+                MockCall::SubmitOrder(order) => Some(order),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(submitted, vec![
+            OrderRequest::market("AAPL", 10, OrderSide::Buy),
+            OrderRequest::market("AAPL", 10, OrderSide::Sell),
+        ]);
+        assert_eq!(mock.calls()[0], MockCall::GetAccount);
+    }
+
+    #[tokio::test]
+    async fn test_mock_limit_order_fills_on_tick() {
+        let mock = MockAlpaca::new(1000.0);
+        mock.set_price("AAPL", 100.0);
+
+        let order = OrderRequest::limit("AAPL", 5, OrderSide::Buy, 95.0).unwrap();
+        let submitted = AlpacaApi::submit_order(&mock, &order).await.unwrap();
+        assert_eq!(submitted["status"], "new");
+
+        mock.tick("AAPL", 96.0);
+        assert_eq!(mock.orders()[0]["status"], "new");
+
+        mock.tick("AAPL", 94.0);
+        let id = submitted["id"].as_str().unwrap();
+        let filled = AlpacaApi::get_order_info(&mock, id, false).await.unwrap();
+        assert_eq!(filled["status"], "filled");
+        assert_eq!(filled["filled_avg_price"], "95");
+        assert_eq!(mock.cash(), 525.0);
+
+        let positions = AlpacaApi::get_positions(&mock).await.unwrap();
+        assert_eq!(positions[0]["qty"], "5");
+    }
+
+    #[tokio::test]
+    async fn test_cassette_record_then_replay() {
+        let mock_server = MockServer::start().await;
+        let data_server = MockServer::start().await;
+        let file = std::env::temp_dir().join(format!("alpaca-cassette-{}.json", std::process::id()));
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "test-account-id", "cash": "1000"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("start", "2025-01-01T00:00:00Z"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "bars": {"AAPL": [{"t": "2025-01-02T14:30:00Z", "o": 1.0, "h": 2.0, "l": 0.5, "c": 1.5, "v": 10, "n": 2, "vw": 1.2}]},
+                "next_page_token": null
+            })))
+            .expect(1)
+            .mount(&data_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/missing"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("no order for key PKTEST12345ABCDEFGHI"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let start = chrono::DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let params = HistoricalBarsParams::builder().start(start).build().unwrap();
+
+        let recorder = std::sync::Arc::new(Cassette::record(&file));
+        let client = test_client(&mock_server.uri(), &data_server.uri())
+            .with_cassette(recorder.clone());
+
+        let account = client.get_account().await.unwrap();
+        let bars = client.get_bars(&["AAPL"], &params).await.unwrap();
+        assert!(matches!(client.get_order_info("missing", false).await, Err(AlpacaError::NotFound { .. })));
+        recorder.save().unwrap();
+
+        let recorded = recorder.interactions();
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded[0].host, "trading");
+        assert_eq!(recorded[1].host, "data");
+        assert_eq!(recorded[2].endpoint, "/v2/orders/{id}");
+        // The key echoed in the error body never reaches the cassette
+        assert!(!std::fs::read_to_string(&file).unwrap().contains("PKTEST12345ABCDEFGHI"));
+
+        // Nothing listens there, every response must come from the cassette
+        let player = std::sync::Arc::new(Cassette::replay(&file).unwrap().ignore_query_param("start"));
+        let offline = test_client("http://127.0.0.1:9", "http://127.0.0.1:10")
+            .with_cassette(player);
+
+        assert_eq!(offline.get_account().await.unwrap(), account);
+        let later = HistoricalBarsParams::builder().start(start + chrono::Duration::days(1)).build().unwrap();
+        assert_eq!(offline.get_bars(&["AAPL"], &later).await.unwrap(), bars);
+        assert!(matches!(offline.get_order_info("missing", false).await, Err(AlpacaError::NotFound { .. })));
+        assert!(matches!(
+            offline.get_positions().await,
+            Err(AlpacaError::UnrecordedRequest { endpoint, .. }) if endpoint == "/v2/positions"
+        ));
+
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_order() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/v2/orders/abc123"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        client.cancel_order("abc123").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_generic_request() {
+        let mock_server = MockServer::start().await;
+        let data_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/ratings"))
+            .and(query_param("symbols", "AAPL"))
+            .and(header("APCA-API-KEY-ID", "PKTEST12345ABCDEFGHI"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{"symbol": "AAPL", "score": 4.5}])))
+            .expect(1)
+            .mount(&data_server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/new-endpoint"))
+            .and(wiremock::matchers::body_json(json!({"enabled": true})))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/forbidden"))
+            .respond_with(ResponseTemplate::new(403).set_body_json(json!({"code": 40310000, "message": "insufficient buying power"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &data_server.uri());
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Rating { symbol: String, score: f64 }
+
+        let ratings: Vec<Rating> = client.request(
+            Method::GET, ApiHost::Data, "/v1beta1/ratings", &[("symbols", "AAPL".to_string())], None::<&()>
+        ).await.unwrap();
+        assert_eq!(ratings, vec![Rating { symbol: "AAPL".to_string(), score: 4.5 }]);
+
+        let body = json!({"enabled": true});
+        let () = client.request(Method::POST, ApiHost::Trading, "/v2/new-endpoint", &[], Some(&body)).await.unwrap();
+
+        let forbidden: Result<Value, _> = client.request(Method::GET, ApiHost::Trading, "/v2/forbidden", &[], None::<&()>).await;
+        assert!(matches!(forbidden, Err(AlpacaError::InsufficientBuyingPower { .. })));
+
+        let not_object: Result<Value, _> = client.request(Method::POST, ApiHost::Trading, "/v2/new-endpoint", &[], Some(&[1, 2])).await;
+        assert!(matches!(not_object, Err(AlpacaError::InvalidParameter(_))));
+    }
+
+    fn synthetic_bar(minute: i64, close: f64) -> Bar {
+        Bar {
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100.0,
+            trade_count: 1,
+            vwap: None,
+            timestamp: chrono::DateTime::UNIX_EPOCH + chrono::Duration::minutes(minute),
+        }
+    }
+
+    #[test]
+    fn test_wrapper_indicators() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        let wrapper = AlpacaWrapper::with_api(mock, vec!["AAPL".to_string()]).unwrap();
+
+        assert_eq!(wrapper.sma("AAPL", 3), None);
+
+        for (minute, close) in [1.0, 2.0, 3.0, 4.0, 5.0].into_iter().enumerate() {
+            wrapper.push_bar("AAPL", synthetic_bar(minute as i64, close));
+        }
+
+        // The current bar updates in place and older bars are ignored
+        wrapper.push_bar("AAPL", synthetic_bar(4, 6.0));
+        wrapper.push_bar("AAPL", synthetic_bar(0, 100.0));
+
+        assert_eq!(wrapper.last_n_closes("AAPL", 3), Some(vec![3.0, 4.0, 6.0]));
+        assert_eq!(wrapper.last_n_closes("AAPL", 6), None);
+        assert_eq!(wrapper.sma("AAPL", 2), Some(5.0));
+        assert_eq!(wrapper.sma("AAPL", 6), None);
+        assert_eq!(wrapper.sma("MSFT", 1), None);
+
+        // Seeded with the SMA of 1, 2, 3 then alpha = 0.5 over 4 and 6
+        assert_eq!(wrapper.ema("AAPL", 3), Some(4.5));
+        assert_eq!(wrapper.ema("AAPL", 5), Some(3.2));
+        assert_eq!(wrapper.ema("AAPL", 6), None);
+        assert_eq!(wrapper.ema("AAPL", 0), None);
+    }
+
+    #[test]
+    fn test_wrapper_bar_history_from_updates() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 10.0);
+        let wrapper = AlpacaWrapper::with_api(mock, vec!["AAPL".to_string()]).unwrap();
+
+        assert_eq!(wrapper.last_n_closes("AAPL", 1), Some(vec![10.0]));
+
+        for minute in 0..BAR_HISTORY + 5 {
+            wrapper.push_bar("MSFT", synthetic_bar(minute as i64, minute as f64));
+        }
+        assert_eq!(wrapper.last_n_closes("MSFT", BAR_HISTORY + 1), None);
+        assert_eq!(wrapper.last_n_closes("MSFT", BAR_HISTORY).unwrap()[0], 5.0);
+    }
+
+    #[test]
+    fn test_wrapper_position_sizing() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(9000.0));
+        mock.set_price("AAPL", 40.0);
+        mock.set_price("MSFT", 40.0);
+        mock.set_fractionable("AAPL", true);
+
+        let wrapper = AlpacaWrapper::with_api(mock, vec!["AAPL".to_string(), "MSFT".to_string()]).unwrap();
+        assert_eq!(wrapper.equity(), 9000.0);
+
+        assert_eq!(wrapper.size_fixed_fraction("AAPL", 0.1), Some(22.5));
+        assert_eq!(wrapper.size_fixed_fraction("MSFT", 0.1), Some(22.0));
+        // 9 / 40 shares round to zero whole shares
+        assert_eq!(wrapper.size_fixed_fraction("AAPL", 0.001), Some(0.225));
+        assert_eq!(wrapper.size_fixed_fraction("MSFT", 0.001), None);
+
+        // 90 at risk over a 2.8 stop per share
+        assert_eq!(wrapper.size_risk_based("AAPL", 0.01, 0.07), Some(32.142857142));
+        assert_eq!(wrapper.size_risk_based("MSFT", 0.01, 0.07), Some(32.0));
+        // Capped at the shares the equity buys
+        assert_eq!(wrapper.size_risk_based("MSFT", 0.01, 0.001), Some(225.0));
+        assert_eq!(wrapper.size_risk_based("MSFT", 0.01, 0.0), None);
+
+        assert_eq!(wrapper.size_fixed_fraction("TSLA", 0.1), None);
+
+        let wrapper = wrapper.with_max_price_age(std::time::Duration::ZERO);
+        assert_eq!(wrapper.size_fixed_fraction("AAPL", 0.1), None);
+    }
+
+    #[test]
+    fn test_wrapper_stop_loss() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(0.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 10.0, 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut events = wrapper.subscribe_stops();
+
+        let sell = wrapper.arm_stop_loss("AAPL", 95.0, StopMode::SellMarket);
+        let alert = wrapper.arm_stop_loss("AAPL", 97.0, StopMode::Alert);
+        let other = wrapper.arm_stop_loss("AAPL", 96.0, StopMode::SellMarket);
+        assert!(wrapper.disarm_stop_loss(other));
+        assert_eq!(wrapper.active_stops().len(), 2);
+
+        mock.set_price("AAPL", 96.0);
+        wrapper.update_prices();
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.stop.id, event.price, event.order), (alert, 96.0, None));
+        assert_eq!(wrapper.active_stops().iter().map(|stop| stop.id).collect::<Vec<_>>(), vec![sell]);
+
+        mock.set_price("AAPL", 94.0);
+        wrapper.update_prices();
+        mock.set_price("AAPL", 90.0);
+        wrapper.update_prices();
+
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.stop.id, sell);
+        assert_eq!(event.order.unwrap()["side"], "sell");
+        assert!(events.try_recv().is_err());
+        assert!(wrapper.active_stops().is_empty());
+        assert!(!wrapper.disarm_stop_loss(sell));
+
+        let sells: Vec<OrderRequest> = mock.calls().into_iter()
+            .filter_map(|call| match call {
+                MockCall::SubmitOrder(order) => Some(order),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sells, vec![OrderRequest::market("AAPL", 10, OrderSide::Sell)]);
+    }
+
+    #[test]
+    fn test_wrapper_stop_loss_ignores_stale_prices() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(0.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 10.0, 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_max_price_age(std::time::Duration::ZERO);
+        wrapper.arm_stop_loss("AAPL", 95.0, StopMode::SellMarket);
+
+        mock.set_price("AAPL", 90.0);
+        wrapper.update_prices();
+
+        assert_eq!(wrapper.active_stops().len(), 1);
+        assert!(mock.orders().is_empty());
+    }
+
+    #[test]
+    fn test_wrapper_daily_pnl() {
+        let session_clock = |is_open: bool, day: i64| {
+            let open = chrono::DateTime::parse_from_rfc3339("2025-01-06T09:30:00-05:00").unwrap() + chrono::Duration::days(day);
+            Clock {
+                timestamp: if is_open { open + chrono::Duration::hours(1) } else { open + chrono::Duration::hours(8) },
+                is_open,
+                next_open: open + chrono::Duration::days(1),
+                next_close: open + chrono::Duration::minutes(390),
+            }
+        };
+
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_clock(session_clock(true, 0));
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut events = wrapper.subscribe_pnl();
+        assert_eq!(wrapper.daily_pnl(), Some(DailyPnl::default()));
+
+        // Round trip of 10 shares from 100 to 110
+        wrapper.manage_buy_signal("AAPL").unwrap();
+        wrapper.update_cash().unwrap();
+        wrapper.update_positions().unwrap();
+        mock.set_price("AAPL", 110.0);
+        wrapper.update_prices();
+        assert_eq!(events.try_recv().unwrap(), DailyPnl { realized: 0.0, unrealized: 100.0 });
+
+        wrapper.manage_sell_signal("AAPL").unwrap();
+        wrapper.update_cash().unwrap();
+        wrapper.update_positions().unwrap();
+        wrapper.update_prices();
+        let pnl = wrapper.daily_pnl().unwrap();
+        assert_eq!(pnl, DailyPnl { realized: 100.0, unrealized: 0.0 });
+        assert_eq!(pnl.total(), 100.0);
+
+        // Held overnight through a gap up
+        wrapper.manage_buy_signal("AAPL").unwrap();
+        wrapper.update_cash().unwrap();
+        wrapper.update_positions().unwrap();
+
+        mock.set_clock(session_clock(false, 0));
+        wrapper.update_prices();
+        assert_eq!(wrapper.daily_pnl(), None);
+
+        mock.set_price("AAPL", 130.0);
+        wrapper.update_prices();
+        mock.set_clock(session_clock(true, 1));
+        wrapper.update_prices();
+        assert_eq!(wrapper.daily_pnl(), Some(DailyPnl::default()));
+
+        mock.set_price("AAPL", 131.0);
+        wrapper.update_prices();
+        assert_eq!(wrapper.daily_pnl(), Some(DailyPnl { realized: 0.0, unrealized: 10.0 }));
+    }
+
+    #[test]
+    fn test_wrapper_journal() {
+        let file = std::env::temp_dir().join(format!("alpaca-journal-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        let start = chrono::Utc::now();
+
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_price("MSFT", 50.0);
+
+        let journal = std::sync::Arc::new(Journal::csv(&file).unwrap());
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string(), "MSFT".to_string()]).unwrap()
+            .with_journal(journal.clone(), "crossover,\n\"v2\"");
+
+        wrapper.manage_buy_signal("AAPL").unwrap();
+
+        // A resting limit order canceled later
+        let limit = OrderRequest::limit("MSFT", 3, OrderSide::Buy, 40.0).unwrap();
+        let accepted = futures::executor::block_on(AlpacaApi::submit_order(mock.as_ref(), &limit)).unwrap();
+        wrapper.journal_order(&accepted);
+        let mut canceled = accepted.clone();
+        canceled["status"] = json!("canceled");
+        wrapper.journal_order(&canceled);
+
+        // Another one filled in part before its cancel, at its fill price
+        let limit = OrderRequest::limit("MSFT", 3, OrderSide::Buy, 40.0).unwrap();
+        let mut partial = futures::executor::block_on(AlpacaApi::submit_order(mock.as_ref(), &limit)).unwrap();
+        partial["status"] = json!("partially_filled");
+        partial["filled_qty"] = json!("1");
+        partial["filled_avg_price"] = json!("39.5");
+        wrapper.journal_order(&partial);
+        partial["status"] = json!("canceled");
+        wrapper.journal_order(&partial);
+
+        let records = journal.query(Some("AAPL"), start..chrono::Utc::now()).unwrap();
+        let outcomes: Vec<JournalOutcome> = records.iter().map(|record| record.outcome).collect();
+        assert_eq!(outcomes, vec![JournalOutcome::Submitted, JournalOutcome::Filled]);
+        assert_eq!(records[1].qty, 10.0);
+        assert_eq!(records[1].price, Some(100.0));
+        assert_eq!(records[1].order_id.as_deref(), Some("mock-order-1"));
+        assert_eq!(records[1].strategy.as_deref(), Some("crossover,\n\"v2\""));
+
+        // Dropping the last handle flushes, the file reads back complete
+        drop(wrapper);
+        drop(journal);
+        let reopened = Journal::csv(&file).unwrap();
+        let records = reopened.query(None, start..chrono::Utc::now()).unwrap();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[2].symbol, "MSFT");
+        assert_eq!((records[2].outcome, records[2].price), (JournalOutcome::Accepted, Some(40.0)));
+        assert_eq!(records[3].outcome, JournalOutcome::Canceled);
+        for record in &records[4..] {
+            assert_eq!((record.outcome, record.qty, record.price), (JournalOutcome::PartiallyFilled, 1.0, Some(39.5)));
+        }
+        let outcomes = crate::OrderOutcomes::from_records(&records);
+        assert_eq!((outcomes.filled, outcomes.partially_filled, outcomes.canceled), (1, 1, 1));
+        assert!(reopened.query(None, chrono::Utc::now()..chrono::Utc::now()).unwrap().is_empty());
+
+        drop(reopened);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_multi_account_wrapper() {
+        let count_prices = |mock: &MockAlpaca| mock.calls().iter()
+            .filter(|call| matches!(call, MockCall::GetPrices { .. }))
+            .count();
+
+        let data = std::sync::Arc::new(MockAlpaca::new(0.0));
+        let paper = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        let live = std::sync::Arc::new(MockAlpaca::new(500.0));
+        for mock in [&data, &paper, &live] {
+            mock.set_price("AAPL", 100.0);
+            mock.set_price("MSFT", 50.0);
+        }
+
+        let multi = MultiAccountWrapper::new(data.clone())
+            .with_account("paper", AlpacaWrapper::with_api(paper.clone(), vec!["AAPL".to_string()]).unwrap())
+            .with_account_api("live", live.clone(), vec!["AAPL".to_string(), "MSFT".to_string()])
+            .unwrap();
+        assert_eq!(multi.labels(), vec!["live", "paper"]);
+
+        // Only the account built on its own fetched its first prices
+        let own_fetches = (count_prices(&paper), count_prices(&live));
+        assert_eq!(own_fetches, (1, 0));
+        for mock in [&data, &paper, &live] {
+            mock.set_price("AAPL", 50.0);
+        }
+        multi.update_prices();
+        multi.update_prices();
+
+        // All the prices come from the shared data client
+        assert_eq!(count_prices(&data), 2);
+        assert_eq!((count_prices(&paper), count_prices(&live)), own_fetches);
+        match data.calls().last().unwrap() {
+            MockCall::GetPrices { assets, .. } => assert_eq!(assets, &vec!["AAPL".to_string(), "MSFT".to_string()]),
+            call => panic!("unexpected call {:?}", call),
+        }
+
+        // Routed by label, sized with the shared ask and each account cash
+        assert_eq!(multi.manage_buy_signal("paper", "AAPL").unwrap().unwrap()["qty"], "20");
+        assert_eq!(multi.manage_buy_signal("live", "MSFT").unwrap().unwrap()["qty"], "10");
+        assert!(matches!(multi.manage_buy_signal("margin", "AAPL"), Err(AlpacaError::InvalidParameter(_))));
+        assert_eq!(paper.orders().len(), 1);
+        assert_eq!(live.orders().len(), 1);
+
+        multi.update_cash();
+        multi.update_positions();
+        let positions = multi.positions_by_account();
+        assert_eq!(positions["paper"]["AAPL"].qty, 20.0);
+        assert!(!positions["paper"].contains_key("MSFT"));
+        assert_eq!(positions["live"]["MSFT"].qty, 10.0);
+
+        // All the cash went into the positions
+        assert_eq!(multi.total_equity_all(), 1500.0);
+    }
+
+    #[tokio::test]
+    async fn test_get_prices_crypto_endpoint() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/trades/latest"))
+            .and(query_param("symbols", "AAPL"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"trades": {"AAPL": {"p": 100.0}}})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta3/crypto/us/latest/trades"))
+            .and(query_param("symbols", "BTC/USD,ETH/USD"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"trades": {
+                "BTC/USD": {"p": 50000.0}, "ETH/USD": {"p": 3000.0}
+            }})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        let prices = client.get_prices(&["BTC/USD", "AAPL", "ETH/USD"], PriceType::Trades).await.unwrap();
+
+        assert_eq!(prices["trades"]["AAPL"]["p"], 100.0);
+        assert_eq!(prices["trades"]["BTC/USD"]["p"], 50000.0);
+        assert_eq!(prices["trades"]["ETH/USD"]["p"], 3000.0);
+    }
+
+    #[test]
+    fn test_wrapper_mixed_crypto_assets() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(60000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_price("BTC/USD", 50000.0);
+        mock.set_position("AAPL", 5.0, 90.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string(), "BTC/USD".to_string()]).unwrap();
+        assert!(wrapper.size_fixed_fraction("BTC/USD", 0.5).is_some_and(|qty| qty.fract() != 0.0));
+
+        let bought = wrapper.manage_buy_signal("BTC/USD").unwrap();
+        assert_eq!(bought["time_in_force"], "gtc");
+        assert_eq!(bought["qty"], "1.2");
+
+        wrapper.update_positions().unwrap();
+        wrapper.update_prices();
+        let positions = wrapper.positions();
+        assert_eq!(positions["AAPL"].qty, 5.0);
+        assert_eq!(positions["BTC/USD"].qty, 1.2);
+        assert_eq!(positions["BTC/USD"].entry, 50000.0);
+        assert_eq!(wrapper.last_n_closes("BTC/USD", 1), Some(vec![50000.0]));
+        assert_eq!(wrapper.last_n_closes("AAPL", 1), Some(vec![100.0]));
+
+        // The mock holds the pair like Alpaca does
+        let held = futures::executor::block_on(AlpacaApi::get_positions(mock.as_ref())).unwrap();
+        assert!(held.as_array().unwrap().iter().any(|position| position["symbol"] == "BTCUSD"));
+    }
+
+    #[test]
+    fn test_wrapper_skips_malformed_positions() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mock_server = runtime.block_on(MockServer::start());
+
+        runtime.block_on(async {
+            Mock::given(method("GET"))
+                .and(path("/v2/account"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "test-account-id", "cash": "1500.5"})))
+                .up_to_n_times(1)
+                .mount(&mock_server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/v2/account"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "test-account-id"})))
+                .mount(&mock_server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/v2/positions"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                    {"symbol": "AAPL", "qty_available": "10", "market_value": "1000", "avg_entry_price": "90", "current_price": "100"},
+                    {"symbol": "MSFT", "qty_available": "ten"},
+                    {"qty_available": "3"},
+                ])))
+                .up_to_n_times(1)
+                .mount(&mock_server)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/v2/positions"))
+                .respond_with(ResponseTemplate::new(500))
+                .mount(&mock_server)
+                .await;
+        });
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        let wrapper = AlpacaWrapper::with_client(std::sync::Arc::new(client), vec!["AAPL".to_string(), "MSFT".to_string()]).unwrap();
+
+        let positions = wrapper.positions();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions["AAPL"].qty, 10.0);
+        assert_eq!(wrapper.equity(), 1500.5 + 1000.0);
+
+        // Failures keep the previous values
+        assert!(matches!(wrapper.update_cash(), Err(AlpacaError::Other(_))));
+        assert!(wrapper.update_positions().is_err());
+        assert_eq!(wrapper.positions(), positions);
+        assert_eq!(wrapper.equity(), 1500.5 + 1000.0);
+    }
+
+    #[test]
+    fn test_order_qty_strings() {
+        let qty = |order: OrderRequest| serde_json::to_value(&order).unwrap()["qty"].clone();
+
+        assert_eq!(qty(OrderRequest::market("AAPL", 10, OrderSide::Buy)), json!("10"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 10i64, OrderSide::Buy)), json!("10"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 10u64, OrderSide::Buy)), json!("10"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 0.531, OrderSide::Buy)), json!("0.531"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 2.0, OrderSide::Buy)), json!("2"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 1e-7, OrderSide::Buy)), json!("0.0000001"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 0.1 + 0.2, OrderSide::Buy)), json!("0.3"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 1.0 / 3.0, OrderSide::Buy)), json!("0.333333333"));
+        assert_eq!(qty(OrderRequest::market("AAPL", 1500.0, OrderSide::Buy)), json!("1500"));
+        assert_eq!(qty(OrderRequest::market("AAPL", "0.531", OrderSide::Buy)), json!("0.531"));
+        assert_eq!(qty(OrderRequest::market("AAPL", " 12.5 ".to_string(), OrderSide::Buy)), json!("12.5"));
+
+        assert_eq!(Qty::parse("0.531").unwrap().as_f64(), 0.531);
+        for invalid in ["0", "0.0", "-1", "abc", "1.2.3", "1e5", ".", ""] {
+            assert!(matches!(Qty::parse(invalid), Err(AlpacaError::InvalidParameter(_))), "{}", invalid);
+        }
+
+        for invalid in [Qty::from(0), Qty::from(-3), Qty::from(f64::NAN), Qty::from(f64::INFINITY), Qty::from(-0.5), Qty::from(1e-10)] {
+            let order = OrderRequest::market("AAPL", invalid, OrderSide::Buy);
+            assert!(matches!(order.body(), Err(AlpacaError::InvalidParameter(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_order_refuses_invalid_qty_locally() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let order = OrderRequest::market("AAPL", 0, OrderSide::Buy);
+        assert!(matches!(client.submit_order(&order).await, Err(AlpacaError::InvalidParameter(_))));
+        assert!(matches!(client.place_order("AAPL", f64::NAN, "buy", None, None).await, Err(AlpacaError::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn test_order_status_round_trip() {
+        let names = [
+            "new", "partially_filled", "filled", "done_for_day", "canceled", "expired",
+            "replaced", "pending_cancel", "pending_replace", "accepted", "pending_new",
+            "accepted_for_bidding", "stopped", "rejected", "suspended", "calculated",
+        ];
+        assert_eq!(OrderStatus::ALL.len(), names.len());
+
+        for (status, name) in OrderStatus::ALL.iter().zip(names) {
+            assert_eq!(serde_json::to_value(status).unwrap(), json!(name));
+            assert_eq!(&serde_json::from_value::<OrderStatus>(json!(name)).unwrap(), status);
+            assert!(!matches!(status, OrderStatus::Unknown(_)));
+        }
+
+        let future: OrderStatus = serde_json::from_value(json!("held_for_review")).unwrap();
+        assert_eq!(future, OrderStatus::Unknown("held_for_review".to_string()));
+        assert_eq!(serde_json::to_value(&future).unwrap(), json!("held_for_review"));
+
+        assert!(OrderStatus::Filled.is_terminal() && !OrderStatus::Filled.is_fillable());
+        assert!(OrderStatus::PartiallyFilled.is_fillable() && OrderStatus::PartiallyFilled.is_cancelable());
+        assert!(!OrderStatus::PendingCancel.is_terminal() && !OrderStatus::PendingCancel.is_cancelable());
+        assert!(OrderStatus::PendingReplace.is_fillable() && !OrderStatus::PendingReplace.is_cancelable());
+        assert!(!future.is_terminal() && !future.is_cancelable());
+    }
+
+    #[tokio::test]
+    async fn test_poll_order_until_matches_on_third_poll() {
+        let mock_server = MockServer::start().await;
+        for status in ["pending_new", "new"] {
+            Mock::given(method("GET"))
+                .and(path("/v2/orders/order-1"))
+                .respond_with(ResponseTemplate::new(200)
+                    .set_body_json(order_json("order-1", "limit", "buy", status)))
+                .up_to_n_times(1)
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-1", "limit", "buy", "partially_filled")))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let order = client.poll_order_until(
+                "order-1",
+                |status| *status == OrderStatus::PartiallyFilled,
+                std::time::Duration::from_millis(5),
+                std::time::Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_order_times_out_with_last_snapshot() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-1", "limit", "buy", "new")))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let deadline = std::time::Duration::from_millis(50);
+        match client.wait_for_order("order-1", std::time::Duration::from_millis(10), deadline).await {
+            Err(AlpacaError::OrderWaitTimeout { deadline: waited, last }) => {
+                assert_eq!(waited, deadline);
+                assert_eq!(last.id, "order-1");
+                assert_eq!(last.status, OrderStatus::New);
+            }
+            other => panic!("expected OrderWaitTimeout, got {:?}", other),
+        }
+    }
+
+    async fn mount_cancel(mock_server: &MockServer, id: &str) {
+        Mock::given(method("DELETE"))
+            .and(path(format!("/v2/orders/{}", id)))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(mock_server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_confirm_canceled() {
+        let mock_server = MockServer::start().await;
+        mount_cancel(&mock_server, "order-1").await;
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-1", "limit", "buy", "pending_cancel")))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-1", "limit", "buy", "canceled")))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let outcome = client.cancel_and_confirm("order-1", std::time::Duration::from_secs(5)).await.unwrap();
+        assert_eq!(outcome, CancelOutcome::Canceled);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_confirm_filled_during_cancel() {
+        let mock_server = MockServer::start().await;
+        mount_cancel(&mock_server, "order-1").await;
+        let mut filled = order_json("order-1", "limit", "buy", "filled");
+        filled["filled_qty"] = json!("10");
+        filled["filled_avg_price"] = json!("187.25");
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(filled))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let outcome = client.cancel_and_confirm("order-1", std::time::Duration::from_secs(5)).await.unwrap();
+        assert_eq!(outcome, CancelOutcome::FilledInstead { filled_qty: 10.0, avg_price: Some(187.25) });
+    }
+
+    #[tokio::test]
+    async fn test_open_orders_walks_all_pages() {
+        let mock_server = MockServer::start().await;
+        let start = chrono::DateTime::parse_from_rfc3339("2024-05-01T14:30:00Z").unwrap();
+        let orders: Vec<Value> = (0..120)
+            .map(|i| {
+                let mut order = order_json(&format!("order-{}", i), "limit", "buy", "new");
+                order["submitted_at"] = json!((start + chrono::Duration::seconds(i)).to_rfc3339());
+                order
+            })
+            .collect();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/orders"))
+            .and(query_param("status", "open"))
+            .and(query_param("nested", "true"))
+            .and(query_param("symbols", "AAPL"))
+            .and(query_param("direction", "asc"))
+            .and(query_param("limit", "100"))
+            .and(query_param_is_missing("after"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&orders[..100]))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // The boundary order comes back again on the second page.
+        Mock::given(method("GET"))
+            .and(path("/v2/orders"))
+            .and(query_param("status", "open"))
+            .and(query_param("after", "2024-05-01T14:31:38.999999Z"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&orders[99..]))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let open = client.open_orders(Some("AAPL")).await.unwrap();
+        assert_eq!(open.len(), 120);
+        assert_eq!(open[0].id, "order-0");
+        assert_eq!(open[119].id, "order-119");
+    }
+
+    fn partially_filled_limit() -> Order {
+        let mut order = order_json("order-1", "limit", "buy", "partially_filled");
+        order["limit_price"] = json!("180");
+        order["filled_qty"] = json!("4");
+        serde_json::from_value(order).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replace_order_qty_and_price() {
+        let order = partially_filled_limit();
+        let request = ReplaceOrderRequest::builder(&order).qty(8).limit_price(181.5).build().unwrap();
+        assert_eq!(request.order_id(), "order-1");
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/v2/orders/order-1"))
+            .and(wiremock::matchers::body_json(json!({"qty": "8", "limit_price": 181.5})))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("order-2", "limit", "buy", "new")))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let replaced = client.replace_order(&request).await.unwrap();
+        assert_eq!(replaced.id, "order-2");
+    }
+
+    #[test]
+    fn test_replace_order_validation() {
+        let order = partially_filled_limit();
+
+        match ReplaceOrderRequest::builder(&order).qty(3).build() {
+            Err(AlpacaError::InvalidParameter(message)) => assert!(message.contains("already filled")),
+            other => panic!("expected InvalidParameter, got {:?}", other),
+        }
+        assert!(ReplaceOrderRequest::builder(&order).qty(4).build().is_ok());
+
+        match ReplaceOrderRequest::builder(&order).build() {
+            Err(AlpacaError::InvalidParameter(message)) => assert!(message.contains("without any change")),
+            other => panic!("expected InvalidParameter, got {:?}", other),
+        }
+
+        assert!(ReplaceOrderRequest::builder(&order).stop_price(170.0).build().is_err());
+        let market: Order = serde_json::from_value(order_json("order-3", "market", "buy", "new")).unwrap();
+        assert!(ReplaceOrderRequest::builder(&market).limit_price(180.0).build().is_err());
+        let filled: Order = serde_json::from_value(order_json("order-4", "limit", "buy", "filled")).unwrap();
+        assert!(ReplaceOrderRequest::builder(&filled).qty(5).build().is_err());
+    }
+
+    #[test]
+    fn test_trade_update_frames() {
+        let mut order = order_json("order-1", "limit", "buy", "partially_filled");
+        order["filled_qty"] = json!("4");
+        order["filled_avg_price"] = json!("179.08");
+        let frame = json!({
+            "stream": "trade_updates",
+            "data": {
+                "event": "partial_fill",
+                "execution_id": "d6a0f2c4-8b5e-4c2e-9d1f-0e7c3b2a1f00",
+                "order": order,
+                "position_qty": "4",
+                "price": "179.08",
+                "qty": "4",
+                "timestamp": "2024-05-01T14:30:01.5Z"
+            }
+        });
+        let update: TradeUpdate = serde_json::from_value(frame["data"].clone()).unwrap();
+        assert_eq!(update.event, TradeEventKind::PartialFill);
+        assert_eq!(update.order.status, OrderStatus::PartiallyFilled);
+        assert_eq!((update.price, update.qty, update.position_qty), (Some(179.08), Some(4.0), Some(4.0)));
+
+        let mut order = order_json("order-1", "limit", "buy", "filled");
+        order["filled_qty"] = json!("10");
+        let frame = json!({
+            "stream": "trade_updates",
+            "data": {
+                "event": "fill",
+                "order": order,
+                "position_qty": "10",
+                "price": "179.1",
+                "qty": "6",
+                "timestamp": "2024-05-01T14:30:02Z"
+            }
+        });
+        let update: TradeUpdate = serde_json::from_value(frame["data"].clone()).unwrap();
+        assert_eq!(update.event, TradeEventKind::Fill);
+        assert_eq!((update.price, update.qty, update.position_qty), (Some(179.1), Some(6.0), Some(10.0)));
+        assert_eq!(update.timestamp.to_rfc3339(), "2024-05-01T14:30:02+00:00");
+
+        let unknown: TradeUpdate = serde_json::from_value(json!({
+            "event": "held_for_review",
+            "order": order_json("order-1", "limit", "buy", "new"),
+            "timestamp": "2024-05-01T14:30:02Z"
+        })).unwrap();
+        assert_eq!(unknown.event, TradeEventKind::Unknown);
+    }
+
+    #[test]
+    fn test_trade_update_from_poll() {
+        let previous: Order = serde_json::from_value(order_json("order-1", "limit", "buy", "new")).unwrap();
+        assert_eq!(TradeUpdate::from_poll(&previous, previous.clone()), None);
+
+        let mut current = order_json("order-1", "limit", "buy", "partially_filled");
+        current["filled_qty"] = json!("4");
+        current["filled_avg_price"] = json!("179.08");
+        current["updated_at"] = json!("2024-05-01T14:31:00Z");
+        let current: Order = serde_json::from_value(current).unwrap();
+
+        let update = TradeUpdate::from_poll(&previous, current.clone()).unwrap();
+        assert_eq!(update.event, TradeEventKind::PartialFill);
+        assert_eq!((update.price, update.qty, update.position_qty), (Some(179.08), Some(4.0), None));
+        assert_eq!(update.timestamp, current.updated_at.unwrap());
+
+        let mut canceled = current.clone();
+        canceled.status = OrderStatus::Canceled;
+        let update = TradeUpdate::from_poll(&current, canceled).unwrap();
+        assert_eq!(update.event, TradeEventKind::Canceled);
+        assert_eq!((update.price, update.qty), (None, None));
+
+        // The rest at 180, the average is (4 * 179.08 + 6 * 180) / 10
+        let mut filled = current.clone();
+        filled.status = OrderStatus::Filled;
+        filled.filled_qty = Some(10.0);
+        filled.filled_avg_price = Some(179.632);
+        let update = TradeUpdate::from_poll(&current, filled).unwrap();
+        assert_eq!(update.event, TradeEventKind::Fill);
+        assert_eq!(update.qty, Some(6.0));
+        assert!((update.price.unwrap() - 180.0).abs() < 1e-9, "{:?}", update.price);
+    }
+
+    #[test]
+    fn test_stream_message_frames() {
+        let frame = r#"[
+            {"T":"success","msg":"authenticated"},
+            {"T":"t","i":96921,"S":"AAPL","x":"D","p":126.55,"s":1,"t":"2021-02-22T15:51:44.208Z","c":["@","I"],"z":"C"},
+            {"T":"q","S":"AMD","bx":"U","bp":87.66,"bs":1,"ax":"Q","ap":87.68,"as":4,"t":"2021-02-22T15:51:45.335689322Z","c":["R"],"z":"C"},
+            {"T":"b","S":"SPY","o":388.985,"h":389.13,"l":388.975,"c":389.12,"v":49378,"t":"2021-02-22T19:15:00Z","n":461,"vw":389.062639},
+            {"T":"d","S":"SPY","o":385.1,"h":391.2,"l":384.9,"c":389.12,"v":4937800,"t":"2021-02-22T05:00:00Z","n":46100,"vw":388.8},
+            {"T":"s","S":"AAPL","sc":"H","sm":"Trading Halt","rc":"T12","rm":"Trading Halted; For information requested by NASDAQ","t":"2021-02-22T19:15:00Z","z":"C"},
+            {"T":"subscription","trades":["AAPL"],"quotes":["AMD","CLDR"],"bars":["*"],"updatedBars":[],"dailyBars":["VOO"],"statuses":["*"],"lulds":[]},
+            {"T":"error","code":405,"msg":"symbol limit exceeded"},
+            {"T":"imbalance","S":"AAPL","p":126.5},
+            {"T":"s","S":"SPY","sc":"H","sm":"Trading Halt","rc":"MWC2","rm":"Market Wide Circuit Breaker Level 2","t":"2021-02-22T19:15:00Z","z":"B"},
+            {"T":"l","S":"AAPL","u":132.88,"d":120.22,"i":"B","t":"2021-02-22T19:15:00.123Z","z":"C"}
+        ]"#;
+
+        let messages = StreamMessage::parse_frame(frame).unwrap();
+        assert_eq!(messages.len(), 11);
+        assert_eq!(messages[0], StreamMessage::Success { msg: "authenticated".to_string() });
+
+        match &messages[1] {
+            StreamMessage::Trade { symbol, trade } => {
+                assert_eq!(symbol, "AAPL");
+                assert_eq!((trade.price, trade.size), (126.55, 1));
+                assert_eq!(trade.conditions, vec!["@", "I"]);
+            }
+            other => panic!("expected a trade, got {:?}", other),
+        }
+        match &messages[2] {
+            StreamMessage::Quote { symbol, quote } => {
+                assert_eq!(symbol, "AMD");
+                assert_eq!((quote.bid_price, quote.ask_price, quote.ask_size), (87.66, 87.68, 4));
+            }
+            other => panic!("expected a quote, got {:?}", other),
+        }
+        match (&messages[3], &messages[4]) {
+            (StreamMessage::Bar { bar, .. }, StreamMessage::DailyBar { symbol, bar: daily }) => {
+                assert_eq!((bar.close, bar.trade_count, bar.vwap), (389.12, 461, Some(389.062639)));
+                assert_eq!((symbol.as_str(), daily.volume), ("SPY", 4937800.0));
+            }
+            other => panic!("expected a bar and a daily bar, got {:?}", other),
+        }
+        match &messages[5] {
+            StreamMessage::TradingStatus(status) => {
+                assert_eq!((status.symbol.as_str(), &status.status_code), ("AAPL", &TradingStatusCode::Halt));
+                assert_eq!(status.reason, HaltReason::InformationRequested);
+            }
+            other => panic!("expected a trading status, got {:?}", other),
+        }
+        match &messages[6] {
+            StreamMessage::SubscriptionAck(subscriptions) => {
+                assert_eq!(subscriptions.quotes, vec!["AMD", "CLDR"]);
+                assert_eq!(subscriptions.daily_bars, vec!["VOO"]);
+                assert!(subscriptions.lulds.is_empty());
+            }
+            other => panic!("expected a subscription ack, got {:?}", other),
+        }
+        assert_eq!(messages[7], StreamMessage::Error { code: 405, msg: "symbol limit exceeded".to_string() });
+        assert_eq!(messages[8], StreamMessage::Unknown(json!({"T": "imbalance", "S": "AAPL", "p": 126.5})));
+        match &messages[9] {
+            StreamMessage::TradingStatus(status) => {
+                assert_eq!(status.reason, HaltReason::MarketWideCircuitBreaker(2));
+                assert_eq!(status.reason.as_str(), "MWC2");
+            }
+            other => panic!("expected a trading status, got {:?}", other),
+        }
+        match &messages[10] {
+            StreamMessage::Luld(luld) => {
+                assert_eq!(luld.symbol, "AAPL");
+                assert_eq!((luld.limit_up_price, luld.limit_down_price), (132.88, 120.22));
+                assert_eq!(luld.indicator, "B");
+            }
+            other => panic!("expected a LULD band, got {:?}", other),
+        }
+
+        // A malformed message is kept raw, the rest of its frame decodes
+        let messages = StreamMessage::parse_frame(r#"[{"T":"t","S":"AAPL"},{"T":"success","msg":"connected"}]"#).unwrap();
+        assert_eq!(messages[0], StreamMessage::Unknown(json!({"T": "t", "S": "AAPL"})));
+        assert_eq!(messages[1], StreamMessage::Success { msg: "connected".to_string() });
+        assert!(StreamMessage::parse_frame(r#"{"T":"success"}"#).is_err());
+    }
+
+    #[test]
+    fn test_wrapper_halt_suppresses_signals() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut halts = wrapper.subscribe_halts();
+
+        let status = |code: &str, reason: &str| -> TradingStatus {
+            serde_json::from_value(json!({
+                "T": "s", "S": "AAPL", "sc": code, "sm": "", "rc": reason, "rm": "",
+                "t": "2024-05-01T15:00:00Z", "z": "C"
+            })).unwrap()
+        };
+
+        wrapper.apply_trading_status(&status("H", "T1"));
+        assert!(wrapper.is_halted("AAPL"));
+        let event = halts.try_recv().unwrap();
+        assert!(event.halted);
+        assert_eq!(event.status.reason, HaltReason::NewsPending);
+
+        // Quotes keep coming from REST, but the pre-halt prices are not trusted
+        wrapper.update_prices();
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+        assert!(wrapper.size_fixed_fraction("AAPL", 0.5).is_none());
+
+        // Quoting resumes before trading, still halted
+        wrapper.apply_trading_status(&status("Q", "T1"));
+        assert!(halts.try_recv().is_err());
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+        assert!(!mock.calls().iter().any(|call| matches!(call, MockCall::SubmitOrder(_))));
+
+        wrapper.apply_trading_status(&status("T", ""));
+        assert!(!wrapper.is_halted("AAPL"));
+        assert!(!halts.try_recv().unwrap().halted);
+
+        wrapper.update_prices();
+        assert!(wrapper.manage_buy_signal("AAPL").is_some());
+    }
+
+    /// Splits CSV text into rows of fields, unquoting the quoted ones.
+    #[test]
+    fn test_bars_csv_round_trip() {
+        let mut bars: Vec<Bar> = vec![
+            serde_json::from_value(bar_json("2024-05-01T15:59:00Z", 170.2)).unwrap(),
+            serde_json::from_value(bar_json("2024-05-01T16:00:00Z", 170.5)).unwrap(),
+        ];
+        bars[1].vwap = None;
+
+        let text = to_csv(&bars, &CsvOptions::default());
+        let rows = crate::csv_export::parse_rows(&text, ',');
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], vec!["t", "o", "h", "l", "c", "v", "n", "vw"]);
+        for (row, bar) in rows[1..].iter().zip(&bars) {
+            assert_eq!(chrono::DateTime::parse_from_rfc3339(&row[0]).unwrap(), bar.timestamp);
+            assert_eq!(row[4].parse::<f64>().unwrap(), bar.close);
+            assert_eq!(row[6].parse::<u64>().unwrap(), bar.trade_count);
+            assert_eq!(row[7].parse::<f64>().ok(), bar.vwap);
+        }
+        assert_eq!(rows[2][7], "");
+    }
+
+    #[test]
+    fn test_trades_and_quotes_csv_round_trip() {
+        let trade: Trade = serde_json::from_value(json!({
+            "t": "2024-05-01T15:59:59.123456789Z", "x": "V", "p": 170.25, "s": 100, "c": ["@", "T"]
+        })).unwrap();
+        let options = CsvOptions::default().delimiter(';').header(false);
+
+        let rows = crate::csv_export::parse_rows(&trade.to_csv(&options), ';');
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], "2024-05-01T15:59:59.123456789Z");
+        assert_eq!((rows[0][1].as_str(), rows[0][4].as_str()), ("V", "@|T"));
+        assert_eq!(rows[0][2].parse::<f64>().unwrap(), trade.price);
+        assert_eq!(rows[0][3].parse::<u64>().unwrap(), trade.size);
+
+        let quote: Quote = serde_json::from_value(json!({
+            "t": "2024-05-01T15:59:59Z", "bp": 170.1, "bs": 3, "ap": 170.3, "as": 5
+        })).unwrap();
+        let rows = crate::csv_export::parse_rows(&to_csv(&[quote.clone(), quote.clone()], &CsvOptions::default()), ',');
+        assert_eq!(rows[0], vec!["t", "bp", "bs", "ap", "as"]);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1][1].parse::<f64>().unwrap(), quote.bid_price);
+        assert_eq!(rows[2][4].parse::<u64>().unwrap(), quote.ask_size);
+
+        // A field holding the delimiter is quoted
+        let mut exchange = trade.clone();
+        exchange.exchange = "a,\"b\"".to_string();
+        let rows = crate::csv_export::parse_rows(&exchange.to_csv(&CsvOptions::default().header(false)), ',');
+        assert_eq!(rows[0].len(), 5);
+        assert_eq!(rows[0][1], "a,\"b\"");
+
+        // And so is a line break, which stays inside the row
+        exchange.exchange = "a\r\nb".to_string();
+        let rows = crate::csv_export::parse_rows(&exchange.to_csv(&CsvOptions::default().header(false)), ',');
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][1], "a\r\nb");
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let order = |filled_at: Value| {
+            let mut order = order_json("order-1", "market", "buy", "filled");
+            order["filled_at"] = filled_at;
+            serde_json::from_value::<Order>(order)
+        };
+
+        let nanos = order(json!("2024-05-01T14:30:00.123456789Z")).unwrap().filled_at.unwrap();
+        assert_eq!(nanos.timestamp_subsec_nanos(), 123456789);
+
+        let offset = order(json!("2024-05-01T10:30:00-04:00")).unwrap().filled_at.unwrap();
+        let utc = order(json!("2024-05-01T14:30:00+00:00")).unwrap().filled_at.unwrap();
+        let zulu = order(json!("2024-05-01T14:30:00Z")).unwrap().filled_at.unwrap();
+        assert_eq!(offset, zulu);
+        assert_eq!(utc, zulu);
+        assert_eq!(order(json!("2024-05-01T14:30:00")).unwrap().filled_at, Some(zulu));
+
+        assert_eq!(order(Value::Null).unwrap().filled_at, None);
+        assert_eq!(order(json!("")).unwrap().filled_at, None);
+        assert!(order(json!("yesterday")).is_err());
+
+        // Written back in UTC with the precision it came with
+        let bar: Bar = serde_json::from_value(bar_json("2024-05-01T15:59:00.5+00:00", 170.2)).unwrap();
+        assert_eq!(serde_json::to_value(&bar).unwrap()["t"], "2024-05-01T15:59:00.500Z");
+    }
+
+    #[test]
+    fn test_serde_num_inputs() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Numbers {
+            #[serde(with = "crate::utils::serde_num::string_or_number")]
+            price: f64,
+            #[serde(with = "crate::utils::serde_num::string_or_number")]
+            count: i64,
+            #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+            limit: Option<f64>,
+            #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+            shares: Option<i64>,
+        }
+
+        let parse = |value: Value| serde_json::from_value::<Numbers>(value);
+
+        let strings = parse(json!({"price": "500000.00", "count": "10", "limit": "1.5", "shares": "-3"})).unwrap();
+        let numbers = parse(json!({"price": 500000.0, "count": 10, "limit": 1.5, "shares": -3})).unwrap();
+        assert_eq!(strings, numbers);
+        assert_eq!(strings, Numbers { price: 500000.0, count: 10, limit: Some(1.5), shares: Some(-3) });
+
+        let nulls = parse(json!({"price": 1, "count": 1, "limit": null, "shares": null})).unwrap();
+        assert_eq!((nulls.limit, nulls.shares), (None, None));
+        let missing = parse(json!({"price": 1, "count": 1})).unwrap();
+        assert_eq!((missing.limit, missing.shares), (None, None));
+
+        // Null is only accepted by the optional fields
+        assert!(parse(json!({"price": null, "count": 1})).is_err());
+        assert!(parse(json!({"price": 1, "count": null})).is_err());
+
+        for garbage in [json!("abc"), json!(""), json!(true), json!([1])] {
+            assert!(parse(json!({"price": garbage.clone(), "count": 1})).is_err(), "f64 from {}", garbage);
+            assert!(parse(json!({"price": 1, "count": garbage.clone()})).is_err(), "i64 from {}", garbage);
+            assert!(parse(json!({"price": 1, "count": 1, "limit": garbage.clone()})).is_err());
+            assert!(parse(json!({"price": 1, "count": 1, "shares": garbage.clone()})).is_err());
+        }
+        assert!(parse(json!({"price": 1, "count": "1.5"})).is_err());
+        assert!(parse(json!({"price": 1, "count": 1.5})).is_err());
+
+        assert_eq!(
+            serde_json::to_value(&strings).unwrap(),
+            json!({"price": "500000", "count": "10", "limit": "1.5", "shares": "-3"})
+        );
+    }
+
+    fn utc(text: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_market_hours_dst_week() {
+        let hours = MarketHours::standard();
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+
+        // Standard time on Friday, daylight time after the switch on Sunday
+        assert_eq!(hours.regular_session(date("2024-03-08")),
+            Some((utc("2024-03-08T14:30:00Z"), utc("2024-03-08T21:00:00Z"))));
+        assert_eq!(hours.regular_session(date("2024-03-11")),
+            Some((utc("2024-03-11T13:30:00Z"), utc("2024-03-11T20:00:00Z"))));
+        assert_eq!(hours.regular_session(date("2024-03-09")), None);
+
+        assert!(hours.is_regular_hours(utc("2024-03-08T20:55:00Z")));
+        assert!(!hours.is_regular_hours(utc("2024-03-11T20:05:00Z")));
+        assert!(hours.is_extended_hours(utc("2024-03-11T20:05:00Z")));
+        assert!(hours.is_extended_hours(utc("2024-03-08T13:45:00Z")));
+        assert!(hours.is_regular_hours(utc("2024-03-11T13:45:00Z")));
+        // 21:30 in New York on Friday, after the extended session
+        assert!(!hours.is_extended_hours(utc("2024-03-09T02:30:00Z")));
+
+        // From Friday evening the next open is Monday's, already in daylight time
+        assert_eq!(hours.next_session_after(utc("2024-03-08T22:00:00Z")),
+            Some((utc("2024-03-11T13:30:00Z"), utc("2024-03-11T20:00:00Z"))));
+        // The fall back happens too
+        assert_eq!(hours.regular_session(date("2024-11-04")).unwrap().0, utc("2024-11-04T14:30:00Z"));
+    }
+
+    #[test]
+    fn test_new_york_switches() {
+        use crate::market_hours::{new_york_date, new_york_to_utc, NewYork};
+        use chrono::TimeZone;
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+        let time = |hour| chrono::NaiveTime::from_hms_opt(hour, 30, 0).unwrap();
+
+        // The clocks go forward at 07:00 UTC and back at 06:00 UTC
+        assert_eq!(utc("2024-03-10T06:59:00Z").with_timezone(&NewYork).to_rfc3339(), "2024-03-10T01:59:00-05:00");
+        assert_eq!(utc("2024-03-10T07:00:00Z").with_timezone(&NewYork).to_rfc3339(), "2024-03-10T03:00:00-04:00");
+        assert_eq!(utc("2024-11-03T05:59:00Z").with_timezone(&NewYork).to_rfc3339(), "2024-11-03T01:59:00-04:00");
+        assert_eq!(utc("2024-11-03T06:00:00Z").with_timezone(&NewYork).to_rfc3339(), "2024-11-03T01:00:00-05:00");
+
+        // The skipped hour is taken an hour later, the repeated one in daylight time
+        assert!(NewYork.from_local_datetime(&date("2024-03-10").and_time(time(2))).single().is_none());
+        assert_eq!(new_york_to_utc(date("2024-03-10"), time(2)), utc("2024-03-10T07:30:00Z"));
+        assert_eq!(new_york_to_utc(date("2024-11-03"), time(1)), utc("2024-11-03T05:30:00Z"));
+
+        // The date changes at the New York midnight, on either side of the switches
+        assert_eq!(new_york_date(utc("2024-03-10T04:59:00Z")), date("2024-03-09"));
+        assert_eq!(new_york_date(utc("2024-03-10T05:00:00Z")), date("2024-03-10"));
+        assert_eq!(new_york_date(utc("2024-11-04T04:59:00Z")), date("2024-11-03"));
+        assert_eq!(new_york_date(utc("2024-11-04T05:00:00Z")), date("2024-11-04"));
+    }
+
+    #[tokio::test]
+    async fn test_market_hours_from_calendar_half_day() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/calendar"))
+            .and(query_param("start", "2024-11-27"))
+            .and(query_param("end", "2024-12-02"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {"date": "2024-11-27", "open": "09:30", "close": "16:00", "session_open": "0400", "session_close": "2000", "settlement_date": "2024-11-29"},
+                {"date": "2024-11-29", "open": "09:30", "close": "13:00", "session_open": "0400", "session_close": "1700", "settlement_date": "2024-12-02"},
+                {"date": "2024-12-02", "open": "09:30", "close": "16:00", "session_open": "0400", "session_close": "2000", "settlement_date": "2024-12-03"}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+
+        let hours = MarketHours::fetch(&client, date("2024-11-27"), date("2024-12-02")).await.unwrap();
+
+        // Thanksgiving is closed, Black Friday closes at 13:00
+        assert_eq!(hours.regular_session(date("2024-11-28")), None);
+        assert_eq!(hours.regular_session(date("2024-11-29")),
+            Some((utc("2024-11-29T14:30:00Z"), utc("2024-11-29T18:00:00Z"))));
+        assert!(!hours.is_regular_hours(utc("2024-11-29T18:30:00Z")));
+        assert!(hours.is_extended_hours(utc("2024-11-29T18:30:00Z")));
+        assert!(!hours.is_extended_hours(utc("2024-11-29T22:30:00Z")));
+
+        assert_eq!(hours.next_session_after(utc("2024-11-27T21:00:00Z")).unwrap().0, utc("2024-11-29T14:30:00Z"));
+        assert_eq!(hours.next_session_after(utc("2024-12-02T15:00:00Z")), None);
+    }
+
+    #[tokio::test]
+    async fn test_historical_bars_params_happy_path() {
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+        let params = HistoricalBarsParams::builder()
+            .timeframe(TimeFrame::Minute(5))
+            .between_dates(date("2024-03-08"), date("2024-03-11"))
+            .limit(1000)
+            .adjustment(Adjustment::Split)
+            .feed(DataFeed::Iex)
+            .asof(date("2024-03-11"))
+            .build()
+            .unwrap();
+
+        // The session bounds are in UTC, across the daylight saving switch
+        assert_eq!(params.options().start, Some(utc("2024-03-08T14:30:00Z")));
+        assert_eq!(params.options().end, Some(utc("2024-03-11T20:00:00Z")));
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/bars"))
+            .and(query_param("timeframe", "5Min"))
+            .and(query_param("start", "2024-03-08T14:30:00Z"))
+            .and(query_param("end", "2024-03-11T20:00:00Z"))
+            .and(query_param("limit", "1000"))
+            .and(query_param("adjustment", "split"))
+            .and(query_param("feed", "iex"))
+            .and(query_param("asof", "2024-03-11"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"bars": {"AAPL": [bar_json("2024-03-08T14:30:00Z", 170.0)]}})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+        let bars = client.get_bars(&["AAPL"], &params).await.unwrap();
+        assert_eq!(bars["AAPL"].len(), 1);
+
+        let recent = HistoricalBarsParams::builder().last_days(3).build().unwrap();
+        let (start, end) = (recent.options().start.unwrap(), recent.options().end.unwrap());
+        assert!(end <= chrono::Utc::now() && start < end);
+        assert!(end - start < chrono::Duration::days(4));
+
+        // Before the open, today has no session yet
+        let early = HistoricalBarsParams::builder().last_days_at(0, utc("2024-05-02T12:00:00Z")).build().unwrap();
+        assert_eq!(early.options().start, Some(utc("2024-05-01T13:30:00Z")));
+        assert_eq!(early.options().end, Some(utc("2024-05-01T20:00:00Z")));
+
+        let during = HistoricalBarsParams::builder().last_days_at(0, utc("2024-05-02T15:00:00Z")).build().unwrap();
+        assert_eq!(during.options().start, Some(utc("2024-05-02T13:30:00Z")));
+        assert_eq!(during.options().end, Some(utc("2024-05-02T15:00:00Z")));
+    }
+
+    #[test]
+    fn test_historical_bars_params_validation() {
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+        let rejected = |builder: HistoricalBarsParamsBuilder| {
+            matches!(builder.build(), Err(AlpacaError::InvalidParameter(_)))
+        };
+        let builder = HistoricalBarsParams::builder;
+
+        // start strictly before end
+        let start = utc("2024-05-01T14:30:00Z");
+        assert!(rejected(builder().start(start).end(start)));
+        assert!(rejected(builder().start(start).end(start - chrono::Duration::minutes(1))));
+        assert!(builder().start(start).end(start + chrono::Duration::minutes(1)).build().is_ok());
+
+        // end in the future, beyond the tolerance
+        assert!(rejected(builder().end(chrono::Utc::now() + chrono::Duration::hours(1))));
+        assert!(builder().end(chrono::Utc::now() + chrono::Duration::seconds(5)).build().is_ok());
+
+        // limit bounds
+        assert!(rejected(builder().limit(0)));
+        assert!(rejected(builder().limit(10001)));
+        assert!(builder().limit(10000).build().is_ok());
+
+        // timeframes the server doesn't serve
+        assert!(rejected(builder().timeframe(TimeFrame::Minute(60))));
+        assert!(rejected(builder().timeframe(TimeFrame::Hour(0))));
+        assert!(rejected(builder().timeframe(TimeFrame::Month(5))));
+        assert!(builder().timeframe(TimeFrame::Month(6)).build().is_ok());
+
+        // adjusted bars off the otc feed
+        assert!(rejected(builder().feed(DataFeed::Otc).adjustment(Adjustment::Split)));
+        assert!(builder().feed(DataFeed::Otc).adjustment(Adjustment::Raw).build().is_ok());
+        assert!(builder().feed(DataFeed::Sip).adjustment(Adjustment::SpinOff).build().is_ok());
+        assert_eq!(serde_json::to_value(Adjustment::SpinOff).unwrap(), json!(Adjustment::SpinOff.to_string()));
+
+        // asof in the future or after the end
+        let tomorrow = chrono::Utc::now().date_naive() + chrono::Duration::days(2);
+        assert!(rejected(builder().asof(tomorrow)));
+        assert!(rejected(builder().end(start).asof(date("2024-05-02"))));
+        assert!(builder().end(start).asof(date("2024-05-01")).build().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_account_number_accessors() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "cash": "8000.50",
+                    "buying_power": "16001.00",
+                    "equity": 12000.5,
+                    "portfolio_value": "12000.50"
+                })))
+            .up_to_n_times(4)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"cash": "n/a", "equity": null})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        assert_eq!(client.cash().await.unwrap(), 8000.5);
+        assert_eq!(client.buying_power(None).await.unwrap(), 16001.0);
+        assert_eq!(client.equity().await.unwrap(), 12000.5);
+        assert_eq!(client.portfolio_value().await.unwrap(), 12000.5);
+
+        // A malformed or missing field is an error, never zero
+        match client.cash().await {
+            Err(AlpacaError::Other(message)) => assert!(message.contains("cash")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(client.equity().await.is_err());
+        assert!(client.portfolio_value().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_daily_change() {
+        let mock_server = MockServer::start().await;
+        for (equity, last_equity) in [("10500", "10000"), ("0", "0"), ("9000.00", "10000.00")] {
+            Mock::given(method("GET"))
+                .and(path("/v2/account"))
+                .respond_with(ResponseTemplate::new(200)
+                    .set_body_json(json!({"equity": equity, "last_equity": last_equity})))
+                .up_to_n_times(1)
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let up = client.daily_change().await.unwrap();
+        assert_eq!(up, DailyChange { equity: 10500.0, last_equity: 10000.0, change: 500.0, change_pct: Some(5.0) });
+
+        // Fresh account: nothing to divide by
+        let fresh = client.daily_change().await.unwrap();
+        assert_eq!((fresh.change, fresh.change_pct), (0.0, None));
+
+        let down = client.daily_change().await.unwrap();
+        assert_eq!((down.change, down.change_pct), (-1000.0, Some(-10.0)));
+    }
+
+    #[tokio::test]
+    async fn test_client_environment() {
+        let paper = test_client("https://paper-api.alpaca.markets", "https://data.alpaca.markets");
+        assert_eq!(paper.environment(), &Environment::Paper);
+        assert!(paper.is_paper());
+
+        let live = test_client("https://api.alpaca.markets/", "https://data.alpaca.markets");
+        assert_eq!(live.environment(), &Environment::Live);
+        assert!(!live.is_paper());
+
+        let mock_server = MockServer::start().await;
+        let mock = test_client(&mock_server.uri(), &mock_server.uri());
+        assert_eq!(mock.environment(), &Environment::Custom(mock_server.uri()));
+        assert!(!mock.is_paper());
+
+        // Same host without TLS is not the official one
+        assert!(matches!(Environment::from_url("http://api.alpaca.markets"), Environment::Custom(_)));
+
+        assert_eq!(serde_json::to_value(&paper).unwrap()["environment"], "paper");
+        assert_eq!(serde_json::to_value(&mock).unwrap()["environment"], json!({"custom": mock_server.uri()}));
+    }
+
+    #[test]
+    fn test_from_parts_validation() {
+        let client = AlpacaClient::from_parts("http://127.0.0.1:8080/", "http://127.0.0.1:8081", "key", "secret").unwrap();
+        assert_eq!((client.base_url(), client.data_url()), ("http://127.0.0.1:8080", "http://127.0.0.1:8081"));
+
+        for url in ["not a url", "ftp://example.com", "mailto:someone@example.com"] {
+            assert!(matches!(
+                AlpacaClient::from_parts(url, "https://data.alpaca.markets", "key", "secret"),
+                Err(AlpacaError::InvalidParameter(_))
+            ), "{}", url);
+        }
+        assert!(matches!(
+            AlpacaClient::from_parts("https://paper-api.alpaca.markets", "https://data.alpaca.markets", "bad\nkey", "secret"),
+            Err(AlpacaError::InvalidKeyFormat)
+        ));
+    }
+
+    fn minute_bar(timestamp: chrono::DateTime<chrono::Utc>, open: f64, close: f64, volume: f64) -> Bar {
+        Bar {
+            open,
+            high: open.max(close) + 0.5,
+            low: open.min(close) - 0.5,
+            close,
+            volume,
+            trade_count: volume as u64 / 10,
+            vwap: Some((open + close) / 2.0),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_resample_bars_keeps_totals() {
+        // Pseudo random minute bars over a whole day, with gaps
+        let mut seed = 42u64;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64
+        };
+        let start = utc("2024-05-01T08:00:00Z");
+        let mut bars = Vec::new();
+        let mut price = 100.0;
+        for minute in 0..(16 * 60) {
+            if next() < 0.2 {
+                continue;
+            }
+            let close = price + next() - 0.5;
+            bars.push(minute_bar(start + chrono::Duration::minutes(minute), price, close, (next() * 1000.0).round() + 1.0));
+            price = close;
+        }
+
+        for target in [TimeFrame::Minute(5), TimeFrame::Minute(15), TimeFrame::Hour(1), TimeFrame::Day] {
+            let resampled = resample_bars(&bars, target);
+            assert!(resampled.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp), "{}", target);
+
+            let volume = |bars: &[Bar]| bars.iter().map(|bar| bar.volume).sum::<f64>();
+            let trades = |bars: &[Bar]| bars.iter().map(|bar| bar.trade_count).sum::<u64>();
+            let notional = |bars: &[Bar]| bars.iter().map(|bar| bar.vwap.unwrap() * bar.volume).sum::<f64>();
+            assert_eq!(volume(&resampled), volume(&bars), "{}", target);
+            assert_eq!(trades(&resampled), trades(&bars), "{}", target);
+            assert!((notional(&resampled) - notional(&bars)).abs() < 1e-6, "{}", target);
+
+            let high = |bars: &[Bar]| bars.iter().map(|bar| bar.high).fold(f64::MIN, f64::max);
+            let low = |bars: &[Bar]| bars.iter().map(|bar| bar.low).fold(f64::MAX, f64::min);
+            assert_eq!(high(&resampled), high(&bars));
+            assert_eq!(low(&resampled), low(&bars));
+            assert_eq!(resampled[0].open, bars[0].open);
+            assert_eq!(resampled.last().unwrap().close, bars.last().unwrap().close);
+        }
+
+        assert_eq!(resample_bars(&bars, TimeFrame::Day).len(), 1);
+        assert!(resample_bars(&[], TimeFrame::Hour(1)).is_empty());
+    }
+
+    #[test]
+    fn test_resample_bars_session_open() {
+        // 09:28 to 09:41 in New York, daylight time
+        let bar = |time: &str, open: f64, close: f64, volume: f64| {
+            minute_bar(utc(&format!("2024-05-01T{}:00Z", time)), open, close, volume)
+        };
+        let bars = vec![
+            bar("13:28", 10.0, 11.0, 100.0),
+            bar("13:29", 11.0, 12.0, 300.0),
+            bar("13:30", 12.0, 13.0, 200.0),
+            bar("13:31", 13.0, 12.5, 200.0),
+            // 13:32 to 13:39 missing
+            bar("13:41", 12.5, 14.0, 50.0),
+        ];
+
+        let five = resample_bars(&bars, TimeFrame::Minute(5));
+        let starts: Vec<_> = five.iter().map(|bar| bar.timestamp).collect();
+        assert_eq!(starts, vec![utc("2024-05-01T13:25:00Z"), utc("2024-05-01T13:30:00Z"), utc("2024-05-01T13:40:00Z")]);
+        assert_eq!((five[0].open, five[0].close, five[0].volume), (10.0, 12.0, 400.0));
+        assert_eq!((five[0].high, five[0].low), (12.5, 9.5));
+        assert_eq!(five[0].vwap, Some((10.5 * 100.0 + 11.5 * 300.0) / 400.0));
+        assert_eq!((five[1].open, five[1].close, five[1].trade_count), (12.0, 12.5, 40));
+
+        // Hours run from the open, so the pre-market minutes are apart
+        let hourly = resample_bars(&bars, TimeFrame::Hour(1));
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(hourly[0].timestamp, utc("2024-05-01T12:30:00Z"));
+        assert_eq!(hourly[1].timestamp, utc("2024-05-01T13:30:00Z"));
+        assert_eq!((hourly[1].open, hourly[1].close, hourly[1].volume), (12.0, 14.0, 450.0));
+
+        // A bar without vwap leaves its bucket without one
+        let mut partial = bars.clone();
+        partial[0].vwap = None;
+        let five = resample_bars(&partial, TimeFrame::Minute(5));
+        assert_eq!((five[0].vwap, five[1].vwap.is_some()), (None, true));
+    }
+
+    fn trade(timestamp: &str, price: f64, size: u64, conditions: &[&str]) -> Trade {
+        Trade {
+            price,
+            size,
+            exchange: "V".to_string(),
+            conditions: conditions.iter().map(|c| c.to_string()).collect(),
+            timestamp: utc(timestamp),
+        }
+    }
+
+    #[test]
+    fn test_vwap() {
+        let trades = vec![
+            trade("2024-05-01T13:30:00Z", 10.0, 100, &["@"]),
+            trade("2024-05-01T13:31:00Z", 11.0, 300, &["@"]),
+            trade("2024-05-01T13:32:00Z", 50.0, 5, &["@", "I"]),
+            trade("2024-05-01T13:33:00Z", 12.0, 100, &["@"]),
+        ];
+        let regular = |trade: &Trade| !trade.conditions.iter().any(|c| c == "I" || c == "4");
+
+        // (1000 + 3300 + 1200) / 500
+        assert_eq!(vwap_by(&trades, regular), Some(11.0));
+        // (5500 + 250) / 505
+        assert_eq!(vwap(&trades), Some(5750.0 / 505.0));
+
+        assert_eq!(vwap(&[]), None);
+        assert_eq!(vwap(&[trade("2024-05-01T13:30:00Z", 10.0, 0, &[])]), None);
+        assert_eq!(vwap_by(&trades, |_| false), None);
+
+        let mut rolling = RollingVwap::new(VwapAnchor::At(utc("2024-05-01T13:00:00Z"))).with_filter(regular);
+        assert_eq!(rolling.value(), None);
+        for (count, _) in trades.iter().enumerate() {
+            rolling.push(&trades[count]);
+            assert_eq!(rolling.value(), vwap_by(&trades[..=count], regular));
+        }
+        assert_eq!(rolling.volume(), 500);
+
+        // Trades before a custom anchor don't count
+        let mut rolling = RollingVwap::new(VwapAnchor::At(utc("2024-05-01T13:31:00Z")));
+        rolling.extend(&trades);
+        assert_eq!(rolling.value(), vwap(&trades[1..]));
+    }
+
+    #[test]
+    fn test_rolling_vwap_session() {
+        let mut rolling = RollingVwap::new(VwapAnchor::Session);
+        assert_eq!(rolling.start(), None);
+
+        // Pre-market is ignored
+        rolling.push(&trade("2024-05-01T12:00:00Z", 99.0, 1000, &[]));
+        assert_eq!((rolling.value(), rolling.start()), (None, None));
+
+        rolling.push(&trade("2024-05-01T13:30:00Z", 10.0, 100, &[]));
+        rolling.push(&trade("2024-05-01T15:00:00Z", 12.0, 100, &[]));
+        assert_eq!(rolling.value(), Some(11.0));
+        assert_eq!(rolling.start(), Some(utc("2024-05-01T13:30:00Z")));
+
+        // Next pre-market keeps the day, the next open starts over
+        rolling.push(&trade("2024-05-02T12:00:00Z", 99.0, 1000, &[]));
+        assert_eq!(rolling.value(), Some(11.0));
+        rolling.push(&trade("2024-05-02T13:45:00Z", 20.0, 10, &[]));
+        assert_eq!((rolling.value(), rolling.volume()), (Some(20.0), 10));
+        assert_eq!(rolling.start(), Some(utc("2024-05-02T13:30:00Z")));
+
+        // A late trade of the previous session is dropped
+        rolling.push(&trade("2024-05-01T19:00:00Z", 1.0, 1000, &[]));
+        assert_eq!(rolling.value(), Some(20.0));
+    }
+
+    #[tokio::test]
+    async fn test_get_option_snapshots_greeks() {
+        let mock_server = MockServer::start().await;
+        let liquid = "AAPL240628C00190000";
+        let illiquid = "AAPL261218P00005000";
+
+        Mock::given(method("GET"))
+            .and(path("/v1beta1/options/snapshots"))
+            .and(query_param("symbols", format!("{},{}", liquid, illiquid)))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "snapshots": {
+                        liquid: {
+                            "latestQuote": {"ap": 2.35, "as": 12, "ax": "C", "bp": 2.3, "bs": 7, "bx": "M", "c": "A", "t": "2024-06-24T19:59:59.5Z"},
+                            "latestTrade": {"c": "I", "p": 2.32, "s": 3, "t": "2024-06-24T19:59:12Z", "x": "C"},
+                            "greeks": {"delta": 0.52, "gamma": 0.061, "rho": 0.012, "theta": -0.35, "vega": 0.08},
+                            "impliedVolatility": 0.241
+                        },
+                        illiquid: {
+                            "latestQuote": {"ap": 0.05, "as": 1, "ax": "C", "bp": 0.0, "bs": 0, "bx": "C", "c": " ", "t": "2024-06-20T14:00:00Z"}
+                        }
+                    },
+                    "next_page_token": null
+                })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+        let snapshots = client.get_option_snapshots(&[liquid, illiquid]).await.unwrap();
+
+        let snapshot = &snapshots[liquid];
+        assert!(snapshot.has_greeks());
+        assert_eq!(snapshot.greeks, Some(OptionGreeks {
+            delta: Some(0.52),
+            gamma: Some(0.061),
+            theta: Some(-0.35),
+            vega: Some(0.08),
+            rho: Some(0.012),
+        }));
+        assert_eq!(snapshot.implied_volatility, Some(0.241));
+        assert_eq!(snapshot.latest_trade.as_ref().unwrap().price, 2.32);
+        assert_eq!(snapshot.latest_trade.as_ref().unwrap().conditions, vec!["I"]);
+        assert_eq!(snapshot.latest_quote.as_ref().unwrap().mid(), 2.325);
+
+        let snapshot = &snapshots[illiquid];
+        assert!(!snapshot.has_greeks());
+        assert_eq!((&snapshot.greeks, snapshot.implied_volatility), (&None, None));
+        assert!(snapshot.latest_trade.is_none());
+
+        // Empty greeks are no greeks either
+        let partial: OptionSnapshot = serde_json::from_value(json!({"greeks": {}})).unwrap();
+        assert!(!partial.has_greeks());
+        let partial: OptionSnapshot = serde_json::from_value(json!({"greeks": {"delta": -0.1}})).unwrap();
+        assert!(partial.has_greeks());
+
+        assert!(client.get_option_snapshots(&[]).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_occ_symbol() {
+        let parsed = parse_occ_symbol("AAPL240628C00190000").unwrap();
+        assert_eq!(parsed, OccSymbol {
+            underlying: "AAPL".to_string(),
+            expiration: chrono::NaiveDate::from_ymd_opt(2024, 6, 28).unwrap(),
+            option_type: OptionType::Call,
+            strike: 190.0,
+        });
+
+        let parsed = parse_occ_symbol("SPY   261218P00412500").unwrap();
+        assert_eq!((parsed.underlying.as_str(), parsed.option_type, parsed.strike), ("SPY", OptionType::Put, 412.5));
+        assert_eq!(parsed.to_string(), "SPY261218P00412500");
+
+        for symbol in ["AAPL240628C00190000", "F250117P00000500", "BRKB251219C01234567", "GOOGL270115P00150000"] {
+            assert_eq!(parse_occ_symbol(symbol).unwrap().to_string(), symbol);
+        }
+
+        for symbol in ["", "AAPL", "240628C00190000", "AAPL240628X00190000", "AAPL241328C00190000",
+                       "AAPL240628C0019000A", "aapl240628C00190000", "TOOLONG240628C00190000",
+                       "AAPL24062\u{e9}C0019000", "AAPL240628\u{e9}0019000"] {
+            assert!(matches!(parse_occ_symbol(symbol), Err(AlpacaError::InvalidParameter(_))), "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn test_wrapper_fetches_configured_price_types() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mock_server = runtime.block_on(MockServer::start());
+        let now = chrono::Utc::now().to_rfc3339();
+
+        runtime.block_on(async {
+            for (price_type, price) in [
+                ("trades", json!({"p": 100.0, "s": 1, "x": "V", "t": now})),
+                ("quotes", json!({"bp": 99.5, "bs": 1, "ap": 100.5, "as": 2, "t": now})),
+                ("bars", json!({"o": 100.0, "h": 101.0, "l": 99.0, "c": 100.0, "v": 10, "t": now})),
+            ] {
+                Mock::given(method("GET"))
+                    .and(path(format!("/v2/stocks/{}/latest", price_type)))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(json!({price_type: {"AAPL": price}})))
+                    .mount(&mock_server)
+                    .await;
+            }
+        });
+
+        let data_requests = || {
+            runtime.block_on(mock_server.received_requests()).unwrap()
+                .iter()
+                .filter(|request| request.url.path().starts_with("/v2/stocks/") && request.url.path().ends_with("/latest"))
+                .map(|request| request.url.path().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        let options = PriceOptions::default().source(PriceSource::Latest);
+        let wrapper = AlpacaWrapper::with_client_options(std::sync::Arc::new(client), vec!["AAPL".to_string()], options).unwrap();
+
+        // All three by default, never from the unmocked snapshots
+        assert_eq!(wrapper.price_types(), &[PriceType::Trades, PriceType::Quotes, PriceType::Bars]);
+        assert_eq!(data_requests().len(), 3);
+        assert_eq!(wrapper.latest_trade("AAPL").unwrap().price, 100.0);
+        assert_eq!(wrapper.latest_bar("AAPL").unwrap().close, 100.0);
+
+        let wrapper = wrapper.with_price_types(&[PriceType::Quotes]);
+        assert_eq!(wrapper.price_types(), &[PriceType::Quotes]);
+        assert!(wrapper.latest_bar("AAPL").is_none());
+
+        for cycle in 1..=2 {
+            wrapper.update_prices();
+            let requests = data_requests();
+            assert_eq!(requests.len(), 3 + cycle);
+            assert_eq!(requests.last().unwrap(), "/v2/stocks/quotes/latest");
+        }
+
+        assert_eq!(wrapper.latest_quote("AAPL").unwrap().ask_price, 100.5);
+        assert!(wrapper.latest_trade("AAPL").is_none());
+        assert!(wrapper.latest_bar("AAPL").is_none());
+        assert!(wrapper.latest_quote("MSFT").is_none());
+    }
+
+    /// Records of the `log` crate, kept for the tests looking at them.
+    struct CapturedLog(std::sync::Mutex<Vec<(log::Level, String)>>);
+
+    impl log::Log for CapturedLog {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+        fn flush(&self) {}
+    }
+
+    /// Logged records containing `marker`, the rest coming from other tests.
+    fn captured_log(marker: &str) -> Vec<(log::Level, String)> {
+        static LOG: std::sync::OnceLock<&'static CapturedLog> = std::sync::OnceLock::new();
+        let logger = LOG.get_or_init(|| {
+            let logger = Box::leak(Box::new(CapturedLog(Default::default())));
+            log::set_logger(logger).unwrap();
+            log::set_max_level(log::LevelFilter::Debug);
+            logger
+        });
+
+        logger.0.lock().unwrap().iter()
+            .filter(|(_, message)| message.contains(marker))
+            .cloned()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_body_logging_errors() {
+        let key = "PKTEST12345ABCDEFGHI";
+        let secret = "abcdefghijklmnopqrstuvwxyz1234567890ABCDEFG";
+        captured_log("");
+
+        let mock_server = MockServer::start().await;
+        let message = format!("rejected BODYLOG, key {} secret {} {}", key, secret, "x".repeat(500));
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(json!({"code": 40010001, "message": message})))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "BODYLOG-account"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_body_logging(BodyLogging::Errors, 300);
+
+        // Successful requests log nothing
+        client.get_account().await.unwrap();
+        assert!(captured_log("BODYLOG-account").is_empty());
+
+        let order = OrderRequest::limit("BODYLOG", 1, OrderSide::Buy, 10.0).unwrap().client_order_id(key);
+        assert!(client.submit_order(&order).await.is_err());
+
+        let logged: Vec<_> = captured_log("body: ").into_iter().filter(|(_, text)| text.contains("BODYLOG")).collect();
+        assert_eq!(logged.len(), 2, "{:?}", logged);
+        assert!(logged.iter().all(|(level, _)| *level == log::Level::Warn));
+
+        let (_, request) = &logged[0];
+        assert!(request.starts_with("POST /v2/orders request body: {"), "{}", request);
+        assert!(request.contains("\"symbol\":\"BODYLOG\""), "{}", request);
+        assert!(request.contains("\"client_order_id\":\"***\""), "{}", request);
+
+        let (_, response) = &logged[1];
+        let prefix = "POST /v2/orders response body: ";
+        assert!(response.starts_with(prefix), "{}", response);
+        let body = response[prefix.len()..].split("... (").next().unwrap();
+        assert_eq!(body.len(), 300);
+        assert!(body.contains("rejected BODYLOG, key *** secret ***"), "{}", body);
+        assert!(response.ends_with(&format!("... ({} bytes)", client.redact(json!({"code": 40010001, "message": message})).len())), "{}", response);
+
+        for (_, text) in &logged {
+            assert!(!text.contains(secret) && !text.contains(key), "{}", text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_body_logging_all() {
+        captured_log("");
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "BODYALL-order"})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+        let order = OrderRequest::market("BODYALL", 1, OrderSide::Buy);
+
+        client.submit_order(&order).await.unwrap();
+        assert!(captured_log("BODYALL").iter().all(|(_, text)| !text.contains("body: ")));
+
+        let client = client.with_body_logging(BodyLogging::All, DEFAULT_BODY_LOG_LIMIT);
+        client.submit_order(&order).await.unwrap();
+
+        let logged: Vec<_> = captured_log("BODYALL").into_iter().filter(|(_, text)| text.contains("body: ")).collect();
+        assert_eq!(logged.len(), 2, "{:?}", logged);
+        assert!(logged.iter().all(|(level, _)| *level == log::Level::Debug));
+        assert!(logged[0].1.starts_with("POST /v2/orders request body: {") && logged[0].1.contains("\"symbol\":\"BODYALL\""));
+        assert_eq!(logged[1].1, "POST /v2/orders response body: {\"id\":\"BODYALL-order\"}");
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_category_timeouts() {
+        let mock_server = MockServer::start().await;
+        let delay = std::time::Duration::from_millis(300);
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/AAPL/quotes/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"quote": {"bp": 1.0, "bs": 1, "ap": 1.1, "as": 1, "t": "2024-05-01T13:30:00Z"}}))
+                .set_delay(delay))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/positions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])).set_delay(delay))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri())
+            .with_retry_policy(RetryPolicy { max_retries: 0, ..Default::default() })
+            .with_timeout(EndpointCategory::MarketDataLatest, std::time::Duration::from_millis(50));
+
+        assert_eq!(client.timeout_for(EndpointCategory::MarketDataLatest), std::time::Duration::from_millis(50));
+        assert_eq!(client.timeout_for(EndpointCategory::Trading), DEFAULT_TIMEOUT);
+
+        match client.get_latest_quote("AAPL").await {
+            Err(AlpacaError::Timeout { endpoint, timeout, .. }) => {
+                assert_eq!(endpoint, "/v2/stocks/AAPL/quotes/latest");
+                assert_eq!(timeout, std::time::Duration::from_millis(50));
+            },
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        assert_eq!(client.get_positions().await.unwrap(), json!([]));
+
+        // The trading timeout only covers the trading API
+        let trading = client.with_timeout(EndpointCategory::Trading, std::time::Duration::from_millis(50));
+        assert!(matches!(trading.get_positions().await, Err(AlpacaError::Timeout { .. })));
+    }
+
+    #[test]
+    fn test_endpoint_category_of() {
+        assert_eq!(EndpointCategory::of("/v2/orders"), EndpointCategory::Trading);
+        assert_eq!(EndpointCategory::of("/v2/account"), EndpointCategory::Trading);
+        assert_eq!(EndpointCategory::of("/v2/stocks/quotes/latest"), EndpointCategory::MarketDataLatest);
+        assert_eq!(EndpointCategory::of("/v1beta3/crypto/us/latest/orderbooks"), EndpointCategory::MarketDataLatest);
+        assert_eq!(EndpointCategory::of("/v1beta1/options/snapshots"), EndpointCategory::MarketDataLatest);
+        assert_eq!(EndpointCategory::of("/v2/stocks/bars"), EndpointCategory::MarketDataHistorical);
+        assert_eq!(EndpointCategory::of("/v1beta1/news"), EndpointCategory::MarketDataHistorical);
+    }
+
+    #[test]
+    fn test_wrapper_shutdown_stops_background_updates() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let dir = std::env::temp_dir().join(format!("alpaca-shutdown-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let journal = std::sync::Arc::new(Journal::csv(dir.join("journal.csv")).unwrap());
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_journal(journal.clone(), "shutdown");
+        let price_requests = || mock.calls().iter().filter(|call| matches!(call, MockCall::GetPrices { .. })).count();
+        let before = price_requests();
+
+        wrapper.start_background_updates(std::time::Duration::from_millis(10));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(price_requests() > before + 1);
+
+        // The background prices reach the cache
+        mock.set_price("AAPL", 120.0);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(wrapper.latest_trade("AAPL").unwrap().price, 120.0);
+
+        wrapper.manage_buy_signal("AAPL").expect("buy order");
+        let report = wrapper.shutdown(std::time::Duration::from_secs(1));
+        assert_eq!(report, ShutdownReport { exited: vec!["price_updates".to_string()], ..Default::default() });
+        assert!(report.is_clean());
+
+        // Nothing runs after the shutdown and the journal is on disk
+        let after = price_requests();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(price_requests(), after);
+        let written = std::fs::read_to_string(dir.join("journal.csv")).unwrap();
+        assert_eq!(written.lines().count(), 3, "{}", written);
+
+        // Later starts exit right away
+        wrapper.start_background_updates(std::time::Duration::from_millis(10));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(price_requests(), after);
+        assert!(wrapper.shutdown(std::time::Duration::from_secs(1)).is_clean());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrapper_with_client_end_to_end() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let trading = runtime.block_on(MockServer::start());
+        let data = runtime.block_on(MockServer::start());
+        let now = chrono::Utc::now().to_rfc3339();
+
+        runtime.block_on(async {
+            Mock::given(method("GET"))
+                .and(path("/v2/account"))
+                .and(header("APCA-API-KEY-ID", "PKTEST12345ABCDEFGHI"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "e2e", "cash": "1000"})))
+                .mount(&trading)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/v2/positions"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+                .mount(&trading)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/v2/assets/AAPL"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "a1", "class": "us_equity", "exchange": "NASDAQ", "symbol": "AAPL",
+                    "status": "active", "tradable": true, "fractionable": true
+                })))
+                .expect(1)
+                .mount(&trading)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/v2/clock"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "timestamp": now, "is_open": true,
+                    "next_open": "2030-01-02T09:30:00-05:00", "next_close": "2030-01-01T16:00:00-05:00"
+                })))
+                .mount(&trading)
+                .await;
+            Mock::given(method("POST"))
+                .and(path("/v2/orders"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "id": "e2e-order", "symbol": "AAPL", "side": "buy", "qty": "6.25",
+                    "status": "accepted", "filled_qty": "0"
+                })))
+                .expect(1)
+                .mount(&trading)
+                .await;
+
+            Mock::given(method("GET"))
+                .and(path("/v2/stocks/snapshots"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({"AAPL": {
+                    "latestTrade": {"p": 160.0, "s": 1, "x": "V", "t": now},
+                    "latestQuote": {"bp": 159.9, "bs": 1, "ap": 160.0, "as": 2, "t": now},
+                    "minuteBar": {"o": 159.0, "h": 161.0, "l": 158.0, "c": 160.0, "v": 10, "t": now},
+                }})))
+                .mount(&data)
+                .await;
+        });
+
+        let client = std::sync::Arc::new(test_client(&trading.uri(), &data.uri()));
+        let wrapper = AlpacaWrapper::with_client(client.clone(), vec!["AAPL".to_string()]).unwrap();
+        assert_eq!(wrapper.environment(), &Environment::Custom(trading.uri()));
+        assert_eq!(wrapper.equity(), 1000.0);
+        // The cash update went through the cached account
+        assert_eq!(client.account_info()["id"], "e2e");
+        assert_eq!(wrapper.latest_quote("AAPL").unwrap().ask_price, 160.0);
+        assert_eq!(wrapper.last_n_closes("AAPL", 1), Some(vec![160.0]));
+
+        // Fractional, from the asset
+        assert_eq!(wrapper.size_fixed_fraction("AAPL", 1.0), Some(6.25));
+        let order = wrapper.manage_buy_signal("AAPL").expect("buy order");
+        assert_eq!(order["id"], "e2e-order");
+
+        let submitted = runtime.block_on(trading.received_requests()).unwrap().into_iter()
+            .find(|request| request.method == Method::POST)
+            .unwrap();
+        let body: Value = serde_json::from_slice(&submitted.body).unwrap();
+        assert_eq!((body["symbol"].as_str(), body["qty"].as_str(), body["side"].as_str()), (Some("AAPL"), Some("6.25"), Some("buy")));
+
+        let client = test_client(&trading.uri(), &data.uri());
+        assert!(matches!(
+            AlpacaWrapper::with_client(std::sync::Arc::new(client), vec![]),
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            AlpacaWrapper::with_api(std::sync::Arc::new(MockAlpaca::new(1000.0)), vec![]),
+            Err(AlpacaError::InvalidParameter(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_account() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "acc", "cash": "100", "status": "ACTIVE"})))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"id": "acc", "cash": "250", "status": "ACCOUNT_UPDATED"}))
+                .set_delay(std::time::Duration::from_millis(300)))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+        assert_eq!(*client.account_info(), Value::Null);
+        assert_eq!(client.account_info_age(), None);
+
+        let account = client.refresh_account().await.unwrap();
+        assert_eq!(account["cash"], "100");
+        assert_eq!(client.account_info(), account);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let age = client.account_info_age().unwrap();
+        assert!(age >= std::time::Duration::from_millis(50));
+
+        // Readers get the previous account during the slow refresh
+        let refresh = tokio::spawn({
+            let client = client.clone();
+            async move { client.refresh_account().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let started = std::time::Instant::now();
+        for _ in 0..1000 {
+            assert_eq!(client.account_info()["cash"], "100");
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+        assert!(!refresh.is_finished());
+
+        refresh.await.unwrap().unwrap();
+        assert_eq!(client.account_info()["status"], "ACCOUNT_UPDATED");
+        assert!(client.account_info_age().unwrap() < age);
+
+        // A failed refresh keeps the cached account
+        let failing = test_client("http://127.0.0.1:9", "https://data.example.com");
+        assert!(failing.refresh_account().await.is_err());
+        assert_eq!(failing.account_info_age(), None);
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let http = |status: u16| AlpacaError::HttpError { status: StatusCode::from_u16(status).unwrap(), endpoint: String::new(), message: String::new() };
+        let api = |status: u16| AlpacaError::ApiError { code: 40010000, message: String::new(), status: StatusCode::from_u16(status).unwrap(), endpoint: String::new() };
+        let mapped = |status: u16, code: u64| crate::alpaca_client::map_api_error(
+            StatusCode::from_u16(status).unwrap(), "/v2/orders", json!({"code": code, "message": ""}).to_string(),
+        );
+        let request_error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let order: Order = serde_json::from_value(order_json("o1", "limit", "buy", "new")).unwrap();
+        let timeout = std::time::Duration::from_secs(1);
+
+        // (error, status, retryable, auth, client bug)
+        let cases: Vec<(AlpacaError, Option<u16>, bool, bool, bool)> = vec![
+            (AlpacaError::InvalidKeyFormat, None, false, true, false),
+            (http(400), Some(400), false, false, true),
+            (http(401), Some(401), false, true, false),
+            (http(403), Some(403), false, true, false),
+            (http(422), Some(422), false, false, true),
+            (http(429), Some(429), true, false, false),
+            (http(500), Some(500), true, false, false),
+            (http(503), Some(503), true, false, false),
+            (AlpacaError::RequestError(request_error), None, false, false, false),
+            (AlpacaError::JsonError(json_error), None, false, false, false),
+            (AlpacaError::InvalidParameter("limit".to_string()), None, false, false, true),
+            (AlpacaError::DayTradeBlocked { reason: "pdt".to_string() }, None, false, false, false),
+            (AlpacaError::InsufficientBuyingPower { needed: 2.0, available: 1.0, status: None, code: None }, None, false, false, false),
+            (AlpacaError::AssetNotTradable { message: String::new(), status: None, code: None }, None, false, false, false),
+            (AlpacaError::InvalidOrder { message: String::new(), status: None, code: None }, None, false, false, true),
+            (mapped(403, 40310000), Some(403), false, false, true),
+            (mapped(422, 42210000), Some(422), false, false, true),
+            (mapped(422, 40010001), Some(422), false, false, true),
+            (api(401), Some(401), false, true, false),
+            (api(403), Some(403), false, false, true),
+            (api(422), Some(422), false, false, true),
+            (api(429), Some(429), true, false, false),
+            (api(502), Some(502), true, false, false),
+            (AlpacaError::CircuitOpen { retry_at: std::time::Instant::now() }, None, false, false, false),
+            (AlpacaError::ResponseTooLarge { limit: 1, received_at_abort: 2 }, None, false, false, false),
+            (AlpacaError::ChunkFailed { symbols: vec!["AAPL".to_string()], source: Box::new(http(503)) }, Some(503), true, false, false),
+            (AlpacaError::ChunkFailed { symbols: vec!["AAPL".to_string()], source: Box::new(http(400)) }, Some(400), false, false, true),
+            (AlpacaError::Shared(std::sync::Arc::new(http(429))), Some(429), true, false, false),
+            (AlpacaError::Shared(std::sync::Arc::new(http(401))), Some(401), false, true, false),
+            (AlpacaError::DownloadInterrupted { summary: DownloadSummary::default(), source: Box::new(http(500)) }, Some(500), true, false, false),
+            (AlpacaError::IoError(std::io::Error::other("disk")), None, false, false, false),
+            (AlpacaError::MarketClosed { next_open: "2024-05-02T09:30:00-04:00".parse().unwrap() }, None, false, false, false),
+            (AlpacaError::NotFound { resource: "orders/o1".to_string() }, Some(404), false, false, false),
+            (AlpacaError::ConnectionError { method: reqwest::Method::GET, endpoint: "/v2/account".to_string(), timeout, message: String::new() }, None, true, false, false),
+            (AlpacaError::Timeout { method: reqwest::Method::GET, endpoint: "/v2/account".to_string(), timeout }, None, true, false, false),
+            (AlpacaError::UnrecordedRequest { method: "GET".to_string(), endpoint: "/v2/account".to_string() }, None, false, false, false),
+            (AlpacaError::OrderWaitTimeout { deadline: timeout, last: Box::new(order) }, None, false, false, false),
+            (AlpacaError::MissingSymbols { symbols: vec!["XYZQ".to_string()] }, None, false, false, false),
+            (AlpacaError::Other("other".to_string()), None, false, false, false),
+        ];
+
+        for (error, status, retryable, auth, client_bug) in cases {
+            assert_eq!(error.status().map(|status| status.as_u16()), status, "{:?}", error);
+            assert_eq!(error.is_retryable(), retryable, "{:?}", error);
+            assert_eq!(error.is_auth(), auth, "{:?}", error);
+            assert_eq!(error.is_client_bug(), client_bug, "{:?}", error);
+            // The retry policy agrees
+            assert_eq!(RetryPolicy::is_transient(&error), retryable, "{:?}", error);
+            assert_eq!(RetryPolicy::default().should_retry(&reqwest::Method::GET, false, false, &error, 1), retryable, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn test_error_serialize() {
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let order: Order = serde_json::from_value(order_json("o1", "limit", "buy", "new")).unwrap();
+        let timeout = std::time::Duration::from_millis(1500);
+        let http = |status: StatusCode, message: &str| AlpacaError::HttpError { status, endpoint: "/v2/orders".to_string(), message: message.to_string() };
+
+        let cases = vec![
+            (AlpacaError::InvalidKeyFormat,
+             json!({"kind": "invalid_key_format", "message": "Invalid API key or secret format", "retryable": false})),
+            (http(StatusCode::BAD_GATEWAY, "bad gateway"),
+             json!({"kind": "http", "message": "bad gateway", "status": 502, "retryable": true, "endpoint": "/v2/orders"})),
+            (AlpacaError::JsonError(json_error),
+             json!({"kind": "json", "message": "JSON error: EOF while parsing an object at line 1 column 1", "retryable": false})),
+            (AlpacaError::InvalidParameter("limit too large".to_string()),
+             json!({"kind": "invalid_parameter", "message": "limit too large", "retryable": false})),
+            (AlpacaError::DayTradeBlocked { reason: "3 day trades".to_string() },
+             json!({"kind": "day_trade_blocked", "message": "3 day trades", "retryable": false})),
+            (AlpacaError::InsufficientBuyingPower { needed: 200.0, available: 150.5, status: None, code: None },
+             json!({"kind": "insufficient_buying_power", "message": "Insufficient buying power: order needs 200.00, 150.50 available",
+                    "retryable": false, "needed": 200.0, "available": 150.5})),
+            (AlpacaError::AssetNotTradable { message: "asset XYZ is not active".to_string(), status: None, code: None },
+             json!({"kind": "asset_not_tradable", "message": "asset XYZ is not active", "retryable": false})),
+            (AlpacaError::InvalidOrder { message: "qty must be > 0".to_string(), status: None, code: None },
+             json!({"kind": "invalid_order", "message": "qty must be > 0", "retryable": false})),
+            // Mapped from an answer of the server
+            (crate::alpaca_client::map_api_error(StatusCode::FORBIDDEN, "/v2/orders",
+                json!({"code": 40310000, "message": "insufficient buying power", "cost_basis": "200", "buying_power": "150.5"}).to_string()),
+             json!({"kind": "insufficient_buying_power", "message": "Insufficient buying power: order needs 200.00, 150.50 available",
+                    "status": 403, "retryable": false, "code": 40310000, "needed": 200.0, "available": 150.5})),
+            (crate::alpaca_client::map_api_error(StatusCode::UNPROCESSABLE_ENTITY, "/v2/orders",
+                json!({"code": 42210000, "message": "asset XYZ is not active"}).to_string()),
+             json!({"kind": "asset_not_tradable", "message": "asset XYZ is not active", "status": 422, "retryable": false, "code": 42210000})),
+            (crate::alpaca_client::map_api_error(StatusCode::UNPROCESSABLE_ENTITY, "/v2/orders",
+                json!({"code": 40010001, "message": "qty must be > 0"}).to_string()),
+             json!({"kind": "invalid_order", "message": "qty must be > 0", "status": 422, "retryable": false, "code": 40010001})),
+            (AlpacaError::ApiError { code: 40010001, message: "invalid order type".to_string(), status: StatusCode::UNPROCESSABLE_ENTITY, endpoint: "/v2/orders".to_string() },
+             json!({"kind": "api", "message": "invalid order type", "status": 422, "retryable": false, "code": 40010001, "endpoint": "/v2/orders"})),
+            (AlpacaError::ResponseTooLarge { limit: 10, received_at_abort: 20 },
+             json!({"kind": "response_too_large", "message": "Response larger than 10 bytes, aborted after 20", "retryable": false, "limit": 10})),
+            (AlpacaError::ChunkFailed { symbols: vec!["AAPL".to_string()], source: Box::new(http(StatusCode::SERVICE_UNAVAILABLE, "down")) },
+             json!({"kind": "chunk_failed", "message": "Request for symbols [\"AAPL\"] failed: HTTP error 503 Service Unavailable: down",
+                    "status": 503, "retryable": true, "symbols": ["AAPL"], "source": "HTTP error 503 Service Unavailable: down"})),
+            (AlpacaError::Shared(std::sync::Arc::new(http(StatusCode::TOO_MANY_REQUESTS, "slow down"))),
+             json!({"kind": "http", "message": "slow down", "status": 429, "retryable": true, "endpoint": "/v2/orders"})),
+            (AlpacaError::DownloadInterrupted {
+                summary: DownloadSummary { rows: 42, ..Default::default() },
+                source: Box::new(AlpacaError::Other("disk full".to_string())),
+             },
+             json!({"kind": "download_interrupted", "message": "Download interrupted after 42 rows: Other error: disk full",
+                    "retryable": false, "rows": 42, "source": "Other error: disk full"})),
+            (AlpacaError::IoError(std::io::Error::other("disk full")),
+             json!({"kind": "io", "message": "IO error: disk full", "retryable": false})),
+            (AlpacaError::MarketClosed { next_open: "2024-05-02T09:30:00-04:00".parse().unwrap() },
+             json!({"kind": "market_closed", "message": "Market closed until 2024-05-02 09:30:00 -04:00", "retryable": false,
+                    "next_open": "2024-05-02T09:30:00-04:00"})),
+            (AlpacaError::NotFound { resource: "orders/o1".to_string() },
+             json!({"kind": "not_found", "message": "Not found: orders/o1", "status": 404, "retryable": false, "resource": "orders/o1"})),
+            (AlpacaError::ConnectionError { method: reqwest::Method::POST, endpoint: "/v2/orders".to_string(), timeout, message: "refused".to_string() },
+             json!({"kind": "connection", "message": "refused", "retryable": true,
+                    "method": "POST", "endpoint": "/v2/orders", "timeout_ms": 1500})),
+            (AlpacaError::Timeout { method: reqwest::Method::GET, endpoint: "/v2/account".to_string(), timeout },
+             json!({"kind": "timeout", "message": "Timeout after 1.5s on GET /v2/account", "retryable": true,
+                    "method": "GET", "endpoint": "/v2/account", "timeout_ms": 1500})),
+            (AlpacaError::UnrecordedRequest { method: "GET".to_string(), endpoint: "/v2/clock".to_string() },
+             json!({"kind": "unrecorded_request", "message": "No recorded interaction for GET /v2/clock", "retryable": false,
+                    "method": "GET", "endpoint": "/v2/clock"})),
+            (AlpacaError::OrderWaitTimeout { deadline: timeout, last: Box::new(order) },
+             json!({"kind": "order_wait_timeout", "message": "Order o1 still new after 1.5s", "retryable": false, "order_id": "o1"})),
+            (AlpacaError::MissingSymbols { symbols: vec!["XYZQ".to_string()] },
+             json!({"kind": "missing_symbols", "message": "No data for symbols [\"XYZQ\"]", "retryable": false, "symbols": ["XYZQ"]})),
+            (AlpacaError::Other("boom".to_string()),
+             json!({"kind": "other", "message": "boom", "retryable": false})),
+        ];
+
+        for (error, expected) in cases {
+            assert_eq!(serde_json::to_value(&error).unwrap(), expected);
+        }
+
+        // Messages of the foreign errors vary, only the shape is stable
+        let request_error = AlpacaError::RequestError(reqwest::Client::new().get("not a url").build().unwrap_err());
+        let value = serde_json::to_value(&request_error).unwrap();
+        assert_eq!((&value["kind"], &value["retryable"]), (&json!("request"), &json!(false)));
+        assert_eq!(value["message"], request_error.to_string());
+
+        let value = serde_json::to_value(AlpacaError::CircuitOpen { retry_at: std::time::Instant::now() }).unwrap();
+        assert_eq!(value["kind"], "circuit_open");
+        assert!(value["message"].as_str().unwrap().starts_with("Circuit open after repeated failures"));
+    }
+
+    #[tokio::test]
+    async fn test_get_prices_checked_missing_symbols() {
+        let mock_server = MockServer::start().await;
+        let trade = json!({"p": 100.0, "s": 1, "x": "V", "t": "2024-05-01T13:30:00Z"});
+
+        // XYZQ changed ticker and is left out
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/trades/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"trades": {"AAPL": trade, "MSFT": trade}})))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+        let symbols = ["AAPL", "XYZQ", "MSFT"];
+
+        let result = client.get_prices_checked(&symbols, &[PriceType::Trades]).await.unwrap();
+        assert_eq!(result.missing, vec!["XYZQ"]);
+        let mut found: Vec<&String> = result.data.keys().collect();
+        found.sort();
+        assert_eq!(found, ["AAPL", "MSFT"]);
+
+        match result.strict() {
+            Err(AlpacaError::MissingSymbols { symbols }) => assert_eq!(symbols, vec!["XYZQ"]),
+            other => panic!("expected the missing symbols, got {:?}", other),
+        }
+
+        let complete = client.get_prices_checked(&["AAPL", "MSFT"], &[PriceType::Trades]).await.unwrap();
+        assert!(complete.missing.is_empty());
+        assert_eq!(complete.strict().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_wrapper_stale_symbols() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_price("MSFT", 200.0);
+
+        let assets = vec!["AAPL".to_string(), "XYZQ".to_string(), "MSFT".to_string()];
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), assets).unwrap();
+        assert_eq!(wrapper.stale_symbols(), vec!["XYZQ"]);
+        assert!(wrapper.latest_trade("XYZQ").is_none());
+
+        mock.set_price("XYZQ", 5.0);
+        wrapper.update_prices();
+        assert!(wrapper.stale_symbols().is_empty());
+        assert_eq!(wrapper.latest_trade("XYZQ").unwrap().price, 5.0);
+    }
+
+    #[test]
+    fn test_wrapper_snapshot_prices_match_latest() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let snapshots = runtime.block_on(MockServer::start());
+        let latest = runtime.block_on(MockServer::start());
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let aapl_trade = json!({"p": 100.0, "s": 1, "x": "V", "t": now});
+        let aapl_quote = json!({"bp": 99.5, "bs": 1, "ap": 100.5, "as": 2, "t": now});
+        let aapl_bar = json!({"o": 100.0, "h": 101.0, "l": 99.0, "c": 100.0, "v": 10, "t": now});
+        let msft_trade = json!({"p": 400.0, "s": 3, "x": "Q", "t": now});
+
+        runtime.block_on(async {
+            // MSFT did not trade in the last minute, no bar
+            Mock::given(method("GET"))
+                .and(path("/v2/stocks/snapshots"))
+                .and(query_param("symbols", "AAPL,MSFT"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "AAPL": {"latestTrade": aapl_trade, "latestQuote": aapl_quote, "minuteBar": aapl_bar,
+                             "dailyBar": aapl_bar, "prevDailyBar": aapl_bar},
+                    "MSFT": {"latestTrade": msft_trade, "latestQuote": null},
+                })))
+                .mount(&snapshots)
+                .await;
+
+            for (price_type, prices) in [
+                ("trades", json!({"AAPL": aapl_trade, "MSFT": msft_trade})),
+                ("quotes", json!({"AAPL": aapl_quote})),
+                ("bars", json!({"AAPL": aapl_bar})),
+            ] {
+                Mock::given(method("GET"))
+                    .and(path(format!("/v2/stocks/{}/latest", price_type)))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(json!({price_type: prices})))
+                    .mount(&latest)
+                    .await;
+            }
+        });
+
+        let data_requests = |server: &MockServer| {
+            runtime.block_on(server.received_requests()).unwrap()
+                .iter()
+                .filter(|request| request.url.path().starts_with("/v2/stocks/"))
+                .count()
+        };
+        let assets = vec!["AAPL".to_string(), "MSFT".to_string()];
+
+        let client = test_client(&snapshots.uri(), &snapshots.uri());
+        let from_snapshots = AlpacaWrapper::with_client(std::sync::Arc::new(client), assets.clone()).unwrap();
+        assert_eq!(from_snapshots.price_source(), PriceSource::Snapshots);
+        assert_eq!(data_requests(&snapshots), 1);
+        for cycle in 2..=3 {
+            from_snapshots.update_prices();
+            assert_eq!(data_requests(&snapshots), cycle);
+        }
+
+        let client = test_client(&latest.uri(), &latest.uri());
+        let options = PriceOptions::default().source(PriceSource::Latest);
+        let from_latest = AlpacaWrapper::with_client_options(std::sync::Arc::new(client), assets.clone(), options).unwrap();
+        // One per type, none to the snapshots
+        assert_eq!(data_requests(&latest), 3);
+
+        for asset in ["AAPL", "MSFT"] {
+            assert_eq!(from_snapshots.latest_trade(asset), from_latest.latest_trade(asset), "{}", asset);
+            assert_eq!(from_snapshots.latest_quote(asset), from_latest.latest_quote(asset), "{}", asset);
+            assert_eq!(from_snapshots.latest_bar(asset), from_latest.latest_bar(asset), "{}", asset);
+            assert_eq!(from_snapshots.last_n_closes(asset, 1), from_latest.last_n_closes(asset, 1), "{}", asset);
+        }
+        assert_eq!(from_snapshots.latest_trade("MSFT").unwrap().price, 400.0);
+        assert!(from_snapshots.latest_quote("MSFT").is_none());
+        assert_eq!(from_snapshots.last_n_closes("AAPL", 1), Some(vec![100.0]));
+        assert!(from_snapshots.stale_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_wrapper_session_report() {
+        let file = std::env::temp_dir().join(format!("alpaca-report-journal-{}.csv", std::process::id()));
+        let output = std::env::temp_dir().join(format!("alpaca-report-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        let _ = std::fs::remove_file(&output);
+
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_price("MSFT", 60.0);
+        mock.set_position("MSFT", 2.0, 50.0);
+        // Open, with the clock asked again on every update
+        let now = chrono::Utc::now().fixed_offset();
+        mock.set_clock(Clock { timestamp: now, is_open: true, next_open: now, next_close: now });
+
+        let journal = std::sync::Arc::new(Journal::csv(&file).unwrap());
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string(), "MSFT".to_string()]).unwrap()
+            .with_journal(journal, "momentum")
+            .with_session_report(&output);
+
+        // Buy 10 at 100, sell them at 110
+        wrapper.manage_buy_signal("AAPL").unwrap();
+        mock.set_price("AAPL", 110.0);
+        wrapper.update_prices();
+        wrapper.update_positions().unwrap();
+        wrapper.manage_sell_signal("AAPL").unwrap();
+        wrapper.update_positions().unwrap();
+        wrapper.update_cash().unwrap();
+        // Counted as an error of the session
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        assert!(runtime.block_on(mock.get_order_info("missing", false)).is_err());
+
+        let report = wrapper.session_report();
+        assert!(report.started_at <= report.ended_at);
+        let mut snapshot = serde_json::to_value(&report).unwrap();
+        let object = snapshot.as_object_mut().unwrap();
+        object.remove("started_at");
+        object.remove("ended_at");
+        for fill in object["fills"].as_array_mut().unwrap() {
+            fill.as_object_mut().unwrap().remove("timestamp");
+        }
+        assert_eq!(snapshot, json!({
+            "strategy": "momentum",
+            "orders": {"submitted": 2, "open": 0, "partially_filled": 0, "filled": 2, "canceled": 0, "rejected": 0},
+            "fills": [
+                {"symbol": "AAPL", "side": "buy", "qty": 10.0, "price": 100.0, "order_id": "mock-order-1"},
+                {"symbol": "AAPL", "side": "sell", "qty": 10.0, "price": 110.0, "order_id": "mock-order-2"},
+            ],
+            "realized_pnl": 100.0,
+            "unrealized_pnl": 20.0,
+            "starting_cash": 1000.0,
+            "ending_cash": 1100.0,
+            "positions": {"MSFT": {"start": 2.0, "end": 2.0}},
+            "requests": {"requests": 8, "errors": 1, "retries": 0},
+        }));
+
+        let text = report.to_string();
+        assert!(text.contains("Strategy: momentum"), "{}", text);
+        assert!(text.contains("Orders: 2 submitted, 2 filled, 0 partially filled, 0 open, 0 canceled, 0 rejected"), "{}", text);
+        assert!(text.contains("buy 10 AAPL @ 100.00"), "{}", text);
+        assert!(text.contains("Realized P&L: 100.00"), "{}", text);
+        assert!(text.contains("Cash: 1000.00 -> 1100.00"), "{}", text);
+        assert!(text.contains("MSFT: 2 -> 2"), "{}", text);
+        assert!(text.contains("Requests: 8 (1 errors, 0 retries)"), "{}", text);
+
+        // Written by the shutdown
+        assert!(wrapper.shutdown(std::time::Duration::from_secs(1)).is_clean());
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(written["orders"], snapshot["orders"]);
+        assert_eq!(written["ending_cash"], 1100.0);
+
+        // Kept once the close resets the daily P&L
+        mock.set_clock(Clock { timestamp: now, is_open: false, next_open: now, next_close: now });
+        wrapper.update_prices();
+        assert_eq!(wrapper.daily_pnl(), None);
+        assert_eq!(wrapper.session_report().realized_pnl, 100.0);
+
+        // Without journal
+        let untracked = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let report = serde_json::to_value(untracked.session_report()).unwrap();
+        for key in ["orders", "fills"] {
+            assert_eq!(report[key], "not tracked", "{}", key);
+        }
+        assert_eq!(report["starting_cash"], 1100.0);
+
+        let _ = std::fs::remove_file(&file);
+        let _ = std::fs::remove_file(&output);
+    }
+
+    #[test]
+    fn test_client_request_counts() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mock_server = runtime.block_on(MockServer::start());
+
+        runtime.block_on(async {
+            Mock::given(method("GET"))
+                .and(path("/v2/clock"))
+                .respond_with(ResponseTemplate::new(503))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path("/v2/positions"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+                .mount(&mock_server)
+                .await;
+        });
+
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        assert_eq!(client.request_counts(), RequestCounts::default());
+
+        runtime.block_on(async {
+            assert!(client.get_positions().await.is_ok());
+            assert!(client.get_clock().await.is_err());
+        });
+        let retries = u64::from(client.retry_policy().max_retries);
+        let counts = client.request_counts();
+        assert_eq!(counts, RequestCounts { requests: 2, errors: 1, retries });
+        assert_eq!(AlpacaApi::request_counts(&client.clone()), Some(counts));
+        assert_eq!(counts.since(&RequestCounts { requests: 1, errors: 0, retries: 0 }),
+                   RequestCounts { requests: 1, errors: 1, retries });
+    }
+
+    #[test]
+    fn test_wrapper_short_and_cover() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_shorting_enabled(true);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap().with_shorting(true);
+        assert!(wrapper.can_short("AAPL"));
+
+        // 10 shares worth the buying power
+        let order = wrapper.manage_sell_signal("AAPL").expect("short order");
+        assert_eq!((order["side"].as_str(), order["qty"].as_str()), (Some("sell"), Some("10")));
+        wrapper.update_positions().unwrap();
+        wrapper.update_cash().unwrap();
+        assert_eq!(wrapper.positions()["AAPL"].qty, -10.0);
+        assert_eq!(wrapper.positions()["AAPL"].entry, 100.0);
+        assert_eq!(wrapper.equity(), 1000.0);
+
+        // Already short, nothing more
+        assert!(wrapper.manage_sell_signal("AAPL").is_none());
+
+        mock.set_price("AAPL", 90.0);
+        wrapper.update_prices();
+        assert_eq!(wrapper.equity(), 1100.0);
+        assert_eq!(wrapper.daily_pnl(), Some(DailyPnl { realized: 0.0, unrealized: 100.0 }));
+
+        // The buy covers the short before any long
+        let order = wrapper.manage_buy_signal("AAPL").expect("cover order");
+        assert_eq!((order["side"].as_str(), order["qty"].as_str()), (Some("buy"), Some("10")));
+        wrapper.update_positions().unwrap();
+        wrapper.update_cash().unwrap();
+        assert!(!wrapper.positions().contains_key("AAPL"));
+        assert_eq!(mock.cash(), 1100.0);
+        assert_eq!(wrapper.daily_pnl(), Some(DailyPnl { realized: 100.0, unrealized: 0.0 }));
+
+        let order = wrapper.manage_buy_signal("AAPL").expect("long order");
+        assert_eq!(order["qty"], "12");
+        wrapper.update_positions().unwrap();
+        assert_eq!(wrapper.positions()["AAPL"].qty, 12.0);
+    }
+
+    #[test]
+    fn test_wrapper_shorting_refused() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_price("BTC/USD", 50000.0);
+        mock.set_shorting_enabled(true);
+
+        // Off by default
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string(), "BTC/USD".to_string()]).unwrap();
+        assert!(!wrapper.can_short("AAPL"));
+        assert!(wrapper.manage_sell_signal("AAPL").is_none());
+
+        // Crypto is never shortable
+        let wrapper = wrapper.with_shorting(true);
+        assert!(wrapper.can_short("AAPL"));
+        assert!(!wrapper.can_short("BTC/USD"));
+        assert!(wrapper.manage_sell_signal("BTC/USD").is_none());
+
+        // Nor without the account flag
+        mock.set_shorting_enabled(false);
+        wrapper.update_cash().unwrap();
+        assert!(!wrapper.can_short("AAPL"));
+        assert!(wrapper.manage_sell_signal("AAPL").is_none());
+
+        assert!(!mock.calls().iter().any(|call| matches!(call, MockCall::SubmitOrder(_))));
+    }
+
+    #[test]
+    fn test_wrapper_order_polling_fills() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 101.0);
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut events = wrapper.subscribe_orders();
+        wrapper.start_order_polling(std::time::Duration::from_millis(10));
+
+        // Resting until the price crosses its limit
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let request = OrderRequest::limit("AAPL", 10.0, OrderSide::Buy, 100.0).unwrap();
+        let order = runtime.block_on(mock.submit_order(&request)).unwrap();
+        let id = order["id"].as_str().unwrap().to_string();
+        assert_eq!(order["status"], "new");
+        wrapper.track_order(&order);
+        assert_eq!(wrapper.open_orders(), vec![id.clone()]);
+
+        let next = |events: &mut tokio::sync::broadcast::Receiver<TradeUpdate>| {
+            runtime.block_on(async { tokio::time::timeout(std::time::Duration::from_secs(2), events.recv()).await })
+                .expect("order event")
+                .unwrap()
+        };
+
+        mock.fill_partially(&id, 4.0, 99.0).unwrap();
+        let update = next(&mut events);
+        assert_eq!(update.event, TradeEventKind::PartialFill);
+        assert_eq!(update.order.status, OrderStatus::PartiallyFilled);
+        assert_eq!((update.qty, update.price), (Some(4.0), Some(99.0)));
+        // Refreshed before the event
+        assert_eq!(wrapper.positions()["AAPL"].qty, 4.0);
+        assert_eq!(wrapper.open_orders(), vec![id.clone()]);
+
+        // The rest at the limit, the average is (4 * 99 + 6 * 100) / 10
+        mock.tick("AAPL", 98.0);
+        let update = next(&mut events);
+        assert_eq!(update.event, TradeEventKind::Fill);
+        assert_eq!(update.order.filled_avg_price, Some(99.6));
+        assert_eq!(update.qty, Some(6.0));
+        assert!((update.price.unwrap() - 100.0).abs() < 1e-9, "{:?}", update.price);
+        assert_eq!(wrapper.positions()["AAPL"].qty, 10.0);
+        assert_eq!(mock.cash(), 1000.0 - 4.0 * 99.0 - 6.0 * 100.0);
+        assert!(wrapper.open_orders().is_empty());
+
+        // Nothing more once filled, nor polled
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(matches!(events.try_recv(), Err(tokio::sync::broadcast::error::TryRecvError::Empty)));
+        let polls = mock.calls().iter().filter(|call| matches!(call, MockCall::GetOrderInfo(_))).count();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(mock.calls().iter().filter(|call| matches!(call, MockCall::GetOrderInfo(_))).count(), polls);
+
+        assert_eq!(wrapper.shutdown(std::time::Duration::from_secs(1)).exited, vec!["order_polling".to_string()]);
+    }
+
+    #[test]
+    fn test_wrapper_order_polling_cancel() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 101.0);
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut events = wrapper.subscribe_orders();
+
+        // Already done, not tracked
+        wrapper.track_order(&json!({"id": "done", "status": "filled"}));
+        assert!(wrapper.open_orders().is_empty());
+
+        // Tracked while idle, polled without waiting the idle interval
+        wrapper.start_order_polling(std::time::Duration::from_millis(50));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let request = OrderRequest::limit("AAPL", 10.0, OrderSide::Buy, 100.0).unwrap();
+        let order = runtime.block_on(mock.submit_order(&request)).unwrap();
+        let id = order["id"].as_str().unwrap().to_string();
+        mock.fill_partially(&id, 2.0, 100.0).unwrap();
+        mock.cancel_order(&id).unwrap();
+        wrapper.track_order(&order);
+
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let event = runtime.block_on(async { tokio::time::timeout(std::time::Duration::from_millis(400), events.recv()).await })
+                .expect("order event")
+                .unwrap();
+            received.push(event);
+        }
+        assert_eq!(received[0].event, TradeEventKind::PartialFill);
+        assert_eq!((received[0].qty, received[0].price), (Some(2.0), Some(100.0)));
+        assert_eq!(received[1].event, TradeEventKind::Canceled);
+        assert_eq!(received[1].order.status, OrderStatus::Canceled);
+        assert!(wrapper.open_orders().is_empty());
+        assert_eq!(wrapper.positions()["AAPL"].qty, 2.0);
+
+        assert!(mock.cancel_order(&id).is_err());
+        wrapper.shutdown(std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_place_market_and_limit_order() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json("o-1", "market", "buy", "accepted")))
+            .mount(&mock_server)
+            .await;
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+
+        let order = client.place_market_order("AAPL", 10, OrderSide::Buy).await.unwrap();
+        assert_eq!((order.id.as_str(), order.status), ("o-1", OrderStatus::Accepted));
+        client.place_market_order("BTC/USD", 0.5, OrderSide::Sell).await.unwrap();
+        client.place_limit_order("AAPL", 5, OrderSide::Sell, 190.5, TimeInForce::Gtc).await.unwrap();
+        client.place_order("AAPL", 1, "buy", None, None).await.unwrap();
+        client.place_order("ETH/USD", 2, "buy", None, None).await.unwrap();
+        client.place_order_full("BTC/USD", 1, "sell", None, None).await.unwrap();
+
+        // Refused before any request
+        for price in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                client.place_limit_order("AAPL", 5, OrderSide::Buy, price, TimeInForce::Day).await,
+                Err(AlpacaError::InvalidParameter(_))
+            ));
+        }
+        assert!(matches!(client.place_market_order("AAPL", 0, OrderSide::Buy).await, Err(AlpacaError::InvalidParameter(_))));
+
+        let bodies: Vec<Value> = mock_server.received_requests().await.unwrap().iter()
+            .map(|request| serde_json::from_slice(&request.body).unwrap())
+            .collect();
+        assert_eq!(bodies, vec![
+            // Day by default, not ioc which expires unfilled orders at once
+            json!({"symbol": "AAPL", "qty": "10", "side": "buy", "type": "market", "time_in_force": "day"}),
+            // Crypto doesn't take day
+            json!({"symbol": "BTC/USD", "qty": "0.5", "side": "sell", "type": "market", "time_in_force": "gtc"}),
+            json!({"symbol": "AAPL", "qty": "5", "side": "sell", "type": "limit", "time_in_force": "gtc", "limit_price": 190.5}),
+            json!({"symbol": "AAPL", "qty": "1", "side": "buy", "type": "market", "time_in_force": "day"}),
+            json!({"symbol": "ETH/USD", "qty": "2", "side": "buy", "type": "market", "time_in_force": "gtc"}),
+            json!({"symbol": "BTC/USD", "qty": "1", "side": "sell", "type": "market", "time_in_force": "gtc"}),
+        ]);
+    }
+
+    #[test]
+    fn test_auction_order_body_and_window() {
+        let body = OrderRequest::market_on_open("AAPL", 10, OrderSide::Buy).body().unwrap();
+        assert_eq!(body["type"], "market");
+        assert_eq!(body["time_in_force"], "opg");
+        let body = OrderRequest::market_on_close("AAPL", 10, OrderSide::Sell).body().unwrap();
+        assert_eq!(body["time_in_force"], "cls");
+
+        // 15:49 and 15:50 in New York, in daylight and in standard time
+        assert!(auction_order_accepted(TimeInForce::Cls, utc("2024-05-01T19:49:00Z")));
+        assert!(!auction_order_accepted(TimeInForce::Cls, utc("2024-05-01T19:50:00Z")));
+        assert!(auction_order_accepted(TimeInForce::Cls, utc("2024-01-10T20:49:00Z")));
+        assert!(!auction_order_accepted(TimeInForce::Cls, utc("2024-01-10T20:50:00Z")));
+        // From 19:00 the orders go for the next session
+        assert!(auction_order_accepted(TimeInForce::Cls, utc("2024-05-01T23:00:00Z")));
+
+        assert!(auction_order_accepted(TimeInForce::Opg, utc("2024-05-01T13:27:00Z")));
+        assert!(!auction_order_accepted(TimeInForce::Opg, utc("2024-05-01T13:28:00Z")));
+        assert!(!auction_order_accepted(TimeInForce::Opg, utc("2024-05-01T19:00:00Z")));
+        assert!(auction_order_accepted(TimeInForce::Opg, utc("2024-05-01T03:00:00Z")));
+
+        assert!(auction_order_accepted(TimeInForce::Day, utc("2024-05-01T19:55:00Z")));
+    }
+
+    #[tokio::test]
+    async fn test_auction_window_check() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "timestamp": "2024-05-01T15:55:00-04:00",
+                    "is_open": true,
+                    "next_open": "2024-05-02T09:30:00-04:00",
+                    "next_close": "2024-05-01T16:00:00-04:00"
+                })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(order_json("moc-order", "market", "buy", "accepted")))
+            .mount(&mock_server)
+            .await;
+
+        let order = OrderRequest::market_on_close("AAPL", 10, OrderSide::Buy);
+        let requests = |kind: &str| {
+            let server = &mock_server;
+            let kind = kind.to_string();
+            async move {
+                server.received_requests().await.unwrap().iter()
+                    .filter(|request| request.url.path() == kind)
+                    .count()
+            }
+        };
+
+        // Off by default, not even asking the clock
+        let client = test_client(&mock_server.uri(), &mock_server.uri());
+        client.submit_order(&order).await.unwrap();
+        assert_eq!(requests("/v2/clock").await, 0);
+        assert_eq!(requests("/v2/orders").await, 1);
+
+        let client = client.with_auction_window_check(AuctionWindowCheck::Reject);
+        let error = client.submit_order(&order).await.unwrap_err();
+        assert!(matches!(error, AlpacaError::InvalidOrder { .. }));
+        assert_eq!(requests("/v2/clock").await, 1);
+        assert_eq!(requests("/v2/orders").await, 1);
+
+        // Orders of other kinds skip the check
+        client.submit_order(&OrderRequest::market("AAPL", 10, OrderSide::Buy)).await.unwrap();
+        assert_eq!(requests("/v2/clock").await, 1);
+        assert_eq!(requests("/v2/orders").await, 2);
+
+        let client = client.with_auction_window_check(AuctionWindowCheck::Warn);
+        client.submit_order(&order).await.unwrap();
+        assert_eq!(requests("/v2/clock").await, 2);
+        assert_eq!(requests("/v2/orders").await, 3);
+    }
+
+    #[test]
+    fn test_wrapper_duplicate_guard() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let window = std::time::Duration::from_millis(300);
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_duplicate_guard(window, DuplicateKey::Side);
+        let mut suppressed = wrapper.subscribe_suppressed();
+
+        // The cash is not refreshed between them, the same buy twice
+        assert!(wrapper.manage_buy_signal("AAPL").is_some());
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+        assert_eq!(mock.orders().len(), 1);
+
+        let event = suppressed.try_recv().unwrap();
+        assert_eq!((event.symbol.as_str(), event.side, event.qty, event.count), ("AAPL", OrderSide::Buy, 10.0, 1));
+        assert!(event.remaining <= window);
+        assert_eq!(suppressed.try_recv().unwrap().count, 2);
+
+        // After the window the signal goes through, and restarts it
+        std::thread::sleep(window);
+        assert!(wrapper.manage_buy_signal("AAPL").is_some());
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+        assert_eq!(mock.orders().len(), 2);
+
+        wrapper.clear_cooldown("AAPL");
+        assert!(wrapper.manage_buy_signal("AAPL").is_some());
+        assert_eq!(mock.orders().len(), 3);
+    }
+
+    #[test]
+    fn test_wrapper_duplicate_guard_by_order() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_duplicate_guard(std::time::Duration::from_secs(60), DuplicateKey::Order);
+
+        assert!(wrapper.manage_buy_signal("AAPL").is_some());
+        assert!(wrapper.manage_buy_signal("AAPL").is_none());
+
+        // A buy of another quantity is not a duplicate
+        mock.set_price("AAPL", 50.0);
+        wrapper.update_prices();
+        let order = wrapper.manage_buy_signal("AAPL").expect("resized order");
+        assert_eq!(order["qty"], "20");
+        assert_eq!(mock.orders().len(), 2);
+    }
+
+    #[test]
+    fn test_multi_leg_vertical_spread_body() {
+        let legs = vec![
+            OptionLeg::new("AAPL250620C00200000", 1, OrderSide::Buy).position_intent(PositionIntent::BuyToOpen),
+            OptionLeg::new("AAPL250620C00210000", 1, OrderSide::Sell).position_intent(PositionIntent::SellToOpen),
+        ];
+        let order = OrderRequest::multi_leg(legs, 2, OrderType::Limit, Some(3.25)).unwrap();
+
+        let body = order.body().unwrap();
+        assert_eq!(serde_json::to_value(&body).unwrap(), json!({
+            "order_class": "mleg",
+            "qty": "2",
+            "type": "limit",
+            "time_in_force": "day",
+            "limit_price": 3.25,
+            "legs": [
+                {"symbol": "AAPL250620C00200000", "ratio_qty": "1", "side": "buy", "position_intent": "buy_to_open"},
+                {"symbol": "AAPL250620C00210000", "ratio_qty": "1", "side": "sell", "position_intent": "sell_to_open"},
+            ],
+        }));
+    }
+
+    #[test]
+    fn test_multi_leg_iron_condor_body() {
+        let legs = vec![
+            OptionLeg::new("SPY250620P00480000", 1, OrderSide::Buy),
+            OptionLeg::new("SPY250620P00490000", 1, OrderSide::Sell),
+            OptionLeg::new("SPY250620C00530000", 1, OrderSide::Sell),
+            OptionLeg::new("SPY250620C00540000", 1, OrderSide::Buy),
+        ];
+        // Opened for a credit
+        let order = OrderRequest::multi_leg(legs, 1, OrderType::Limit, Some(-1.1)).unwrap();
+
+        let body = order.body().unwrap();
+        assert!(!body.contains_key("symbol") && !body.contains_key("side"));
+        assert_eq!(body["limit_price"], -1.1);
+        let legs = body["legs"].as_array().unwrap();
+        assert_eq!(legs.len(), 4);
+        assert_eq!(legs[2], json!({"symbol": "SPY250620C00530000", "ratio_qty": "1", "side": "sell"}));
+
+        let market = OrderRequest::multi_leg(order.legs.clone(), 1, OrderType::Market, None).unwrap();
+        assert_eq!(market.body().unwrap()["type"], "market");
+        assert!(!market.body().unwrap().contains_key("limit_price"));
+    }
+
+    #[test]
+    fn test_multi_leg_validation() {
+        let leg = |symbol: &str, ratio_qty| OptionLeg::new(symbol, ratio_qty, OrderSide::Buy);
+        let invalid = |result: Result<OrderRequest, AlpacaError>| matches!(result, Err(AlpacaError::InvalidParameter(_)));
+
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1)], 1, OrderType::Market, None)));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1); 5], 1, OrderType::Market, None)));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 0)], 1, OrderType::Market, None)));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 2)], 1, OrderType::Stop, None)));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 2)], 1, OrderType::Limit, None)));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 2)], 1, OrderType::Limit, Some(f64::NAN))));
+        assert!(invalid(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 2)], 1, OrderType::Market, Some(1.0))));
+        assert!(OrderRequest::multi_leg(vec![leg("A", 1), leg("B", 2)], 1, OrderType::Market, None).is_ok());
+    }
+
+    #[test]
+    fn test_multi_leg_order_response() {
+        let leg = |id: &str, symbol: &str, side: &str, intent: &str| json!({
+            "id": id,
+            "client_order_id": format!("{}-client", id),
+            "created_at": "2025-01-02T15:00:00Z",
+            "asset_id": format!("{}-asset", id),
+            "symbol": symbol,
+            "order_class": "mleg",
+            "qty": "2",
+            "filled_qty": "0",
+            "type": "limit",
+            "side": side,
+            "time_in_force": "day",
+            "status": "new",
+            "ratio_qty": "1",
+            "position_intent": intent,
+            "legs": null,
+        });
+        let order: Order = serde_json::from_value(json!({
+            "id": "mleg-order",
+            "client_order_id": "mleg-client",
+            "created_at": "2025-01-02T15:00:00Z",
+            "symbol": "",
+            "order_class": "mleg",
+            "qty": "2",
+            "type": "limit",
+            "side": "buy",
+            "time_in_force": "day",
+            "limit_price": "3.25",
+            "status": "new",
+            "legs": [
+                leg("leg-1", "AAPL250620C00200000", "buy", "buy_to_open"),
+                leg("leg-2", "AAPL250620C00210000", "sell", "sell_to_open"),
+            ],
+        })).unwrap();
+
+        assert_eq!(order.asset_id, "");
+        let legs = order.legs.as_ref().unwrap();
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[1].symbol, "AAPL250620C00210000");
+        assert_eq!((legs[1].side, legs[1].ratio_qty, legs[1].position_intent),
+            (OrderSide::Sell, Some(1), Some(PositionIntent::SellToOpen)));
+        assert_eq!(order.ratio_qty, None);
+    }
+
+    #[test]
+    fn test_wrapper_equity_curve() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        assert_eq!(wrapper.max_drawdown(), Some(0.0));
+
+        mock.set_position("AAPL", 10.0, 100.0);
+        wrapper.update_positions().unwrap();
+        for price in [100.0, 80.0, 120.0, 110.0] {
+            mock.set_price("AAPL", price);
+            wrapper.update_prices();
+        }
+
+        // The first sample is the construction one, without position
+        let curve: Vec<f64> = wrapper.equity_curve().iter().map(|(_, equity)| *equity).collect();
+        assert_eq!(curve, vec![1000.0, 2000.0, 1800.0, 2200.0, 2100.0]);
+        assert!(wrapper.equity_curve().windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert_eq!(wrapper.high_water_mark(), Some(2200.0));
+        // From 2000 down to 1800, deeper than from 2200 to 2100
+        assert!((wrapper.max_drawdown().unwrap() - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_wrapper_equity_curve_bounds() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 1.0, 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_equity_curve_capacity(2);
+        for price in [90.0, 110.0, 105.0] {
+            mock.set_price("AAPL", price);
+            wrapper.update_prices();
+        }
+        let curve: Vec<f64> = wrapper.equity_curve().iter().map(|(_, equity)| *equity).collect();
+        assert_eq!(curve, vec![1110.0, 1105.0]);
+        assert_eq!(wrapper.high_water_mark(), Some(1110.0));
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_equity_curve_retention(std::time::Duration::from_millis(50));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        wrapper.update_prices();
+        assert_eq!(wrapper.equity_curve().len(), 1);
+    }
+
+    fn new_york_today() -> chrono::NaiveDate {
+        crate::market_hours::new_york_date(chrono::Utc::now())
+    }
+
+    #[test]
+    fn test_wrapper_split_adjustment() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 400.0);
+        mock.set_position("AAPL", 10.0, 300.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        wrapper.arm_stop_loss("AAPL", 350.0, StopMode::Alert);
+        let mut events = wrapper.subscribe_corporate_actions();
+
+        // 4:1 effective tomorrow, the positions cached today are pre-split
+        let tomorrow = new_york_today().succ_opt().unwrap();
+        mock.add_corporate_actions(CorporateActions {
+            forward_splits: vec![Split {
+                id: "split-1".to_string(),
+                symbol: "AAPL".to_string(),
+                new_rate: 4.0,
+                old_rate: 1.0,
+                process_date: tomorrow,
+                ex_date: tomorrow,
+                record_date: None,
+                payable_date: None,
+            }],
+            ..Default::default()
+        });
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let adjustments = runtime.block_on(wrapper.apply_corporate_actions_on(tomorrow, true)).unwrap();
+        assert_eq!(adjustments, vec![CorporateAdjustment::Split {
+            symbol: "AAPL".to_string(),
+            ratio: 4.0,
+            qty: (10.0, 40.0),
+            entry: (300.0, 75.0),
+            stops: 1,
+        }]);
+        assert_eq!(events.try_recv().unwrap(), adjustments[0]);
+        assert_eq!((wrapper.positions()["AAPL"].qty, wrapper.positions()["AAPL"].entry), (40.0, 75.0));
+        assert_eq!(wrapper.active_stops()[0].stop_price, 87.5);
+
+        // Applied once
+        assert!(runtime.block_on(wrapper.apply_corporate_actions_on(tomorrow, true)).unwrap().is_empty());
+
+        // The post-split price is no drop for the stop
+        let mut stops = wrapper.subscribe_stops();
+        mock.set_price("AAPL", 100.0);
+        wrapper.update_prices();
+        assert!(stops.try_recv().is_err());
+        assert_eq!(wrapper.equity(), 5000.0);
+    }
+
+    #[test]
+    fn test_wrapper_split_after_refresh() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 40.0, 75.0);
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+
+        // Effective today, the positions refreshed today are adjusted already
+        let today = new_york_today();
+        mock.add_corporate_actions(CorporateActions {
+            forward_splits: vec![Split {
+                id: "split-1".to_string(),
+                symbol: "AAPL".to_string(),
+                new_rate: 4.0,
+                old_rate: 1.0,
+                process_date: today,
+                ex_date: today,
+                record_date: None,
+                payable_date: None,
+            }],
+            ..Default::default()
+        });
+
+        let adjustments = wrapper.apply_corporate_actions().unwrap();
+        assert!(matches!(adjustments[..], [CorporateAdjustment::Split { qty: (40.0, 40.0), stops: 0, .. }]));
+        assert_eq!(wrapper.positions()["AAPL"].entry, 75.0);
+    }
+
+    #[test]
+    fn test_wrapper_cash_dividend() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 10.0, 90.0);
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_dividend_check_interval(std::time::Duration::ZERO);
+        let activity_calls = || mock.calls().iter()
+            .filter(|call| matches!(call, MockCall::GetAccountActivities(_)))
+            .count();
+
+        let today = new_york_today();
+        mock.add_corporate_actions(CorporateActions {
+            cash_dividends: vec![CashDividend {
+                id: "dividend-1".to_string(),
+                symbol: "AAPL".to_string(),
+                rate: 0.25,
+                special: false,
+                foreign: false,
+                process_date: today,
+                ex_date: today - chrono::Duration::days(7),
+                record_date: None,
+                payable_date: Some(today),
+            }],
+            ..Default::default()
+        });
+
+        // Due but not credited yet
+        assert!(wrapper.apply_corporate_actions().unwrap().is_empty());
+        assert_eq!(activity_calls(), 1);
+
+        mock.add_activity(json!({
+            "id": "activity-1",
+            "activity_type": "DIV",
+            "date": today.to_string(),
+            "symbol": "AAPL",
+            "qty": "10",
+            "per_share_amount": "0.25",
+            "net_amount": "2.5",
+        }));
+        let mut events = wrapper.subscribe_corporate_actions();
+        wrapper.update_prices();
+        assert_eq!(events.try_recv().unwrap(), CorporateAdjustment::CashDividend { symbol: "AAPL".to_string(), amount: 2.5 });
+        assert_eq!(wrapper.equity(), 2002.5);
+        assert_eq!(mock.cash(), 1002.5);
+
+        // Nothing more is due
+        wrapper.update_prices();
+        assert_eq!(activity_calls(), 2);
+        assert_eq!(wrapper.equity(), 2002.5);
+    }
+
+    #[test]
+    fn test_mock_alpaca_server_defaults() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let server = runtime.block_on(MockAlpacaServer::start());
+
+        runtime.block_on(async {
+            let client = server.client();
+            assert_eq!(client.get_account().await.unwrap(), mock_account());
+            assert_eq!(client.get_positions().await.unwrap(), json!([]));
+            assert!(client.get_clock().await.unwrap().is_open);
+
+            let prices = client.get_prices_multi(&["AAPL", "BTC/USD"], &[PriceType::Trades, PriceType::Quotes]).await.unwrap();
+            assert_eq!(prices["BTC/USD"][&PriceType::Quotes]["ap"], server.price());
+            assert_eq!(client.get_latest_bar("MSFT").await.unwrap().close, server.price());
+
+            // Mounted later, the test mocks win over the defaults
+            Mock::given(method("GET"))
+                .and(path("/v2/positions"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(mock_positions(8)))
+                .mount(server.server())
+                .await;
+            let positions = client.get_positions().await.unwrap();
+            assert_eq!(positions[5]["symbol"], "TSLA");
+            assert_eq!(positions[7]["symbol"], "SYM7");
+        });
+
+        // With the 10 AAPL of the positions mounted above, whole shares
+        // without the asset, 100000 of cash at 100
+        let wrapper = AlpacaWrapper::with_client(std::sync::Arc::new(server.client()), vec!["AAPL".to_string()]).unwrap();
+        assert_eq!(wrapper.equity(), 101000.0);
+        let order = wrapper.manage_buy_signal("AAPL").expect("buy order");
+        assert_eq!((order["symbol"].as_str(), order["qty"].as_str(), order["status"].as_str()),
+            (Some("AAPL"), Some("1000"), Some("accepted")));
+        assert_eq!(serde_json::from_value::<Order>(order).unwrap().time_in_force, TimeInForce::Day);
+    }
+
+    #[tokio::test]
+    async fn test_with_reqwest_client_timeouts() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/clock"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "timestamp": "2024-05-01T10:00:00-04:00",
+                    "is_open": true,
+                    "next_open": "2024-05-02T09:30:00-04:00",
+                    "next_close": "2024-05-01T16:00:00-04:00"
+                }))
+                .set_delay(std::time::Duration::from_millis(300)))
+            .mount(&mock_server)
+            .await;
+
+        let client = |http: reqwest::Client| test_client(&mock_server.uri(), &mock_server.uri())
+            .with_retry_policy(RetryPolicy::none())
+            .with_reqwest_client(http);
+        let tiny = std::time::Duration::from_millis(50);
+
+        // The timeout of the given client, stricter than the crate one, is
+        // the one reported
+        let http = reqwest::Client::builder().timeout(tiny).build().unwrap();
+        let started = std::time::Instant::now();
+        let result = client(http).get_clock().await;
+        assert!(matches!(result, Err(AlpacaError::Timeout { timeout, .. }) if timeout >= tiny && timeout < DEFAULT_TIMEOUT), "{:?}", result);
+        assert!(started.elapsed() < std::time::Duration::from_millis(250));
+
+        // The crate timeout, stricter than that of the client
+        let http = reqwest::Client::builder().timeout(std::time::Duration::from_secs(10)).build().unwrap();
+        let strict = client(http.clone()).with_timeout(EndpointCategory::Trading, tiny);
+        assert!(matches!(strict.get_clock().await, Err(AlpacaError::Timeout { timeout, .. }) if timeout == tiny));
+
+        // Both loose enough
+        assert!(client(http).get_clock().await.unwrap().is_open);
+    }
+
+    /// Client always answered 503 on /v2/account by a replayed cassette,
+    /// nothing goes through the network so paused time can drive it.
+    fn unavailable_client(name: &str, backoff: std::time::Duration) -> AlpacaClient {
+        let file = std::env::temp_dir().join(format!("alpaca-{}-{}.json", name, std::process::id()));
+        let interaction = json!({
+            "method": "GET", "host": "trading", "endpoint": "/v2/account", "path": "/v2/account",
+            "query": [], "status": 503, "content_type": "text/plain", "body": "unavailable"
+        });
+        std::fs::write(&file, serde_json::to_vec(&json!([interaction])).unwrap()).unwrap();
+        let cassette = std::sync::Arc::new(Cassette::replay(&file).unwrap());
+        let _ = std::fs::remove_file(&file);
+
+        test_client("http://127.0.0.1:9", "http://127.0.0.1:10")
+            .with_cassette(cassette)
+            .with_retry_policy(RetryPolicy { max_retries: 10, backoff, ..Default::default() })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_abandons_backoff() {
+        let client = unavailable_client("deadline", std::time::Duration::from_secs(10))
+            .with_deadline(std::time::Duration::from_secs(25));
+
+        // Attempts at 0s and 10s, the next one would start after 30s
+        let start = tokio::time::Instant::now();
+        let error = client.get_account().await.unwrap_err();
+        assert_eq!(start.elapsed(), std::time::Duration::from_secs(10));
+
+        let AlpacaError::DeadlineExceeded { budget, attempts, last } = &error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(*budget, std::time::Duration::from_secs(25));
+        assert_eq!(*attempts, 2);
+        assert_eq!(last.as_ref().unwrap().status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!error.is_retryable());
+
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(value["kind"], "deadline_exceeded");
+        assert_eq!(value["attempts"], 2);
+        assert_eq!(value["budget_ms"], 25000);
+        assert_eq!(client.request_counts().errors, 1);
+        assert_eq!(client.request_counts().retries, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_within_shares_budget() {
+        let client = unavailable_client("within", std::time::Duration::from_secs(4))
+            .with_deadline(std::time::Duration::from_secs(60));
+
+        // Attempts at 0, 4, 12 and 28s for the first call, then at 28 and
+        // 32s for the second, which gives up with 3s left
+        let start = tokio::time::Instant::now();
+        let error = client.within(std::time::Duration::from_secs(35), async {
+            let first = client.get_account().await;
+            assert!(matches!(first, Err(AlpacaError::DeadlineExceeded { attempts: 4, .. })));
+            client.get_account().await
+        }).await.unwrap_err();
+        assert_eq!(start.elapsed(), std::time::Duration::from_secs(32));
+
+        let AlpacaError::DeadlineExceeded { budget, attempts, last } = &error else {
+            panic!("unexpected error {:?}", error);
+        };
+        assert_eq!(*budget, std::time::Duration::from_secs(35));
+        assert_eq!(*attempts, 6);
+        assert!(last.is_some());
+
+        // With the client deadline nearer, it wins
+        let client = client.with_deadline(std::time::Duration::from_secs(5));
+        let error = client.within(std::time::Duration::from_secs(35), client.get_account()).await.unwrap_err();
+        assert!(matches!(error, AlpacaError::DeadlineExceeded { attempts: 2, .. }));
+        assert_eq!(error.to_string(), "Deadline of 5s exceeded after 2 attempts");
+    }
+
+    #[tokio::test]
+    async fn test_trading_day_helpers_cache_calendar() {
+        use chrono::{Datelike, Days};
+
+        // The cached window is around today, so is the calendar. The
+        // Monday three weeks ahead is a holiday.
+        let today = chrono::Utc::now().date_naive();
+        let holiday = today + Days::new(21 - today.weekday().num_days_from_monday() as u64);
+        let (friday, tuesday) = (holiday - Days::new(3), holiday + Days::new(1));
+        let days: Vec<Value> = (0..1000)
+            .map(|offset| today - Days::new(500) + Days::new(offset))
+            .filter(|date| date.weekday().number_from_monday() <= 5 && *date != holiday)
+            .map(|date| json!({"date": date.to_string(), "open": "09:30", "close": "16:00"}))
+            .collect();
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/calendar"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Value::Array(days)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        // Concurrent first calls from clones still fetch once
+        let (first, second) = (client.clone(), client.clone());
+        let (is_friday, is_holiday) = tokio::join!(first.is_trading_day(friday), second.is_trading_day(holiday));
+        assert!(is_friday.unwrap());
+        assert!(!is_holiday.unwrap());
+
+        assert!(!client.is_trading_day(friday + Days::new(1)).await.unwrap());
+        assert!(!client.is_trading_day(friday + Days::new(2)).await.unwrap());
+        assert!(client.is_trading_day(tuesday).await.unwrap());
+
+        assert_eq!(client.next_trading_day(friday).await.unwrap(), tuesday);
+        assert_eq!(client.next_trading_day(holiday).await.unwrap(), tuesday);
+        assert_eq!(client.previous_trading_day(tuesday).await.unwrap(), friday);
+        assert_eq!(client.previous_trading_day(friday + Days::new(2)).await.unwrap(), friday);
+
+        let week = client.trading_days_between(friday - Days::new(4), tuesday).await.unwrap();
+        assert_eq!(week.len(), 6);
+        assert_eq!(week.first(), Some(&(friday - Days::new(4))));
+        assert_eq!(week[4..], [friday, tuesday]);
+        assert!(client.trading_days_between(tuesday, friday).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watchlist_add_untradable() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .respond_with(ResponseTemplate::new(422)
+                .set_body_json(json!({"code": 42210000, "message": "asset BOGUS not found"})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/watchlists/watchlist-1"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(watchlist_json(&["AAPL"])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let result = client.add_to_watchlist("watchlist-1", "BOGUS").await;
+        assert!(matches!(result, Err(AlpacaError::AssetNotTradable { .. })), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_not_found() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/positions/AAPL"))
+            .respond_with(ResponseTemplate::new(404)
+                .set_body_json(json!({"code": 40410000, "message": "position does not exist"}))
+                .set_delay(std::time::Duration::from_millis(200)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_retry_policy(RetryPolicy::none())
+            .with_request_coalescing(true);
+
+        // The caller awaiting the first one gets the error as NotFound too
+        let (first, second) = tokio::join!(client.get_position("AAPL"), client.get_position("AAPL"));
+        assert_eq!(first.unwrap(), None);
+        assert_eq!(second.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_dropped_probe() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/account"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"id": "test-account-id"}))
+                .set_delay(std::time::Duration::from_millis(500)))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_retry_policy(RetryPolicy::none())
+            .with_circuit_breaker(1, std::time::Duration::from_millis(50));
+
+        assert!(client.get_account().await.is_err());
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        assert_eq!(client.circuit_state(), Some(CircuitState::HalfOpen));
+
+        // The probe is cancelled before its answer, the next request probes
+        let probe = tokio::time::timeout(std::time::Duration::from_millis(50), client.get_account()).await;
+        assert!(probe.is_err());
+        assert_eq!(client.get_account().await.unwrap()["id"], "test-account-id");
+        assert_eq!(client.circuit_state(), Some(CircuitState::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_listed_post_not_retried_without_client_order_id() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v2/orders"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com")
+            .with_retry_policy(RetryPolicy {
+                retry_methods: vec![Method::GET, Method::POST],
+                backoff: std::time::Duration::from_millis(1),
+                ..Default::default()
+            });
+
+        let order = OrderRequest::market("AAPL", 1, OrderSide::Buy);
+        assert!(client.submit_order(&order).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_page_token_refused_across_chunks() {
+        let client = test_client("https://api.example.com", "https://data.example.com")
+            .with_symbol_chunking(1, 2);
+
+        let options = HistoricalOptions { page_token: Some("token".to_string()), ..Default::default() };
+        let result = client.get_trades(&["AAPL", "MSFT"], &options).await;
+        assert!(matches!(result, Err(AlpacaError::InvalidParameter(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_confirm_already_filled() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(422)
+                .set_body_json(json!({"code": 42210000, "message": "order is already in \"filled\" state"})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let mut filled = order_json("order-1", "market", "sell", "filled");
+        filled["filled_qty"] = json!("5");
+        filled["filled_avg_price"] = json!("101.5");
+        Mock::given(method("GET"))
+            .and(path("/v2/orders/order-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(filled))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client(&mock_server.uri(), "https://data.example.com");
+
+        let outcome = client.cancel_and_confirm("order-1", std::time::Duration::from_secs(5)).await.unwrap();
+        assert_eq!(outcome, CancelOutcome::FilledInstead { filled_qty: 5.0, avg_price: Some(101.5) });
+    }
+
+    #[test]
+    fn test_wrapper_clock_asked_once_per_session() {
+        let clock_calls = |mock: &MockAlpaca| mock.calls().iter().filter(|call| **call == MockCall::GetClock).count();
+
+        // Open until the close in an hour
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        for _ in 0..5 {
+            wrapper.update_prices();
+        }
+        assert_eq!(clock_calls(&mock), 1, "{:?}", mock.calls());
+        assert!(wrapper.daily_pnl().is_some());
+
+        // A close already passed is asked again on every update
+        let now = chrono::Utc::now().fixed_offset();
+        mock.set_clock(Clock { timestamp: now, is_open: false, next_open: now, next_close: now });
+        let closed = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let before = clock_calls(&mock);
+        closed.update_prices();
+        closed.update_prices();
+        assert_eq!(clock_calls(&mock), before + 2);
+    }
+
+    #[test]
+    fn test_wrapper_duplicate_guard_in_flight() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_submit_delay(std::time::Duration::from_millis(100));
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap()
+            .with_duplicate_guard(std::time::Duration::from_secs(60), DuplicateKey::Side);
+        let mut suppressed = wrapper.subscribe_suppressed();
+
+        // Flapping signals while the first order is still being submitted
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let orders = runtime.block_on(futures::future::join_all(
+            (0..5).map(|_| wrapper.manage_buy_signal_async("AAPL"))
+        ));
+        assert_eq!(orders.iter().filter(|order| order.is_some()).count(), 1);
+        assert_eq!(mock.orders().len(), 1);
+        assert_eq!(suppressed.try_recv().unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_reqwest_client_body_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Sends the headers, then stalls in the middle of the body
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await;
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 100\r\n\r\n{\"is_open\"")
+                .await
+                .unwrap();
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        });
+
+        let tiny = std::time::Duration::from_millis(100);
+        let client = test_client(&uri, &uri)
+            .with_retry_policy(RetryPolicy::none())
+            .with_reqwest_client(reqwest::Client::new())
+            .with_timeout(EndpointCategory::Trading, tiny);
+
+        let started = std::time::Instant::now();
+        let result = client.get_clock().await;
+        assert!(matches!(result, Err(AlpacaError::Timeout { timeout, .. }) if timeout == tiny), "{:?}", result);
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_get_prices_checked_failed_types() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/trades/latest"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_json(json!({"trades": {"AAPL": {"p": 170.0, "s": 10, "t": "2024-05-01T15:59:59Z"}}})))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/stocks/quotes/latest"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("Internal Server Error"))
+            .mount(&mock_server)
+            .await;
+
+        let client = test_client("https://api.example.com", &mock_server.uri());
+
+        let result = client.get_prices_checked(&["AAPL"], &[PriceType::Trades, PriceType::Quotes]).await.unwrap();
+        assert_eq!(result.data["AAPL"][&PriceType::Trades]["p"], 170.0);
+        assert!(result.missing.is_empty());
+        assert_eq!(result.failed.len(), 1);
+        assert_eq!(result.failed[0].0, PriceType::Quotes);
+        assert!(matches!(result.failed[0].1, AlpacaError::HttpError { status: StatusCode::INTERNAL_SERVER_ERROR, .. }));
+
+        assert!(matches!(result.strict(), Err(AlpacaError::HttpError { status: StatusCode::INTERNAL_SERVER_ERROR, .. })));
+    }
+
+    #[test]
+    fn test_wrapper_price_options_first_fetch() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+
+        let options = PriceOptions::default().types(&[PriceType::Quotes]);
+        let wrapper = AlpacaWrapper::with_api_options(mock.clone(), vec!["AAPL".to_string()], options).unwrap();
+        assert_eq!(wrapper.price_types(), &[PriceType::Quotes]);
+
+        // Already the construction only asked for the quotes
+        let fetched: Vec<Vec<PriceType>> = mock.calls().into_iter()
+            .filter_map(|call| match call {
+                MockCall::GetPrices { types, .. } => Some(types),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fetched, vec![vec![PriceType::Quotes]]);
+        assert_eq!(wrapper.latest_quote("AAPL").unwrap().ask_price, 100.0);
+        assert!(wrapper.latest_trade("AAPL").is_none());
+        assert!(wrapper.latest_bar("AAPL").is_none());
+    }
+
+    #[test]
+    fn test_wrapper_dividend_check_interval() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(1000.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 10.0, 90.0);
+        let activity_calls = || mock.calls().iter()
+            .filter(|call| matches!(call, MockCall::GetAccountActivities(_)))
+            .count();
+
+        let today = new_york_today();
+        mock.add_corporate_actions(CorporateActions {
+            cash_dividends: vec![CashDividend {
+                id: "dividend-1".to_string(),
+                symbol: "AAPL".to_string(),
+                rate: 0.25,
+                special: false,
+                foreign: false,
+                process_date: today,
+                ex_date: today - chrono::Duration::days(7),
+                record_date: None,
+                payable_date: Some(today),
+            }],
+            ..Default::default()
+        });
+
+        // Due from the construction, then not asked again for a while
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        assert_eq!(activity_calls(), 1);
+        for _ in 0..3 {
+            wrapper.update_prices();
+        }
+        assert_eq!(activity_calls(), 1);
+
+        // The explicit check is not limited
+        assert!(wrapper.apply_corporate_actions().unwrap().is_empty());
+        assert_eq!(activity_calls(), 2);
+    }
+
+    #[test]
+    fn test_wrapper_background_updates_fire_stops() {
+        let mock = std::sync::Arc::new(MockAlpaca::new(0.0));
+        mock.set_price("AAPL", 100.0);
+        mock.set_position("AAPL", 10.0, 100.0);
+
+        let wrapper = AlpacaWrapper::with_api(mock.clone(), vec!["AAPL".to_string()]).unwrap();
+        let mut events = wrapper.subscribe_stops();
+        let mut pnl = wrapper.subscribe_pnl();
+        let stop = wrapper.arm_stop_loss("AAPL", 95.0, StopMode::SellMarket);
+
+        // Only the background task updates the prices from here
+        mock.set_price("AAPL", 90.0);
+        wrapper.start_background_updates(std::time::Duration::from_millis(10));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(wrapper.shutdown(std::time::Duration::from_secs(1)).is_clean());
+
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.stop.id, event.price), (stop, 90.0));
+        assert_eq!(event.order.unwrap()["side"], "sell");
+        assert!(events.try_recv().is_err());
+        assert!(pnl.try_recv().is_ok());
+
+        let sells: Vec<OrderRequest> = mock.calls().into_iter()
+            .filter_map(|call| match call {
+                MockCall::SubmitOrder(order) => Some(order),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sells, vec![OrderRequest::market("AAPL", 10, OrderSide::Sell)]);
+    }
 }
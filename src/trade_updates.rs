@@ -0,0 +1,132 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Order events in one shape whatever the transport, the trade updates
+//! stream or the polling of the orders endpoints.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Order, OrderStatus};
+
+/// Kind of a `TradeUpdate`, with the event names of the trade updates
+/// stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeEventKind {
+    New,
+    Fill,
+    PartialFill,
+    Canceled,
+    Expired,
+    DoneForDay,
+    Replaced,
+    Rejected,
+    PendingNew,
+    PendingCancel,
+    PendingReplace,
+    Stopped,
+    Suspended,
+    Calculated,
+    OrderCancelRejected,
+    OrderReplaceRejected,
+    /// Events added to the API later.
+    #[serde(other)]
+    Unknown,
+}
+
+impl TradeEventKind {
+    /// The event a change to `status` is reported as.
+    pub fn from_status(status: &OrderStatus) -> Self {
+        match status {
+            OrderStatus::New | OrderStatus::Accepted | OrderStatus::AcceptedForBidding => Self::New,
+            OrderStatus::PartiallyFilled => Self::PartialFill,
+            OrderStatus::Filled => Self::Fill,
+            OrderStatus::Canceled => Self::Canceled,
+            OrderStatus::Expired => Self::Expired,
+            OrderStatus::DoneForDay => Self::DoneForDay,
+            OrderStatus::Replaced => Self::Replaced,
+            OrderStatus::Rejected => Self::Rejected,
+            OrderStatus::PendingNew => Self::PendingNew,
+            OrderStatus::PendingCancel => Self::PendingCancel,
+            OrderStatus::PendingReplace => Self::PendingReplace,
+            OrderStatus::Stopped => Self::Stopped,
+            OrderStatus::Suspended => Self::Suspended,
+            OrderStatus::Calculated => Self::Calculated,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn is_fill(&self) -> bool {
+        matches!(self, Self::Fill | Self::PartialFill)
+    }
+}
+
+/// Change to an order.
+///
+/// For fills `price` and `qty` are those of the execution and
+/// `position_qty` the position size after it; polling can't see the
+/// position, so it leaves `position_qty` empty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradeUpdate {
+    pub event: TradeEventKind,
+    pub order: Order,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub price: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub qty: Option<f64>,
+    #[serde(with = "crate::utils::serde_num::opt_string_or_number", default)]
+    pub position_qty: Option<f64>,
+    #[serde(with = "crate::utils::utc_timestamp")]
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TradeUpdate {
+    /// Update between two polled snapshots of the same order, `None` when
+    /// neither the status nor the filled quantity changed.
+    ///
+    /// The execution of a fill is the quantity filled since `previous`,
+    /// priced out of the change of the average price of the order.
+    pub fn from_poll(previous: &Order, current: Order) -> Option<Self> {
+        let filled_before = previous.filled_qty.unwrap_or(0.0);
+        let filled_now = current.filled_qty.unwrap_or(0.0);
+        let fill_qty = filled_now - filled_before;
+
+        let event = if fill_qty > 0.0 {
+            if current.status == OrderStatus::Filled { TradeEventKind::Fill } else { TradeEventKind::PartialFill }
+        } else if current.status != previous.status {
+            TradeEventKind::from_status(&current.status)
+        } else {
+            return None;
+        };
+
+        let (price, qty) = if event.is_fill() {
+            let price = match (previous.filled_avg_price, current.filled_avg_price) {
+                (Some(before), Some(now)) if filled_before > 0.0 => Some((filled_now * now - filled_before * before) / fill_qty),
+                (_, now) => now,
+            };
+            (price, Some(fill_qty))
+        } else {
+            (None, None)
+        };
+
+        let timestamp = current.filled_at.filter(|_| event.is_fill())
+            .or(current.updated_at)
+            .unwrap_or(current.created_at);
+
+        Some(Self { event, order: current, price, qty, position_qty: None, timestamp })
+    }
+}
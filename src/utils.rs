@@ -19,6 +19,7 @@
 use std::{fmt, str::FromStr};
 
 use std::collections::HashMap;
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize, Serializer};
 use serde::ser::SerializeMap;
 use reqwest::header::HeaderMap;
@@ -33,7 +34,9 @@ where
 
     for (name, value) in headers.iter() {
         let name_str = name.as_str();
-        if let Ok(value_str) = value.to_str() {
+        if value.is_sensitive() {
+            map.serialize_entry(name_str, REDACTED)?;
+        } else if let Ok(value_str) = value.to_str() {
             map.serialize_entry(name_str, value_str)?;
         }
     }
@@ -41,6 +44,26 @@ where
     map.end()
 }
 
+const REDACTED: &str = "***";
+
+/// Crypto pairs are written `BTC/USD` by the market data API.
+pub fn is_crypto(symbol: &str) -> bool {
+    symbol.contains('/')
+}
+
+/// Symbol of `asset` in positions and orders, where the crypto pairs
+/// lose their slash: `BTC/USD` is held as `BTCUSD`.
+pub fn position_symbol(asset: &str) -> String {
+    asset.replace('/', "")
+}
+
+/// Replaces every occurrence of the non empty `secrets` in `text`.
+pub(crate) fn redact(text: &str, secrets: &[&str]) -> String {
+    secrets.iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| text.replace(secret, REDACTED))
+}
+
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, std::cmp::Eq)]
 pub enum PriceType {
@@ -99,8 +122,9 @@ impl Default for AtomicF64 {
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub(crate) struct Position {
+/// Position of a symbol as cached by `AlpacaWrapper`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Position {
     pub qty: f64,
     pub value: f64,
     pub entry: f64,
@@ -164,3 +188,356 @@ pub(crate) mod atomic_f64 {
 
 
 
+
+/// Converts the HTML of a news article into plain text.
+///
+/// This is not a full HTML parser: comments, scripts and styles are
+/// dropped, tags are removed, block level tags become line breaks and
+/// the usual entities are decoded.
+pub fn strip_html(html: &str) -> String {
+    let hidden = regex::Regex::new(r"(?is)<!--.*?-->|<(script|style)\b.*?</(script|style)\s*>").unwrap();
+    let breaks = regex::Regex::new(r"(?i)<br\s*/?>|</(p|div|li|h[1-6]|tr)\s*>").unwrap();
+    let tags = regex::Regex::new(r"(?s)<[^>]*>").unwrap();
+    let spaces = regex::Regex::new(r"[ \t\r\f]+").unwrap();
+
+    let text = hidden.replace_all(html, "");
+    let text = breaks.replace_all(&text, "\n");
+    let text = tags.replace_all(&text, "");
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(|line| spaces.replace_all(line, " ").trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The trading API encodes the numbers as strings while the data API uses
+/// JSON numbers. These modules accept both forms for any number type
+/// parsed with `FromStr`, and serialize back as strings.
+pub(crate) mod serde_num {
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber<T> {
+        String(String),
+        Number(T),
+    }
+
+    impl<T: FromStr> StringOrNumber<T>
+    where
+        T::Err: Display,
+    {
+        fn parse<E: serde::de::Error>(self) -> Result<T, E> {
+            match self {
+                Self::String(s) => s.parse().map_err(|e| E::custom(format!("invalid number {:?}: {}", s, e))),
+                Self::Number(n) => Ok(n),
+            }
+        }
+    }
+
+    pub(crate) mod string_or_number {
+        use super::*;
+
+        pub fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&value.to_string())
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+        where
+            T: FromStr + Deserialize<'de>,
+            T::Err: Display,
+            D: Deserializer<'de>,
+        {
+            StringOrNumber::deserialize(deserializer)?.parse()
+        }
+    }
+
+    /// Same as `string_or_number` with null as `None`.
+    pub(crate) mod opt_string_or_number {
+        use super::*;
+
+        pub fn serialize<T: Display, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => serializer.serialize_str(&value.to_string()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            T: FromStr + Deserialize<'de>,
+            T::Err: Display,
+            D: Deserializer<'de>,
+        {
+            Option::<StringOrNumber<T>>::deserialize(deserializer)?
+                .map(StringOrNumber::parse)
+                .transpose()
+        }
+    }
+}
+
+/// Number `field` of the account, sent as a string or a number. Missing,
+/// malformed or non finite values are an error rather than zero.
+pub(crate) fn account_number(account: &serde_json::Value, field: &str) -> Result<f64, crate::AlpacaError> {
+    let value = &account[field];
+    match value {
+        serde_json::Value::String(s) => s.parse::<f64>().ok(),
+        other => other.as_f64(),
+    }
+    .filter(|number| number.is_finite())
+    .ok_or_else(|| crate::AlpacaError::Other(format!("Account without valid {}: {}", field, value)))
+}
+
+/// Parses the timestamps of both APIs: RFC3339 with any precision up to
+/// nanoseconds and a `Z` or numeric offset, or without offset, taken as
+/// UTC.
+pub(crate) fn parse_timestamp(text: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    match DateTime::parse_from_rfc3339(text) {
+        Ok(timestamp) => Ok(timestamp.to_utc()),
+        Err(e) => chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f")
+            .map(|timestamp| timestamp.and_utc())
+            .map_err(|_| e),
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+pub(crate) mod utc_timestamp {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format_timestamp(value))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        parse_timestamp(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Nullable timestamps, like `filled_at` until the order fills. An empty
+/// string is `None` too.
+pub(crate) mod opt_utc_timestamp {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(value) => serializer.serialize_str(&format_timestamp(value)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .filter(|text| !text.is_empty())
+            .map(|text| parse_timestamp(&text).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Start of the `target` bucket holding `timestamp`. Intraday buckets are
+/// counted from the 09:30 open of the New York date, the others start at
+/// New York midnight (on Monday for the weeks, on the first month of the
+/// group for the months).
+fn bucket_start(timestamp: DateTime<Utc>, target: crate::TimeFrame) -> DateTime<Utc> {
+    use chrono::{Datelike, NaiveTime};
+    use crate::market_hours::{new_york_date, new_york_to_utc};
+
+    let date = new_york_date(timestamp);
+    let step = |open: DateTime<Utc>, minutes: u32| {
+        let minutes = i64::from(minutes.max(1));
+        let elapsed = (timestamp - open).num_minutes();
+        open + chrono::Duration::minutes(elapsed.div_euclid(minutes) * minutes)
+    };
+
+    match target {
+        crate::TimeFrame::Minute(n) => step(new_york_to_utc(date, NaiveTime::from_hms_opt(9, 30, 0).unwrap()), n),
+        crate::TimeFrame::Hour(n) => step(new_york_to_utc(date, NaiveTime::from_hms_opt(9, 30, 0).unwrap()), n * 60),
+        crate::TimeFrame::Day => new_york_to_utc(date, NaiveTime::MIN),
+        crate::TimeFrame::Week => {
+            let monday = date - chrono::Duration::days(date.weekday().num_days_from_monday().into());
+            new_york_to_utc(monday, NaiveTime::MIN)
+        }
+        crate::TimeFrame::Month(n) => {
+            let month0 = date.month0() / n.max(1) * n.max(1);
+            let first = chrono::NaiveDate::from_ymd_opt(date.year(), month0 + 1, 1).unwrap();
+            new_york_to_utc(first, NaiveTime::MIN)
+        }
+    }
+}
+
+/// Aggregates `bars` into `target` bars, stamped with the start of their
+/// bucket (see `bucket_start`). Empty buckets produce no bar, and the vwap
+/// is only kept when every source bar of the bucket has one.
+pub fn resample_bars(bars: &[crate::Bar], target: crate::TimeFrame) -> Vec<crate::Bar> {
+    let mut buckets: std::collections::BTreeMap<DateTime<Utc>, Vec<&crate::Bar>> = Default::default();
+    for bar in bars {
+        buckets.entry(bucket_start(bar.timestamp, target)).or_default().push(bar);
+    }
+
+    buckets.into_iter()
+        .map(|(timestamp, mut group)| {
+            group.sort_by_key(|bar| bar.timestamp);
+
+            let volume: f64 = group.iter().map(|bar| bar.volume).sum();
+            let weighted: Option<f64> = group.iter().map(|bar| bar.vwap.map(|vwap| vwap * bar.volume)).sum();
+
+            crate::Bar {
+                open: group[0].open,
+                high: group.iter().map(|bar| bar.high).fold(f64::MIN, f64::max),
+                low: group.iter().map(|bar| bar.low).fold(f64::MAX, f64::min),
+                close: group[group.len() - 1].close,
+                volume,
+                trade_count: group.iter().map(|bar| bar.trade_count).sum(),
+                vwap: weighted.filter(|_| volume > 0.0).map(|weighted| weighted / volume),
+                timestamp,
+            }
+        })
+        .collect()
+}
+
+/// Volume weighted average price of `trades`, `None` without volume.
+pub fn vwap(trades: &[crate::Trade]) -> Option<f64> {
+    vwap_by(trades, |_| true)
+}
+
+/// Like `vwap`, only counting the trades accepted by `include`. Useful to
+/// leave out odd lots or derivatively priced prints.
+pub fn vwap_by(trades: &[crate::Trade], include: impl Fn(&crate::Trade) -> bool) -> Option<f64> {
+    let (notional, volume) = trades.iter()
+        .filter(|trade| include(trade))
+        .fold((0.0, 0u64), |(notional, volume), trade| {
+            (notional + trade.price * trade.size as f64, volume + trade.size)
+        });
+    (volume > 0).then(|| notional / volume as f64)
+}
+
+/// Where a `RollingVwap` starts counting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VwapAnchor {
+    /// The 09:30 open of the New York session of the trades. Earlier trades
+    /// are ignored and a trade of a later session starts over.
+    Session,
+    /// Every trade from this instant on.
+    At(DateTime<Utc>),
+}
+
+/// Incremental VWAP, fed trade by trade from the historical API or the
+/// stream and readable at any point.
+pub struct RollingVwap {
+    anchor: VwapAnchor,
+    include: Box<dyn Fn(&crate::Trade) -> bool + Send + Sync>,
+    start: Option<DateTime<Utc>>,
+    notional: f64,
+    volume: u64,
+}
+
+impl fmt::Debug for RollingVwap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RollingVwap")
+            .field("anchor", &self.anchor)
+            .field("start", &self.start)
+            .field("notional", &self.notional)
+            .field("volume", &self.volume)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RollingVwap {
+    pub fn new(anchor: VwapAnchor) -> Self {
+        Self {
+            anchor,
+            include: Box::new(|_| true),
+            start: match anchor {
+                VwapAnchor::Session => None,
+                VwapAnchor::At(start) => Some(start),
+            },
+            notional: 0.0,
+            volume: 0,
+        }
+    }
+
+    /// Only counts the trades accepted by `include`, as `vwap_by`.
+    pub fn with_filter(mut self, include: impl Fn(&crate::Trade) -> bool + Send + Sync + 'static) -> Self {
+        self.include = Box::new(include);
+        self
+    }
+
+    /// Adds `trade`, when it is accepted and not before the anchor.
+    pub fn push(&mut self, trade: &crate::Trade) {
+        if !(self.include)(trade) {
+            return;
+        }
+
+        if self.anchor == VwapAnchor::Session {
+            use crate::market_hours::{new_york_date, new_york_to_utc};
+            let open = new_york_to_utc(new_york_date(trade.timestamp), chrono::NaiveTime::from_hms_opt(9, 30, 0).unwrap());
+            if trade.timestamp < open {
+                return;
+            }
+            if self.start.is_none_or(|start| open > start) {
+                self.reset();
+                self.start = Some(open);
+            }
+        }
+
+        if self.start.is_some_and(|start| trade.timestamp >= start) {
+            self.notional += trade.price * trade.size as f64;
+            self.volume += trade.size;
+        }
+    }
+
+    pub fn extend<'a>(&mut self, trades: impl IntoIterator<Item = &'a crate::Trade>) {
+        for trade in trades {
+            self.push(trade);
+        }
+    }
+
+    /// Current VWAP, `None` until some volume is counted.
+    pub fn value(&self) -> Option<f64> {
+        (self.volume > 0).then(|| self.notional / self.volume as f64)
+    }
+
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+
+    /// Instant the current VWAP counts from, `None` for a session anchor
+    /// before its first trade.
+    pub fn start(&self) -> Option<DateTime<Utc>> {
+        self.start
+    }
+
+    /// Drops the counted trades, keeping the anchor.
+    pub fn reset(&mut self) {
+        self.notional = 0.0;
+        self.volume = 0;
+    }
+}
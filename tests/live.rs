@@ -0,0 +1,114 @@
+// Copyright (C) 2025  Jimmy Aguilar Mena
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Live tests against the paper trading API.
+//!
+//! They are ignored by default and only talk to Alpaca when
+//! `ALPACA_LIVE_TESTS=1` and the paper keys are in `ALPACA_API_KEY` and
+//! `ALPACA_SECRET_KEY`:
+//!
+//! ```sh
+//! ALPACA_LIVE_TESTS=1 cargo test --test live -- --ignored --test-threads=1
+//! ```
+//!
+//! They only read the account, except `order_lifecycle` which places a one
+//! share limit order far below the market and cancels it right away.
+//! Everything passes with the market closed.
+
+use std::time::Duration;
+use alpaca_rs::{AlpacaClient, OrderSide, OrderStatus, TimeInForce};
+
+const SYMBOL: &str = "AAPL";
+
+async fn live_client() -> Option<AlpacaClient> {
+    if std::env::var("ALPACA_LIVE_TESTS").as_deref() != Ok("1") {
+        eprintln!("ALPACA_LIVE_TESTS is not 1, skipping");
+        return None;
+    }
+
+    let client = AlpacaClient::connect_from_env().await.expect("paper credentials");
+    assert!(client.base_url().contains("paper"), "live tests only run against the paper API");
+    Some(client)
+}
+
+#[tokio::test]
+#[ignore]
+async fn account_and_positions() {
+    let Some(client) = live_client().await else { return };
+
+    let account = client.get_account().await.unwrap();
+    assert!(account["cash"].as_str().is_some_and(|cash| cash.parse::<f64>().is_ok()));
+
+    let positions = client.get_positions().await.unwrap();
+    assert!(positions.is_array());
+}
+
+#[tokio::test]
+#[ignore]
+async fn clock() {
+    let Some(client) = live_client().await else { return };
+
+    let clock = client.get_clock().await.unwrap();
+    assert!(clock.next_open > clock.timestamp || clock.is_open);
+}
+
+#[tokio::test]
+#[ignore]
+async fn asset() {
+    let Some(client) = live_client().await else { return };
+
+    let asset = client.get_asset(SYMBOL).await.unwrap();
+    assert_eq!(asset.symbol, SYMBOL);
+}
+
+#[tokio::test]
+#[ignore]
+async fn latest_prices() {
+    let Some(client) = live_client().await else { return };
+
+    // The last values are served with the market closed too
+    let trade = client.get_latest_trade(SYMBOL).await.unwrap();
+    assert!(trade.price > 0.0);
+
+    let quote = client.get_latest_quote(SYMBOL).await.unwrap();
+    assert!(quote.ask_price >= 0.0);
+}
+
+#[tokio::test]
+#[ignore]
+async fn order_lifecycle() {
+    let Some(client) = live_client().await else { return };
+
+    let price = client.get_latest_trade(SYMBOL).await.unwrap().price;
+    let limit = ((price * 0.5).max(1.0) * 100.0).round() / 100.0;
+
+    let id = client.place_limit_order(SYMBOL, 1, OrderSide::Buy, limit, TimeInForce::Day).await.unwrap().id;
+
+    // Cancel before asserting anything so a failure leaves no open order
+    let cancel = client.cancel_order(&id).await;
+
+    let mut status = OrderStatus::New;
+    for _ in 0..10 {
+        status = client.get_order(&id, false).await.unwrap().status;
+        if status == OrderStatus::Canceled {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    cancel.unwrap();
+    assert_eq!(status, OrderStatus::Canceled);
+}